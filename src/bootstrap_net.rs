@@ -0,0 +1,22 @@
+use sand::engine::{nnue::Network, params::EvalParams};
+
+const USAGE: &str = "usage: bootstrap_net <net.nnue> [--params <params.txt>]";
+
+/// Writes a network playing like the piece values and piece-square tables, the one embedded
+/// in the engine until a trained one replaces it
+pub fn main() -> Result<(), &'static str> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().ok_or(USAGE)?;
+
+    let params = match (args.next().as_deref(), args.next()) {
+        (None, _) => EvalParams::default(),
+        (Some("--params"), Some(params_path)) => EvalParams::load(&params_path)?,
+        _ => return Err(USAGE),
+    };
+
+    let network = Network::from_params(&params);
+    std::fs::write(&path, network.to_bytes()).map_err(|_| "can't write network file")?;
+    println!("network written to {path}");
+
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use sand::{chess::*, engine::book::BookEntry};
+
+const USAGE: &str = "usage: build_book <games.pgn> <book.bin> [--max-ply N] [--min-ply N] \
+                     [--min-games N] [--min-elo N]";
+
+struct Options {
+    pgn_path: String,
+    book_path: String,
+    /// only the first `max_ply` moves of each game go into the book
+    max_ply: usize,
+    /// games shorter than this are skipped (quick draws, forfeits...)
+    min_ply: usize,
+    /// moves played in fewer games than this are left out
+    min_games: u32,
+    /// both players must be rated at least this much, if set
+    min_elo: u32,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, &'static str> {
+        let mut options = Options {
+            pgn_path: args.next().ok_or(USAGE)?,
+            book_path: args.next().ok_or(USAGE)?,
+            max_ply: 16,
+            min_ply: 0,
+            min_games: 1,
+            min_elo: 0,
+        };
+
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or(USAGE)?;
+            let value = value
+                .parse::<u32>()
+                .map_err(|_| "option values must be numbers")?;
+
+            match flag.as_str() {
+                "--max-ply" => options.max_ply = value as usize,
+                "--min-ply" => options.min_ply = value as usize,
+                "--min-games" => options.min_games = value,
+                "--min-elo" => options.min_elo = value,
+                _ => return Err(USAGE),
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn accepts(&self, game: &PgnGame) -> bool {
+        let rated = |tag| {
+            game.tag(tag)
+                .and_then(|elo| elo.parse::<u32>().ok())
+                .is_some_and(|elo| elo >= self.min_elo)
+        };
+
+        game.moves.len() >= self.min_ply
+            && game.result != "*"
+            && (self.min_elo == 0 || (rated("WhiteElo") && rated("BlackElo")))
+    }
+}
+
+/// How a move did from the point of view of the side that played it
+#[derive(Default)]
+struct MoveStats {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl MoveStats {
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Polyglot's usual weighting: 2 points per win and 1 per draw
+    fn score(&self) -> u32 {
+        2 * self.wins + self.draws
+    }
+}
+
+pub fn main() -> Result<(), &'static str> {
+    let options = Options::parse(std::env::args().skip(1))?;
+
+    let bytes = std::fs::read(&options.pgn_path).map_err(|_| "can't read PGN file")?;
+    let games = parse_pgn(&String::from_utf8_lossy(&bytes));
+
+    let mut stats: HashMap<(u64, u16), MoveStats> = HashMap::new();
+    let mut used_games = 0;
+    // games only used up to a move that couldn't be read
+    let mut truncated_games = 0;
+
+    for pgn_game in games.iter().filter(|game| options.accepts(game)) {
+        let winner = match pgn_game.result.as_str() {
+            "1-0" => Some(Color::White),
+            "0-1" => Some(Color::Black),
+            _ => None,
+        };
+
+        let Ok(mut game) = Game::new(pgn_game.tag("FEN").unwrap_or(STARTPOS_FEN)) else {
+            continue;
+        };
+
+        let mut truncated = false;
+        for san in pgn_game.moves.iter().take(options.max_ply) {
+            let key = game.board().polyglot_key();
+            let color = game.board().side_to_move;
+
+            // keep what was played up to an unreadable move
+            let Ok(mov) = game.make_san_move(san) else {
+                truncated = true;
+                break;
+            };
+
+            let move_stats = stats.entry((key, BookEntry::encode_move(mov))).or_default();
+            match winner {
+                Some(winner) if winner == color => move_stats.wins += 1,
+                Some(_) => move_stats.losses += 1,
+                None => move_stats.draws += 1,
+            }
+        }
+
+        match truncated {
+            true => truncated_games += 1,
+            false => used_games += 1,
+        }
+    }
+
+    let scores: Vec<(u64, u16, u32)> = stats
+        .into_iter()
+        .filter(|(_, move_stats)| move_stats.games() >= options.min_games && move_stats.score() > 0)
+        .map(|((key, mov), move_stats)| (key, mov, move_stats.score()))
+        .collect();
+
+    // weights only have 16 bits, so positions whose best score overflows get scaled down
+    let mut max_scores: HashMap<u64, u32> = HashMap::new();
+    for &(key, _, score) in &scores {
+        let max = max_scores.entry(key).or_default();
+        *max = (*max).max(score);
+    }
+
+    let mut entries: Vec<BookEntry> = scores
+        .into_iter()
+        .map(|(key, mov, score)| {
+            let max = max_scores[&key] as u64;
+            let weight = if max > u16::MAX as u64 {
+                (score as u64 * u16::MAX as u64 / max).max(1)
+            } else {
+                score as u64
+            };
+
+            BookEntry {
+                key,
+                mov,
+                weight: weight as u16,
+                learn: 0,
+            }
+        })
+        .collect();
+
+    // sorted by key, and by decreasing weight within a position like polyglot does
+    entries.sort_by(|a, b| {
+        a.key
+            .cmp(&b.key)
+            .then(b.weight.cmp(&a.weight))
+            .then(a.mov.cmp(&b.mov))
+    });
+
+    let bytes: Vec<u8> = entries.iter().flat_map(|entry| entry.to_bytes()).collect();
+    std::fs::write(&options.book_path, bytes).map_err(|_| "can't write book file")?;
+
+    println!(
+        "{used_games} of {} games used, {truncated_games} more up to an unreadable move, {} book \
+         entries written to {}",
+        games.len(),
+        entries.len(),
+        options.book_path
+    );
+
+    Ok(())
+}
@@ -0,0 +1,1153 @@
+use std::{cmp::Reverse, collections::BinaryHeap, collections::HashMap, sync::LazyLock};
+
+use sand::{
+    chess::{
+        attacks::{magics, tables},
+        *,
+    },
+    engine::syzygy::Tablebases,
+};
+
+const USAGE: &str = "usage: build_tables <directory> <table>...";
+
+// Builds Syzygy tables for small endings by retrograde analysis, in the format the engine reads:
+// 4 pieces at most, no repeated pieces and one pawn at most. The endings captures and
+// promotions lead to are built (and written) first. Results come from the analysis alone, the
+// decoder is only used at the end to read a sample of every table back.
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// flags of the stored values, as in the Syzygy format
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const SINGLE_VALUE: u8 = 128;
+
+const MAX_PIECES: usize = 4;
+const BLOCK_SIZE_LOG: u8 = 10;
+const SPAN_LOG: u8 = 10;
+// block lengths and sparse index offsets are 16 bits, and the latter point up to half a span
+// past the end of the last block
+const MAX_BLOCK_VALUES: usize = (1 << 16) - (1 << SPAN_LOG);
+// symbols have 12 bits, 0xFFF marks the values themselves
+const MAX_SYMBOLS: usize = 4000;
+const LEAF: u16 = 0xFFF;
+// a symbol of the decoder can't expand to more values than this
+const MAX_SYMBOL_VALUES: u32 = 256;
+// codes are read from a 64 bit buffer refilled 32 bits at a time
+const MAX_CODE_LEN: u8 = 32;
+// pairs seen fewer times than this aren't worth a symbol
+const MIN_PAIR_COUNT: u32 = 32;
+const PAIRS_PER_PASS: usize = 64;
+
+// results of the side to move, as the tables store them (minus 2)
+const WIN: i8 = 2;
+const DRAW: i8 = 0;
+const LOSS: i8 = -2;
+// illegal positions, and legal ones not solved yet
+const UNKNOWN: i8 = i8::MIN;
+// value of the best capture or pawn move, when there are none
+const NO_ZEROING: i8 = -3;
+// positions nobody looks up in a table
+const DONT_CARE: u8 = 0xFF;
+
+// one position in this many is read back from the written tables
+const VERIFY_STEP: usize = 53;
+
+const ORDER: [Piece; 6] = [
+    Piece::King,
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Pawn,
+];
+
+fn rank_of(square: Square) -> u64 {
+    (square / BOARD_WIDTH as Square) as u64
+}
+
+fn file_of(square: Square) -> u64 {
+    (square % BOARD_WIDTH as Square) as u64
+}
+
+fn off_a1h8(square: Square) -> i32 {
+    rank_of(square) as i32 - file_of(square) as i32
+}
+
+/// Squares below the a1-h8 diagonal to 0..27, and the a1-d1-d4 triangle to 0..9 with the
+/// diagonal last
+static MAPS: LazyLock<([u64; BOARD_SIZE], [u64; BOARD_SIZE])> = LazyLock::new(|| {
+    let mut map_b1h1h7 = [0; BOARD_SIZE];
+    let below: Vec<Square> = (0..BOARD_SIZE as Square)
+        .filter(|&square| off_a1h8(square) < 0)
+        .collect();
+    for (code, &square) in below.iter().enumerate() {
+        map_b1h1h7[square as usize] = code as u64;
+    }
+
+    let mut map_a1d1d4 = [0; BOARD_SIZE];
+    let triangle = (0..BOARD_SIZE as Square).filter(|&square| file_of(square) <= 3);
+    let (diagonal, below): (Vec<Square>, Vec<Square>) = triangle
+        .filter(|&square| off_a1h8(square) <= 0 && rank_of(square) <= 3)
+        .partition(|&square| off_a1h8(square) == 0);
+    for (code, &square) in below.iter().chain(&diagonal).enumerate() {
+        map_a1d1d4[square as usize] = code as u64;
+    }
+
+    (map_b1h1h7, map_a1d1d4)
+});
+
+fn side_name(pieces: &[(Piece, Color)], color: Color) -> String {
+    ORDER
+        .iter()
+        .flat_map(|&piece_type| {
+            let count = pieces
+                .iter()
+                .filter(|&&piece| piece == (piece_type, color))
+                .count();
+            std::iter::repeat_n(piece_type.to_char().to_ascii_uppercase(), count)
+        })
+        .collect()
+}
+
+/// Tables are named after the strong side first: more pieces, or better ones
+fn is_stronger(side: &str, other: &str) -> bool {
+    let ranks = |side: &str| -> Vec<usize> {
+        side.chars()
+            .map(|chr| "KQRBNP".find(chr).unwrap_or(6))
+            .collect()
+    };
+    side.len() > other.len() || (side.len() == other.len() && ranks(side) <= ranks(other))
+}
+
+fn table_name(pieces: &[(Piece, Color)]) -> String {
+    let white = side_name(pieces, Color::White);
+    let black = side_name(pieces, Color::Black);
+    match is_stronger(&white, &black) {
+        true => format!("{white}v{black}"),
+        false => format!("{black}v{white}"),
+    }
+}
+
+struct Material {
+    name: String,
+    /// Pieces in encoding order: the pawn, then the strong side (white) and the weak one
+    pieces: Vec<(Piece, Color)>,
+    has_pawns: bool,
+    symmetric: bool,
+}
+
+impl Material {
+    fn from_name(name: &str) -> Result<Material, &'static str> {
+        let (white, black) = name.split_once('v').ok_or("invalid table name")?;
+        if !is_stronger(white, black) {
+            return Err("table names go with the strong side first");
+        }
+
+        let mut pieces = Vec::new();
+        for (side, color) in [(white, Color::White), (black, Color::Black)] {
+            if !side.starts_with('K') || side[1..].contains('K') {
+                return Err("every side needs one king");
+            }
+            for chr in side.chars() {
+                let piece = (Piece::from_char(chr)?, color);
+                if pieces.contains(&piece) {
+                    return Err("tables with repeated pieces aren't supported");
+                }
+                pieces.push(piece);
+            }
+        }
+
+        let pawns = pieces
+            .iter()
+            .filter(|(piece, _)| *piece == Piece::Pawn)
+            .count();
+        if pawns > 1 || pieces.len() > MAX_PIECES {
+            return Err("only tables of up to 4 pieces with one pawn at most are supported");
+        }
+        if let Some(pawn) = pieces.iter().position(|(piece, _)| *piece == Piece::Pawn) {
+            let pawn = pieces.remove(pawn);
+            pieces.insert(0, pawn);
+        }
+
+        Ok(Material {
+            name: name.to_string(),
+            pieces,
+            has_pawns: pawns > 0,
+            symmetric: white == black,
+        })
+    }
+
+    /// The endings a capture or a promotion leads to
+    fn children(&self) -> Vec<String> {
+        let mut children = Vec::new();
+        let mut push = |pieces: Vec<(Piece, Color)>| {
+            if pieces.len() > 2 && !children.contains(&table_name(&pieces)) {
+                children.push(table_name(&pieces));
+            }
+        };
+
+        let captures = |pieces: &Vec<(Piece, Color)>, color: Color| {
+            let mut captures = Vec::new();
+            for (i, &(piece, piece_color)) in pieces.iter().enumerate() {
+                if piece != Piece::King && piece_color != color {
+                    let mut captured = pieces.clone();
+                    captured.remove(i);
+                    captures.push(captured);
+                }
+            }
+            captures
+        };
+
+        for color in [Color::White, Color::Black] {
+            captures(&self.pieces, color)
+                .into_iter()
+                .for_each(&mut push);
+        }
+
+        if let Some(&(_, color)) = self.pieces.iter().find(|(piece, _)| *piece == Piece::Pawn) {
+            for promotion in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+                let mut promoted = self.pieces.clone();
+                promoted[0] = (promotion, color);
+                captures(&promoted, color).into_iter().for_each(&mut push);
+                push(promoted);
+            }
+        }
+
+        children
+    }
+
+    fn material_key(&self, flip: bool) -> u64 {
+        self.pieces
+            .iter()
+            .map(|&(piece, color)| {
+                material_key_unit(piece, if flip { color.toggle() } else { color })
+            })
+            .sum()
+    }
+
+    /// Number of positions of a side to move and file in the table: the leading pieces (or
+    /// pawn) and then the squares left for every other piece
+    fn table_size(&self) -> u64 {
+        let (leading, first) = match self.has_pawns {
+            true => (6, 1),
+            false => (31332, 3),
+        };
+        (first..self.pieces.len()).fold(leading, |size, i| size * (64 - i as u64))
+    }
+
+    /// File and index of a position in the table, with the same symmetries and piece grouping
+    /// the decoder uses. `squares` are those of the pieces in encoding order.
+    fn table_index(&self, squares: &[Square]) -> (usize, u64) {
+        let mut squares = squares.to_vec();
+        let (map_b1h1h7, map_a1d1d4) = &*MAPS;
+
+        if file_of(squares[0]) > 3 {
+            squares.iter_mut().for_each(|square| *square ^= 7);
+        }
+
+        let (file, mut idx, leading) = if self.has_pawns {
+            (file_of(squares[0]) as usize, rank_of(squares[0]) - 1, 1)
+        } else {
+            if rank_of(squares[0]) > 3 {
+                squares.iter_mut().for_each(|square| *square ^= 56);
+            }
+            // the first leading piece off the diagonal goes below it
+            if let Some(i) = (0..3).find(|&i| off_a1h8(squares[i]) != 0)
+                && off_a1h8(squares[i]) > 0
+            {
+                for square in &mut squares[i..] {
+                    *square = ((*square >> 3) | (*square << 3)) & 63;
+                }
+            }
+
+            let [s0, s1, s2] = [squares[0], squares[1], squares[2]];
+            let (s0, s1, s2) = (s0 as u64, s1 as u64, s2 as u64);
+            let adjust1 = (s1 > s0) as u64;
+            let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+            let rank = |square: u64| square / 8;
+
+            let idx = if off_a1h8(s0 as Square) != 0 {
+                (map_a1d1d4[s0 as usize] * 63 + s1 - adjust1) * 62 + s2 - adjust2
+            } else if off_a1h8(s1 as Square) != 0 {
+                (6 * 63 + rank(s0) * 28 + map_b1h1h7[s1 as usize]) * 62 + s2 - adjust2
+            } else if off_a1h8(s2 as Square) != 0 {
+                6 * 63 * 62
+                    + 4 * 28 * 62
+                    + rank(s0) * 7 * 28
+                    + (rank(s1) - adjust1) * 28
+                    + map_b1h1h7[s2 as usize]
+            } else {
+                6 * 63 * 62
+                    + 4 * 28 * 62
+                    + 4 * 7 * 28
+                    + rank(s0) * 7 * 6
+                    + (rank(s1) - adjust1) * 6
+                    + rank(s2)
+                    - adjust2
+            };
+            (0, idx, 3)
+        };
+
+        // every other piece on the squares the previous ones left
+        let mut factor = if self.has_pawns { 6 } else { 31332 };
+        for i in leading..squares.len() {
+            let below = squares[..i]
+                .iter()
+                .filter(|&&other| other < squares[i])
+                .count();
+            idx += (squares[i] as u64 - below as u64) * factor;
+            factor *= 64 - i as u64;
+        }
+
+        (file, idx)
+    }
+}
+
+/// Positions are indexed by the squares of the pieces in encoding order, then the side to move
+fn position_of(squares: &[Square], side_to_move: Color) -> usize {
+    let placement = squares
+        .iter()
+        .rev()
+        .fold(0, |placement, &square| (placement << 6) | square as usize);
+    (placement << 1) | side_to_move as usize
+}
+
+fn squares_of(position: usize, pieces: usize) -> Vec<Square> {
+    (0..pieces)
+        .map(|i| ((position >> (1 + 6 * i)) & 63) as Square)
+        .collect()
+}
+
+fn side_to_move_of(position: usize) -> Color {
+    [Color::White, Color::Black][position & 1]
+}
+
+struct Table {
+    material: Material,
+    /// Result of the side to move, or `UNKNOWN` for illegal positions
+    wdl: Vec<i8>,
+    /// Plies to the next capture, pawn move or mate with the best play
+    dtz: Vec<u8>,
+    /// Value of the best capture or pawn move, `NO_ZEROING` without any
+    zeroing: Vec<i8>,
+}
+
+/// The values of a side to move and file of a table, compressed
+enum Pairs {
+    Single(u8),
+    Compressed {
+        max_len: u8,
+        min_len: u8,
+        lowest: Vec<u16>,
+        btree: Vec<[u8; 3]>,
+        sparse_index: Vec<(u32, u16)>,
+        block_lengths: Vec<u16>,
+        blocks: Vec<u8>,
+    },
+}
+
+impl Pairs {
+    fn sizes(&self, flags: u8) -> Vec<u8> {
+        let Pairs::Compressed {
+            max_len,
+            min_len,
+            lowest,
+            btree,
+            block_lengths,
+            ..
+        } = self
+        else {
+            let Pairs::Single(value) = self else {
+                unreachable!()
+            };
+            return vec![flags | SINGLE_VALUE, *value];
+        };
+
+        let mut bytes = vec![flags, BLOCK_SIZE_LOG, SPAN_LOG, 0];
+        bytes.extend((block_lengths.len() as u32).to_le_bytes());
+        bytes.extend([*max_len, *min_len]);
+        bytes.extend(lowest.iter().flat_map(|lowest| lowest.to_le_bytes()));
+        bytes.extend((btree.len() as u16).to_le_bytes());
+        bytes.extend(btree.iter().flatten());
+        if btree.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    fn sparse_index(&self) -> Vec<u8> {
+        match self {
+            Pairs::Single(_) => Vec::new(),
+            Pairs::Compressed { sparse_index, .. } => sparse_index
+                .iter()
+                .flat_map(|&(block, offset)| {
+                    block.to_le_bytes().into_iter().chain(offset.to_le_bytes())
+                })
+                .collect(),
+        }
+    }
+
+    fn block_lengths(&self) -> Vec<u8> {
+        match self {
+            Pairs::Single(_) => Vec::new(),
+            Pairs::Compressed { block_lengths, .. } => block_lengths
+                .iter()
+                .flat_map(|length| length.to_le_bytes())
+                .collect(),
+        }
+    }
+
+    fn blocks(&self) -> &[u8] {
+        match self {
+            Pairs::Single(_) => &[],
+            Pairs::Compressed { blocks, .. } => blocks,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.sizes(0).len()
+            + self.sparse_index().len()
+            + self.block_lengths().len()
+            + self.blocks().len()
+    }
+}
+
+/// Huffman code lengths of the symbols used, limited to what the decoder can read
+fn code_lengths(counts: &[u64]) -> Vec<u8> {
+    let mut counts = counts.to_vec();
+    loop {
+        let mut heap: BinaryHeap<Reverse<(u64, usize)>> = counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(symbol, &count)| Reverse((count, symbol)))
+            .collect();
+
+        let mut lengths = vec![0u8; counts.len()];
+        if heap.len() == 1 {
+            let Reverse((_, symbol)) = heap.pop().unwrap();
+            lengths[symbol] = 1;
+            return lengths;
+        }
+
+        let mut parents = vec![usize::MAX; counts.len()];
+        while heap.len() > 1 {
+            let Reverse((count_a, a)) = heap.pop().unwrap();
+            let Reverse((count_b, b)) = heap.pop().unwrap();
+            let node = parents.len();
+            parents.push(usize::MAX);
+            parents[a] = node;
+            parents[b] = node;
+            heap.push(Reverse((count_a + count_b, node)));
+        }
+
+        for symbol in (0..counts.len()).filter(|&symbol| counts[symbol] > 0) {
+            let mut node = symbol;
+            while parents[node] != usize::MAX {
+                node = parents[node];
+                lengths[symbol] += 1;
+            }
+        }
+
+        if lengths.iter().all(|&length| length <= MAX_CODE_LEN) {
+            return lengths;
+        }
+        // flatter counts make shorter codes
+        for count in counts.iter_mut().filter(|count| **count > 0) {
+            *count = *count / 2 + 1;
+        }
+    }
+}
+
+/// Replaces the most common pairs of symbols by new symbols (Re-Pair) and Huffman codes the
+/// result in blocks, like the Syzygy generator does
+fn compress(values: &[u8]) -> Pairs {
+    if values.iter().all(|&value| value == values[0]) {
+        return Pairs::Single(values[0]);
+    }
+
+    // (left, right) children of every symbol, the value itself on the left for leaves
+    let mut symbols: Vec<(u16, u16)> = Vec::new();
+    let mut symbol_values: Vec<u32> = Vec::new();
+    let mut leaves = [u16::MAX; 256];
+    for &value in values {
+        if leaves[value as usize] == u16::MAX {
+            leaves[value as usize] = symbols.len() as u16;
+            symbols.push((value as u16, LEAF));
+            symbol_values.push(1);
+        }
+    }
+    let mut sequence: Vec<u16> = values.iter().map(|&value| leaves[value as usize]).collect();
+
+    let mut pair_counts = vec![0u32; 1 << 24];
+    let pair = |a: u16, b: u16| ((a as usize) << 12) | b as usize;
+    loop {
+        let mut seen = Vec::new();
+        let mut i = 0;
+        while i + 1 < sequence.len() {
+            let (a, b) = (sequence[i], sequence[i + 1]);
+            if pair_counts[pair(a, b)] == 0 {
+                seen.push((a, b));
+            }
+            pair_counts[pair(a, b)] += 1;
+            // runs only hold half as many pairs as they're long
+            i += if a == b && sequence.get(i + 2) == Some(&a) {
+                2
+            } else {
+                1
+            };
+        }
+
+        seen.sort_by_key(|&(a, b)| Reverse(pair_counts[pair(a, b)]));
+        let mut used = vec![false; symbols.len()];
+        let mut chosen = Vec::new();
+        for &(a, b) in &seen {
+            let count = pair_counts[pair(a, b)];
+            if count < MIN_PAIR_COUNT
+                || chosen.len() == PAIRS_PER_PASS
+                || symbols.len() + chosen.len() == MAX_SYMBOLS
+            {
+                break;
+            }
+            // pairs sharing a symbol would get in the way of each other
+            let fits = symbol_values[a as usize] + symbol_values[b as usize] <= MAX_SYMBOL_VALUES;
+            if fits && !used[a as usize] && !used[b as usize] {
+                used[a as usize] = true;
+                used[b as usize] = true;
+                chosen.push((a, b));
+            }
+        }
+        for &(a, b) in &seen {
+            pair_counts[pair(a, b)] = 0;
+        }
+        if chosen.is_empty() {
+            break;
+        }
+
+        for &(a, b) in &chosen {
+            pair_counts[pair(a, b)] = symbols.len() as u32 + 1;
+            symbols.push((a, b));
+            symbol_values.push(symbol_values[a as usize] + symbol_values[b as usize]);
+        }
+
+        let mut replaced = Vec::with_capacity(sequence.len());
+        let mut i = 0;
+        while i < sequence.len() {
+            let new_symbol = match sequence.get(i + 1) {
+                Some(&b) => pair_counts[pair(sequence[i], b)],
+                None => 0,
+            };
+            if new_symbol != 0 {
+                replaced.push(new_symbol as u16 - 1);
+                i += 2;
+            } else {
+                replaced.push(sequence[i]);
+                i += 1;
+            }
+        }
+        sequence = replaced;
+
+        for &(a, b) in &chosen {
+            pair_counts[pair(a, b)] = 0;
+        }
+    }
+
+    // canonical code: longer codes first and with lower symbols, so the symbols of each length
+    // are numbered in a row from the lowest one
+    let mut counts = vec![0u64; symbols.len()];
+    for &symbol in &sequence {
+        counts[symbol as usize] += 1;
+    }
+    let lengths = code_lengths(&counts);
+    let max_len = *lengths.iter().max().unwrap();
+    let min_len = *lengths.iter().filter(|&&length| length > 0).min().unwrap();
+
+    let mut order: Vec<usize> = (0..symbols.len()).collect();
+    order.sort_by_key(|&symbol| (lengths[symbol] == 0, Reverse(lengths[symbol])));
+    let mut renumbered = vec![0u16; symbols.len()];
+    for (new, &symbol) in order.iter().enumerate() {
+        renumbered[symbol] = new as u16;
+    }
+
+    let lengths_num = (max_len - min_len + 1) as usize;
+    let mut length_counts = vec![0u64; lengths_num];
+    for &length in lengths.iter().filter(|&&length| length > 0) {
+        length_counts[(length - min_len) as usize] += 1;
+    }
+    let mut lowest = vec![0u16; lengths_num];
+    let mut base = vec![0u64; lengths_num];
+    for i in (0..lengths_num - 1).rev() {
+        lowest[i] = lowest[i + 1] + length_counts[i + 1] as u16;
+        base[i] = (base[i + 1] + length_counts[i + 1]) / 2;
+    }
+    let code = |symbol: usize| {
+        let i = (lengths[symbol] - min_len) as usize;
+        base[i] + (renumbered[symbol] - lowest[i]) as u64
+    };
+
+    let btree = order
+        .iter()
+        .map(|&symbol| {
+            let (left, right) = match symbols[symbol] {
+                (value, LEAF) => (value, LEAF),
+                (a, b) => (renumbered[a as usize], renumbered[b as usize]),
+            };
+            [
+                left as u8,
+                (left >> 8) as u8 | ((right & 0xF) << 4) as u8,
+                (right >> 4) as u8,
+            ]
+        })
+        .collect();
+
+    // symbols are never split between blocks
+    let block_size = 1 << BLOCK_SIZE_LOG;
+    let mut blocks = Vec::new();
+    let mut block_lengths = Vec::new();
+    let mut block_starts = vec![0];
+    let mut bits = 0;
+    let mut block_values = 0;
+    blocks.resize(block_size, 0u8);
+
+    for &symbol in &sequence {
+        let symbol = symbol as usize;
+        let length = lengths[symbol] as usize;
+        let symbol_values = symbol_values[symbol] as usize;
+
+        if bits + length > 8 * block_size || block_values + symbol_values > MAX_BLOCK_VALUES {
+            block_lengths.push(block_values as u16 - 1);
+            block_starts.push(block_starts.last().unwrap() + block_values);
+            blocks.resize(blocks.len() + block_size, 0);
+            bits = 0;
+            block_values = 0;
+        }
+
+        let block = blocks.len() - block_size;
+        let code = code(symbol);
+        for bit in 0..length {
+            if code >> (length - 1 - bit) & 1 != 0 {
+                blocks[block + (bits + bit) / 8] |= 0x80 >> ((bits + bit) % 8);
+            }
+        }
+        bits += length;
+        block_values += symbol_values;
+    }
+    block_lengths.push(block_values as u16 - 1);
+
+    // the block and offset of the value in the middle of every span
+    let span = 1 << SPAN_LOG;
+    let sparse_index = (0..values.len().div_ceil(span))
+        .map(|k| {
+            let idx = k * span + span / 2;
+            let block = block_starts.partition_point(|&start| start <= idx) - 1;
+            (block as u32, (idx - block_starts[block]) as u16)
+        })
+        .collect();
+
+    Pairs::Compressed {
+        max_len,
+        min_len,
+        lowest,
+        btree,
+        sparse_index,
+        block_lengths,
+        blocks,
+    }
+}
+
+/// Gives the values nobody looks up the one before them, which costs nothing once compressed
+fn fill_dont_cares(values: &mut [u8]) {
+    let mut previous = values
+        .iter()
+        .copied()
+        .find(|&value| value != DONT_CARE)
+        .unwrap_or(0);
+    for value in values {
+        match *value {
+            DONT_CARE => *value = previous,
+            _ => previous = *value,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    tables: Vec<Table>,
+    /// The table of a material key, and whether colors are flipped in it
+    by_key: HashMap<u64, (usize, bool)>,
+}
+
+impl Builder {
+    fn build(&mut self, name: &str, directory: &str) -> Result<(), &'static str> {
+        if self.tables.iter().any(|table| table.material.name == name) {
+            return Ok(());
+        }
+
+        let material = Material::from_name(name)?;
+        for child in material.children() {
+            self.build(&child, directory)?;
+        }
+
+        let table = self.solve(material)?;
+        let wdl = table.wdl_file();
+        let dtz = table.dtz_file();
+        let path = |extension| format!("{directory}/{name}.{extension}");
+        std::fs::write(path("rtbw"), &wdl).map_err(|_| "can't write table file")?;
+        std::fs::write(path("rtbz"), &dtz).map_err(|_| "can't write table file")?;
+
+        let results = |result| table.wdl.iter().filter(|&&wdl| wdl == result).count();
+        let longest = (0..table.wdl.len())
+            .filter(|&position| table.wdl[position] != UNKNOWN)
+            .map(|position| table.dtz[position])
+            .max()
+            .unwrap_or(0);
+        println!(
+            "{name}: {} wins, {} draws, {} losses, longest DTZ {longest} plies, {} + {} bytes",
+            results(WIN),
+            results(DRAW),
+            results(LOSS),
+            wdl.len(),
+            dtz.len()
+        );
+
+        table.verify(&Tablebases::new(directory))?;
+
+        let index = self.tables.len();
+        self.by_key
+            .insert(table.material.material_key(false), (index, false));
+        self.by_key
+            .entry(table.material.material_key(true))
+            .or_insert((index, true));
+        self.tables.push(table);
+        Ok(())
+    }
+
+    /// Result of a position for the side to move, from the tables built so far
+    fn probe(&self, board: &Board) -> i8 {
+        if (board.occupancies[0] | board.occupancies[1]).count_ones() == 2 {
+            return DRAW;
+        }
+
+        let (index, flip) = self.by_key[&board.material_key];
+        let table = &self.tables[index];
+        table.wdl[position_on(board, &table.material, flip)]
+    }
+
+    fn solve(&self, material: Material) -> Result<Table, &'static str> {
+        let pieces = material.pieces.len();
+        let size = 2 << (6 * pieces);
+        let mut wdl = vec![UNKNOWN; size];
+        let mut dtz = vec![0u8; size];
+        let mut legal = vec![false; size];
+        // moves that aren't captures or pawn moves, which stay in this table, and aren't known
+        // to lose yet
+        let mut moves_left = vec![0u8; size];
+        let mut zeroing = vec![NO_ZEROING; size];
+
+        let empty = [
+            Board::new("8/8/8/8/8/8/8/8 w - - 0 1")?,
+            Board::new("8/8/8/8/8/8/8/8 b - - 0 1")?,
+        ];
+
+        // pawn moves stay in the table, so the positions with the pawn further up go first
+        let slices: Vec<Option<u64>> = match material.pieces[0] {
+            (Piece::Pawn, Color::White) => (1..7).rev().map(Some).collect(),
+            (Piece::Pawn, Color::Black) => (1..7).map(Some).collect(),
+            _ => vec![None],
+        };
+
+        for slice in slices {
+            // positions solved with each DTZ, to go on from in order
+            let mut levels: Vec<Vec<u32>> = vec![Vec::new(); 2];
+
+            for position in 0..size {
+                let squares = squares_of(position, pieces);
+                if slice.is_some_and(|rank| rank_of(squares[0]) != rank) {
+                    continue;
+                }
+                let occupancy = squares
+                    .iter()
+                    .fold(0, |occupancy, &square| occupancy | bit(square));
+                if occupancy.count_ones() as usize != pieces {
+                    continue;
+                }
+
+                let mut board = empty[position & 1].clone();
+                for (&square, &(piece, color)) in squares.iter().zip(&material.pieces) {
+                    board.toggle_piece(square, piece, color);
+                }
+                let side_to_move = board.side_to_move;
+                if is_king_attcked(side_to_move.toggle(), &board) {
+                    continue;
+                }
+                legal[position] = true;
+
+                let mut has_moves = false;
+                for mov in gen_color_moves(&board) {
+                    let zeroes = board.pieces[mov.get_from() as usize].0 == Piece::Pawn
+                        || board.pieces[mov.get_to() as usize].0 != Piece::None;
+
+                    let undo = board.make_move(mov);
+                    if is_legal_move(mov, &board) {
+                        has_moves = true;
+                        if !zeroes {
+                            moves_left[position] += 1;
+                        } else {
+                            let child = match board.material_key == material.material_key(false) {
+                                true => wdl[position_on(&board, &material, false)],
+                                false => self.probe(&board),
+                            };
+                            zeroing[position] = zeroing[position].max(-child);
+                        }
+                    }
+                    board.undo_move(&undo);
+                }
+
+                if !has_moves {
+                    wdl[position] = match is_king_attcked(side_to_move, &board) {
+                        true => LOSS,
+                        false => DRAW,
+                    };
+                    if wdl[position] == LOSS {
+                        levels[0].push(position as u32);
+                    }
+                } else if zeroing[position] == WIN {
+                    wdl[position] = WIN;
+                    dtz[position] = 1;
+                    levels[1].push(position as u32);
+                } else if moves_left[position] == 0 {
+                    // captures and pawn moves only
+                    wdl[position] = zeroing[position];
+                    if wdl[position] == LOSS {
+                        dtz[position] = 1;
+                        levels[1].push(position as u32);
+                    }
+                }
+            }
+
+            // every position one move away from a solved one, going back from the shortest DTZ
+            let mut level = 0;
+            while level < levels.len() {
+                let solved = std::mem::take(&mut levels[level]);
+                let next = level + 1;
+                if !solved.is_empty() && next > 100 {
+                    return Err("the 50-move rule matters here, which isn't supported");
+                }
+
+                for &position in &solved {
+                    let position = position as usize;
+                    for previous in predecessors(&material, position) {
+                        if !legal[previous] || wdl[previous] != UNKNOWN {
+                            continue;
+                        }
+
+                        if wdl[position] == LOSS {
+                            wdl[previous] = WIN;
+                        } else {
+                            moves_left[previous] -= 1;
+                            if moves_left[previous] > 0 || zeroing[previous] > LOSS {
+                                continue;
+                            }
+                            wdl[previous] = LOSS;
+                        }
+
+                        dtz[previous] = next as u8;
+                        if levels.len() <= next {
+                            levels.resize(next + 1, Vec::new());
+                        }
+                        levels[next].push(previous as u32);
+                    }
+                }
+                level += 1;
+            }
+
+            // whatever is left can't be won or lost
+            for position in 0..size {
+                if legal[position] && wdl[position] == UNKNOWN {
+                    wdl[position] = DRAW;
+                }
+            }
+        }
+
+        Ok(Table {
+            material,
+            wdl,
+            dtz,
+            zeroing,
+        })
+    }
+}
+
+/// Index of a position of `material`, with colors and ranks swapped if `flip`
+fn position_on(board: &Board, material: &Material, flip: bool) -> usize {
+    let squares: Vec<Square> = material
+        .pieces
+        .iter()
+        .map(|&(piece, color)| {
+            let color = if flip { color.toggle() } else { color };
+            let square = board.bitboards[color as usize][piece as usize].trailing_zeros();
+            square as Square ^ if flip { 56 } else { 0 }
+        })
+        .collect();
+
+    let side_to_move = match flip {
+        true => board.side_to_move.toggle(),
+        false => board.side_to_move,
+    };
+    position_of(&squares, side_to_move)
+}
+
+/// Positions where the side that just moved had a piece elsewhere, for a move that isn't a
+/// capture or a pawn move
+fn predecessors(material: &Material, position: usize) -> Vec<usize> {
+    let mover = side_to_move_of(position).toggle();
+    let squares = squares_of(position, material.pieces.len());
+    let occupancy = squares
+        .iter()
+        .fold(0, |occupancy, &square| occupancy | bit(square));
+
+    let mut predecessors = Vec::new();
+    for (i, &(piece, color)) in material.pieces.iter().enumerate() {
+        if color != mover {
+            continue;
+        }
+
+        let square = squares[i] as usize;
+        let bishop = || magics::SLIDING_ATTACKS[get_bishop_index(squares[i], occupancy)];
+        let rook = || magics::SLIDING_ATTACKS[get_rook_index(squares[i], occupancy)];
+        let from = match piece {
+            Piece::Knight => tables::KNIGHT_ATTACKS[square],
+            Piece::Bishop => bishop(),
+            Piece::Rook => rook(),
+            Piece::Queen => bishop() | rook(),
+            Piece::King => tables::KING_ATTACKS[square],
+            _ => 0,
+        } & !occupancy;
+
+        for from in from.ones_iter() {
+            let mut previous = squares.clone();
+            previous[i] = from;
+            predecessors.push(position_of(&previous, mover));
+        }
+    }
+    predecessors
+}
+
+impl Table {
+    fn sides(&self) -> Vec<Color> {
+        match self.material.symmetric {
+            true => vec![Color::White],
+            false => vec![Color::White, Color::Black],
+        }
+    }
+
+    fn files(&self) -> usize {
+        if self.material.has_pawns { 4 } else { 1 }
+    }
+
+    fn positions(&self, side_to_move: Color) -> impl Iterator<Item = (usize, Vec<Square>)> + '_ {
+        (0..self.wdl.len())
+            .filter(move |&position| {
+                self.wdl[position] != UNKNOWN && side_to_move_of(position) == side_to_move
+            })
+            .map(|position| (position, squares_of(position, self.material.pieces.len())))
+    }
+
+    /// Values of a side to move, by file, with `value` giving what to store for a position.
+    /// Symmetric positions have to agree.
+    fn values(&self, side_to_move: Color, value: impl Fn(usize) -> u8) -> Vec<Vec<u8>> {
+        let size = self.material.table_size() as usize;
+        let mut values = vec![vec![DONT_CARE; size]; self.files()];
+
+        for (position, squares) in self.positions(side_to_move) {
+            let (file, idx) = self.material.table_index(&squares);
+            let stored = &mut values[file][idx as usize];
+            let value = value(position);
+            assert!(
+                *stored == DONT_CARE || *stored == value,
+                "{}: two positions with different values share index {idx}",
+                self.material.name
+            );
+            *stored = value;
+        }
+        values
+    }
+
+    fn header(&self, magic: [u8; 4]) -> Vec<u8> {
+        let mut bytes = magic.to_vec();
+        bytes.push(!self.material.symmetric as u8 | (self.material.has_pawns as u8) << 1);
+        for _ in 0..self.files() {
+            bytes.push(0); // the leading group is the lowest part of the index
+            bytes.extend(self.material.pieces.iter().map(|&(piece, color)| {
+                let code = (piece as u8 + 1) | (color as u8) << 3;
+                code | code << 4 // the same for both sides to move
+            }));
+        }
+        bytes.resize(bytes.len().next_multiple_of(2), 0);
+        bytes
+    }
+
+    fn write(mut bytes: Vec<u8>, pairs: &[&Pairs], flags: &[u8], maps: &[Vec<u8>]) -> Vec<u8> {
+        for (pairs, &flags) in pairs.iter().zip(flags) {
+            bytes.extend(pairs.sizes(flags));
+        }
+        maps.iter().for_each(|map| bytes.extend(map));
+        bytes.resize(bytes.len().next_multiple_of(2), 0);
+
+        for pairs in pairs {
+            bytes.extend(pairs.sparse_index());
+        }
+        for pairs in pairs {
+            bytes.extend(pairs.block_lengths());
+        }
+        for pairs in pairs {
+            bytes.resize(bytes.len().next_multiple_of(64), 0);
+            bytes.extend(pairs.blocks());
+        }
+        bytes
+    }
+
+    fn wdl_file(&self) -> Vec<u8> {
+        let mut pairs: Vec<Vec<Pairs>> = (0..self.files()).map(|_| Vec::new()).collect();
+        for side_to_move in self.sides() {
+            let values = self.values(side_to_move, |position| (self.wdl[position] + 2) as u8);
+            for (file, mut values) in values.into_iter().enumerate() {
+                fill_dont_cares(&mut values);
+                pairs[file].push(compress(&values));
+            }
+        }
+
+        let pairs: Vec<&Pairs> = pairs.iter().flatten().collect();
+        let flags = vec![0; pairs.len()];
+        Table::write(self.header(WDL_MAGIC), &pairs, &flags, &[])
+    }
+
+    /// The decoder gets the DTZ of draws and of positions where a capture or pawn move is best
+    /// without the table: winning ones, or losing ones when there are no other moves (the only
+    /// losses with a DTZ of 1)
+    fn needs_dtz(&self, position: usize) -> bool {
+        match self.wdl[position] {
+            WIN => self.zeroing[position] != WIN,
+            LOSS => self.dtz[position] != 1,
+            _ => false,
+        }
+    }
+
+    /// DTZ tables only store one side to move, the one that takes less space for each file.
+    /// Values are exact plies, through a map of the ones used by wins and losses.
+    fn dtz_file(&self) -> Vec<u8> {
+        let mut chosen = Vec::new();
+        for file in 0..self.files() {
+            let mut best: Option<(Pairs, u8, Vec<u8>)> = None;
+
+            for side_to_move in self.sides() {
+                let plies = |position: usize| self.dtz[position].max(1) - 1;
+                let used = |result| {
+                    let mut used: Vec<u8> = self
+                        .positions(side_to_move)
+                        .filter(|&(position, _)| {
+                            self.wdl[position] == result && self.needs_dtz(position)
+                        })
+                        .map(|(position, _)| plies(position))
+                        .collect();
+                    used.sort();
+                    used.dedup();
+                    used
+                };
+                let (wins, losses) = (used(WIN), used(LOSS));
+
+                let values = self.values(side_to_move, |position| {
+                    let map = match self.wdl[position] {
+                        _ if !self.needs_dtz(position) => return DONT_CARE,
+                        WIN => &wins,
+                        _ => &losses,
+                    };
+                    map.binary_search(&plies(position)).unwrap() as u8
+                });
+                let mut values = values.into_iter().nth(file).unwrap();
+                fill_dont_cares(&mut values);
+                let pairs = compress(&values);
+
+                // win, loss, and the cursed ones that never happen here
+                let mut map = Vec::new();
+                for used in [&wins, &losses, &Vec::new(), &Vec::new()] {
+                    map.push(used.len() as u8);
+                    map.extend(used);
+                }
+                let flags = (STM * side_to_move as u8) | MAPPED | WIN_PLIES | LOSS_PLIES;
+
+                if best.as_ref().is_none_or(|(best, _, best_map)| {
+                    pairs.len() + map.len() < best.len() + best_map.len()
+                }) {
+                    best = Some((pairs, flags, map));
+                }
+            }
+            chosen.push(best.unwrap());
+        }
+
+        let pairs: Vec<&Pairs> = chosen.iter().map(|(pairs, _, _)| pairs).collect();
+        let flags: Vec<u8> = chosen.iter().map(|&(_, flags, _)| flags).collect();
+        let maps: Vec<Vec<u8>> = chosen.iter().map(|(_, _, map)| map.clone()).collect();
+        Table::write(self.header(DTZ_MAGIC), &pairs, &flags, &maps)
+    }
+
+    /// Reads a sample of the positions back with the engine's decoder
+    fn verify(&self, tablebases: &Tablebases) -> Result<(), &'static str> {
+        let empty = [
+            Board::new("8/8/8/8/8/8/8/8 w - - 0 1")?,
+            Board::new("8/8/8/8/8/8/8/8 b - - 0 1")?,
+        ];
+
+        for position in (0..self.wdl.len()).step_by(VERIFY_STEP) {
+            if self.wdl[position] == UNKNOWN {
+                continue;
+            }
+
+            let mut board = empty[position & 1].clone();
+            let squares = squares_of(position, self.material.pieces.len());
+            for (&square, &(piece, color)) in squares.iter().zip(&self.material.pieces) {
+                board.toggle_piece(square, piece, color);
+            }
+
+            let wdl = tablebases
+                .probe_wdl(&board)
+                .ok_or("written table not found")? as i8;
+            let dtz = tablebases
+                .probe_dtz(&board)
+                .ok_or("written table not found")?;
+            let expected_dtz =
+                self.dtz[position].max(1) as i32 * self.wdl[position].signum() as i32;
+            if wdl != self.wdl[position] || dtz != expected_dtz {
+                eprintln!(
+                    "{}: wdl {wdl} dtz {dtz}, expected wdl {} dtz {expected_dtz}",
+                    board.to_fen(),
+                    self.wdl[position]
+                );
+                return Err("the written tables don't read back the same");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn main() -> Result<(), &'static str> {
+    let mut args = std::env::args().skip(1);
+    let directory = args.next().ok_or(USAGE)?;
+    let names: Vec<String> = args.collect();
+    if names.is_empty() {
+        return Err(USAGE);
+    }
+
+    std::fs::create_dir_all(&directory).map_err(|_| "can't create the table directory")?;
+    let mut builder = Builder::default();
+    for name in &names {
+        builder.build(name, &directory)?;
+    }
+
+    Ok(())
+}
@@ -299,6 +299,16 @@ pub fn get_attackers(square: Square, attacker_color: Color, board: &Board) -> u6
 pub fn is_square_attacked(square: Square, attacker_color: Color, board: &Board) -> bool {
     let occupancy =
         board.occupancies[Color::White as usize] | board.occupancies[Color::Black as usize];
+    is_square_attacked_through(square, attacker_color, board, occupancy)
+}
+
+/// Like `is_square_attacked`, but sliders see through everything not in `occupancy`
+fn is_square_attacked_through(
+    square: Square,
+    attacker_color: Color,
+    board: &Board,
+    occupancy: u64,
+) -> bool {
     let attacker_bitboards = board.bitboards[attacker_color as usize];
     let attackers_queens = attacker_bitboards[Piece::Queen as usize];
 
@@ -322,52 +332,48 @@ pub fn is_king_attcked(king_color: Color, board: &Board) -> bool {
     is_square_attacked(
         board.bitboards[king_color as usize][Piece::King as usize].trailing_zeros() as Square,
         king_color.toggle(),
-        &board,
+        board,
     )
 }
 
-fn get_castling_moves(board: &Board) -> ArrayVec<[Move; 2]> {
-    const E1: Square = 4;
-    const WHITE_KING_SIDE: Square = E1 + 2;
-    const WHITE_QUEEN_SIDE: Square = E1 - 2;
-
-    const E8: Square = 60;
-    const BLACK_KING_SIDE: Square = E8 + 2;
-    const BLACK_QUEEN_SIDE: Square = E8 - 2;
-
-    const KING_SIDE_FLAG: MoveFlag = MoveFlag {
-        move_type: MoveType::KingSideCastle,
-        promotion: Piece::None,
-    };
-    const QUEEN_SIDE_FLAG: MoveFlag = MoveFlag {
-        move_type: MoveType::QueenSideCastle,
-        promotion: Piece::None,
-    };
+/// Squares from `a` to `b` (both included) on the same rank
+fn rank_span(a: Square, b: Square) -> u64 {
+    let (low, high) = (a.min(b), a.max(b));
+    (u64::MAX >> (63 - high)) & (u64::MAX << low)
+}
 
+/// Castling moves are encoded as king takes rook, which works for both standard chess and
+/// Chess960
+fn get_castling_moves(board: &Board) -> ArrayVec<[Move; 2]> {
     let mut castles = ArrayVec::<[Move; 2]>::new();
     let occupancy =
         board.occupancies[Color::White as usize] | board.occupancies[Color::Black as usize];
 
-    let rights = board.castling_rights;
-    match board.side_to_move {
-        Color::White => {
-            // Check only if square is empty to be able to efficiently undo the move
-            if rights & Castling::WK != 0 && occupancy & (bit(WHITE_KING_SIDE) | bit(5)) == 0 {
-                castles.push(Move::new(E1, WHITE_KING_SIDE, KING_SIDE_FLAG));
-            }
-            if rights & Castling::WQ != 0 && occupancy & (bit(WHITE_QUEEN_SIDE) | bit(3)) == 0 {
-                castles.push(Move::new(E1, WHITE_QUEEN_SIDE, QUEEN_SIDE_FLAG));
-            }
+    let color = board.side_to_move;
+    let king = board.bitboards[color as usize][Piece::King as usize].trailing_zeros() as Square;
+
+    for move_type in [MoveType::KingSideCastle, MoveType::QueenSideCastle] {
+        let right = Castling::right(color, move_type);
+        if board.castling_rights & right == 0 {
+            continue;
         }
-        Color::Black => {
-            if rights & Castling::BK != 0 && occupancy & (bit(BLACK_KING_SIDE) | bit(61)) == 0 {
-                castles.push(Move::new(E8, BLACK_KING_SIDE, KING_SIDE_FLAG));
-            }
-            if rights & Castling::BQ != 0 && occupancy & (bit(BLACK_QUEEN_SIDE) | bit(59)) == 0 {
-                castles.push(Move::new(E8, BLACK_QUEEN_SIDE, QUEEN_SIDE_FLAG));
-            }
+
+        let rook = board.castling_rook(right);
+        let (king_to, rook_to) = Castling::destinations(king, move_type);
+
+        // everything the king and the rook walk over must be empty, except for themselves
+        let path = rank_span(king, king_to) | rank_span(rook, rook_to);
+        if path & occupancy & !(bit(king) | bit(rook)) == 0 {
+            castles.push(Move::new(
+                king,
+                rook,
+                MoveFlag {
+                    move_type,
+                    promotion: Piece::None,
+                },
+            ));
         }
-    };
+    }
 
     castles
 }
@@ -380,38 +386,30 @@ pub fn is_legal_move(mov: Move, board: &Board) -> bool {
     // who made the move
     let color = board.side_to_move.toggle();
 
-    let king_bitboard = board.bitboards[color as usize][Piece::King as usize];
-
-    // Move must be already done
-    debug_assert!(
-        board.pieces[mov.get_from() as usize].0 == Piece::None
-            && board.pieces[mov.get_to() as usize].0 != Piece::None
-    );
-
     if move_type == MoveType::KingSideCastle || move_type == MoveType::QueenSideCastle {
-        let (in_between, through, rook_bit) = match (color, move_type) {
-            (Color::White, MoveType::KingSideCastle) => (bit(5) | bit(6), &[4, 5, 6][..], bit(5)),
-            (Color::White, MoveType::QueenSideCastle) => {
-                (bit(1) | bit(2) | bit(3), &[4, 3, 2][..], bit(3))
-            }
-            (Color::Black, MoveType::KingSideCastle) => {
-                (bit(61) | bit(62), &[60, 61, 62][..], bit(61))
-            }
-            (Color::Black, MoveType::QueenSideCastle) => {
-                (bit(57) | bit(58) | bit(59), &[60, 59, 58][..], bit(59))
-            }
-            _ => unreachable!(),
-        };
-
-        let occupancy =
-            board.occupancies[Color::White as usize] | board.occupancies[Color::Black as usize];
-        let occupancy_without_updated_pieces = occupancy & !(king_bitboard | rook_bit);
-
-        in_between & occupancy_without_updated_pieces == 0
-            && through
-                .iter()
-                .all(|&square| !is_square_attacked(square, color.toggle(), board))
+        let from = mov.get_from();
+        let (king_to, rook_to) = Castling::destinations(from, move_type);
+
+        // Move must be already done
+        debug_assert!(board.pieces[king_to as usize] == (Piece::King, color));
+
+        // the path was already checked to be empty on generation, so only attacks remain: the
+        // king can't castle out of, through or into check. The rook is ignored as a blocker
+        // because it wasn't there before castling (e.g. it can't block the check it escapes)
+        let occupancy = (board.occupancies[Color::White as usize]
+            | board.occupancies[Color::Black as usize])
+            & !bit(rook_to);
+
+        rank_span(from, king_to)
+            .ones_iter()
+            .all(|square| !is_square_attacked_through(square, color.toggle(), board, occupancy))
     } else {
+        // Move must be already done
+        debug_assert!(
+            board.pieces[mov.get_from() as usize].0 == Piece::None
+                && board.pieces[mov.get_to() as usize].0 != Piece::None
+        );
+
         !is_king_attcked(color, board)
     }
 }
@@ -1,5 +1,4 @@
-use super::zobrist::*;
-use crate::engine::evaluation::W;
+use super::{attacks::tables, moves::*, zobrist::*};
 
 pub const BOARD_WIDTH: usize = 8;
 pub const BOARD_SIZE: usize = 64;
@@ -82,12 +81,52 @@ pub const PIECE_TYPES: [Piece; 6] = [
     Piece::King,
 ];
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MatingMaterial {
+    /// At least one side may still be able to force checkmate
+    Sufficient,
+    /// Checkmate is possible, but neither side can force it
+    Drawish,
+    /// Checkmate is impossible for both sides (FIDE dead position)
+    Insufficient,
+}
+
 pub struct Castling;
 impl Castling {
     pub const WK: u8 = 1;
     pub const WQ: u8 = 2;
     pub const BK: u8 = 4;
     pub const BQ: u8 = 8;
+
+    /// Rook squares of a standard game, indexed like the rights (KQkq)
+    pub const STANDARD_ROOKS: [Square; 4] = [7, 0, 63, 56];
+
+    pub fn right(color: Color, move_type: MoveType) -> u8 {
+        match (color, move_type) {
+            (Color::White, MoveType::KingSideCastle) => Castling::WK,
+            (Color::White, MoveType::QueenSideCastle) => Castling::WQ,
+            (Color::Black, MoveType::KingSideCastle) => Castling::BK,
+            (Color::Black, MoveType::QueenSideCastle) => Castling::BQ,
+            _ => unreachable!("not a castling move"),
+        }
+    }
+
+    /// Returns the squares where the king and the rook land after castling, which are the same
+    /// for every starting position (g and f files or c and d files)
+    pub fn destinations(king_from: Square, move_type: MoveType) -> (Square, Square) {
+        let back_rank = king_from - king_from % BOARD_WIDTH as Square;
+        match move_type {
+            MoveType::KingSideCastle => (back_rank + 6, back_rank + 5),
+            MoveType::QueenSideCastle => (back_rank + 2, back_rank + 3),
+            _ => unreachable!("not a castling move"),
+        }
+    }
+}
+
+/// Material key of a single piece. The key counts the pieces of each type and color in 4 bits,
+/// which is enough since a side can't have more than 10 of a kind
+pub fn material_key_unit(piece_type: Piece, color: Color) -> u64 {
+    1 << (4 * (color as usize * PIECE_TYPES.len() + piece_type as usize))
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -97,14 +136,14 @@ pub struct Board {
     pub occupancies: [u64; 2],
 
     pub zobrist: u64,
+    pub material_key: u64,
+    pub pawn_key: u64, // Zobrist of the pawns alone
     pub en_passant_square: Option<Square>,
     pub halfmove_clock: u8,
-    pub castling_rights: u8, // 4 bits for KQkq
+    pub castling_rights: u8,         // 4 bits for KQkq
+    pub castling_rooks: [Square; 4], // rook of each castling right, indexed like the rights
     pub side_to_move: Color,
-
-    pub bonus: [W; 2],
-    pub material: [i16; 2],
-    pub phase: usize,
+    pub chess960: bool, // write castling moves as king takes rook
 }
 
 impl Board {
@@ -112,7 +151,8 @@ impl Board {
     /// - If the square is empty, the piece is added.
     /// - If the same piece/color is present, it is removed.
     ///
-    /// Updates bitboards, occupancies, Zobrist and evaluation terms accordingly.
+    /// Updates bitboards, occupancies, Zobrist, material and pawn keys accordingly. Evaluation
+    /// state lives in the `Evaluator`, which gets the same changes from its own hooks.
     pub fn toggle_piece(&mut self, square: Square, piece_type: Piece, color: Color) {
         let square_bit = bit(square);
         let (current_piece, current_color) = self.pieces[square as usize];
@@ -125,29 +165,20 @@ impl Board {
             (current_piece, current_color),
         );
 
-        // mirror for whites because:
-        // table index    -> 0=a8 63=h1
-        // engine square  -> 0=a1 63=h8
-        let square_lookup = match color {
-            Color::White => square as usize ^ 56, // ^ 56 mirrors vertically
-            Color::Black => square as usize,
-        };
-
         if current_piece == Piece::None {
-            self.phase += Board::PHASE_VALUE[piece_type as usize];
-            self.bonus[color as usize] += Board::PST[piece_type as usize][square_lookup];
-            self.material[color as usize] += Board::PIECE_VALUES[piece_type as usize];
+            self.material_key += material_key_unit(piece_type, color);
             self.pieces[square as usize] = (piece_type, color)
         } else {
-            self.phase -= Board::PHASE_VALUE[piece_type as usize];
-            self.bonus[color as usize] -= Board::PST[piece_type as usize][square_lookup];
-            self.material[color as usize] -= Board::PIECE_VALUES[piece_type as usize];
+            self.material_key -= material_key_unit(piece_type, color);
             self.pieces[square as usize] = (Piece::None, Color::White)
         };
         self.bitboards[color as usize][piece_type as usize] ^= square_bit;
         self.occupancies[color as usize] ^= square_bit;
 
         self.zobrist ^= ZOBRIST_PIECE[color as usize][piece_type as usize][square as usize];
+        if piece_type == Piece::Pawn {
+            self.pawn_key ^= ZOBRIST_PIECE[color as usize][Piece::Pawn as usize][square as usize];
+        }
     }
 
     /// This function doesn't update zobrist based on piece positioning because `toggle_piece`
@@ -204,6 +235,73 @@ impl Board {
         Ok(())
     }
 
+    /// Parses a castling right in any of the FEN flavours:
+    /// - `KQkq`: the outermost rook on that side (standard FEN and X-FEN)
+    /// - `A`-`H` / `a`-`h`: the rook on that file (Shredder-FEN and X-FEN inner rooks)
+    ///
+    /// Rights without a matching king and rook on the back rank are ignored.
+    fn parse_castling_right(&mut self, chr: char) {
+        let color = if chr.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let back_rank = match color {
+            Color::White => RANKS[0],
+            Color::Black => RANKS[7],
+        };
+
+        let king = self.bitboards[color as usize][Piece::King as usize] & back_rank;
+        if king == 0 {
+            return;
+        }
+        let king = king.trailing_zeros() as Square;
+
+        let rooks = self.bitboards[color as usize][Piece::Rook as usize] & back_rank;
+        let king_side = rooks & ((u64::MAX << king) << 1); // files after the king
+        let queen_side = rooks & (bit(king) - 1); // files before the king
+
+        let rook = match chr.to_ascii_lowercase() {
+            'k' if king_side != 0 => 63 - king_side.leading_zeros() as Square,
+            'q' if queen_side != 0 => queen_side.trailing_zeros() as Square,
+            file @ 'a'..='h' => {
+                let rook = back_rank.trailing_zeros() as Square + (file as u8 - b'a');
+                if rooks & bit(rook) == 0 {
+                    return;
+                }
+                rook
+            }
+            _ => return,
+        };
+
+        let move_type = if rook > king {
+            MoveType::KingSideCastle
+        } else {
+            MoveType::QueenSideCastle
+        };
+        let right = Castling::right(color, move_type);
+
+        self.castling_rights |= right;
+        self.castling_rooks[right.trailing_zeros() as usize] = rook;
+
+        // anything but a king on the e-file and rooks on the corners needs Chess960 castling
+        let king_file = king % BOARD_WIDTH as Square;
+        if king_file != 4 || rook != Castling::STANDARD_ROOKS[right.trailing_zeros() as usize] {
+            self.chess960 = true;
+        }
+    }
+
+    /// The square of the rook used by a castling right (one of the `Castling` constants)
+    pub fn castling_rook(&self, right: u8) -> Square {
+        debug_assert!(right.is_power_of_two() && right <= Castling::BQ);
+        self.castling_rooks[right.trailing_zeros() as usize]
+    }
+
+    /// Writes a move in UCI notation, with castling as king takes rook in Chess960 games
+    pub fn move_to_uci(&self, mov: Move) -> String {
+        mov.to_uci(self.chess960)
+    }
+
     pub fn new(fen: &str) -> Result<Self, &'static str> {
         let mut tokens = fen.split_whitespace();
 
@@ -213,14 +311,14 @@ impl Board {
             occupancies: [0u64; 2],
 
             zobrist: 0u64,
+            material_key: 0u64,
+            pawn_key: 0u64,
             en_passant_square: None,
             halfmove_clock: 0,
             castling_rights: 0,
+            castling_rooks: Castling::STANDARD_ROOKS,
             side_to_move: Color::White,
-
-            bonus: [W(0, 0); 2],
-            phase: 0,
-            material: [0; 2],
+            chess960: false,
         };
 
         if let Some(positioning_part) = tokens.next() {
@@ -237,13 +335,7 @@ impl Board {
 
         if let Some(castling_part) = tokens.next() {
             for chr in castling_part.chars() {
-                board.castling_rights |= match chr {
-                    'K' => 1 << 0,
-                    'Q' => 1 << 1,
-                    'k' => 1 << 2,
-                    'q' => 1 << 3,
-                    _ => 0u8,
-                }
+                board.parse_castling_right(chr);
             }
         }
 
@@ -262,26 +354,131 @@ impl Board {
         Ok(board)
     }
 
-    /// Checks for insufficient material draws: KvK, KvN, KvB, and KvNN
-    pub fn is_insufficient_material(&self) -> bool {
-        let (mut pawn_rook_queen, mut bishop, mut knight) = (0u64, 0u64, 0u64);
-        for color in 0..2 {
-            pawn_rook_queen |= self.bitboards[color][Piece::Pawn as usize]
-                | self.bitboards[color][Piece::Rook as usize]
-                | self.bitboards[color][Piece::Queen as usize];
-            bishop |= self.bitboards[color][Piece::Bishop as usize];
-            knight |= self.bitboards[color][Piece::Knight as usize];
+    /// The position as a FEN, with Shredder-FEN castling rights in Chess960 games. The board
+    /// doesn't count full moves, so it's always 1
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for rank in (0..BOARD_WIDTH as i8).rev() {
+            let mut empty = 0;
+            for file in 0..BOARD_WIDTH as i8 {
+                match self.pieces[to_square(rank, file) as usize] {
+                    (Piece::None, _) => empty += 1,
+                    (piece_type, color) => {
+                        if empty > 0 {
+                            placement += &empty.to_string();
+                            empty = 0;
+                        }
+                        placement.push(match color {
+                            Color::White => piece_type.to_char().to_ascii_uppercase(),
+                            Color::Black => piece_type.to_char(),
+                        });
+                    }
+                }
+            }
+            if empty > 0 {
+                placement += &empty.to_string();
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+
+        let side = match self.side_to_move {
+            Color::White => 'w',
+            Color::Black => 'b',
+        };
+
+        let mut castling = String::new();
+        for (right, standard) in [
+            (Castling::WK, 'K'),
+            (Castling::WQ, 'Q'),
+            (Castling::BK, 'k'),
+            (Castling::BQ, 'q'),
+        ] {
+            if self.castling_rights & right == 0 {
+                continue;
+            }
+            castling.push(if self.chess960 {
+                let file = (b'a' + self.castling_rook(right) % BOARD_WIDTH as u8) as char;
+                match standard.is_ascii_uppercase() {
+                    true => file.to_ascii_uppercase(),
+                    false => file,
+                }
+            } else {
+                standard
+            });
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let en_passant = match self.en_passant_square {
+            Some(square) => format!(
+                "{}{}",
+                (b'a' + square % BOARD_WIDTH as u8) as char,
+                square / BOARD_WIDTH as u8 + 1
+            ),
+            None => "-".to_string(),
+        };
+
+        format!(
+            "{placement} {side} {castling} {en_passant} {} 1",
+            self.halfmove_clock
+        )
+    }
+
+    /// Classifies the material on the board by how (if at all) checkmate can still happen.
+    ///
+    /// Only looks at the pieces, so positions that are dead because of the pawn structure
+    /// (fully blocked files and the like) are reported as `Sufficient`
+    pub fn mating_material(&self) -> MatingMaterial {
+        let mut pawn_rook_queen = 0u64;
+        for color in [Color::White, Color::Black] {
+            pawn_rook_queen |= self.bitboards[color as usize][Piece::Pawn as usize]
+                | self.bitboards[color as usize][Piece::Rook as usize]
+                | self.bitboards[color as usize][Piece::Queen as usize];
         }
         if pawn_rook_queen != 0 {
-            return false;
+            return MatingMaterial::Sufficient;
+        }
+
+        let [white_knights, black_knights] = [Color::White, Color::Black]
+            .map(|c| self.bitboards[c as usize][Piece::Knight as usize]);
+        let [white_bishops, black_bishops] = [Color::White, Color::Black]
+            .map(|c| self.bitboards[c as usize][Piece::Bishop as usize]);
+        let knights = white_knights | black_knights;
+        let bishops = white_bishops | black_bishops;
+        let minors = knights | bishops;
+
+        // a lone minor piece can't mate, and neither can any number of bishops that all
+        // travel on the same color complex
+        let same_colored_bishops = bishops & LIGHT_SQUARES == 0 || bishops & DARK_SQUARES == 0;
+        if minors.count_ones() <= 1 || (knights == 0 && same_colored_bishops) {
+            return MatingMaterial::Insufficient;
         }
 
-        let minors = bishop | knight;
-        let either_bare = self.occupancies[Color::White as usize].count_ones() == 1
-            || self.occupancies[Color::White as usize].count_ones() == 1;
-        let have_one_minor = minors == 0 || minors & (minors - 1) == 0;
+        // can this side mate a bare king by force? (KBN, KBB on both colors, three minors...)
+        let can_force_mate = |knights: u64, bishops: u64| {
+            let minors = (knights | bishops).count_ones();
+            let both_colors = bishops & LIGHT_SQUARES != 0 && bishops & DARK_SQUARES != 0;
+
+            minors >= 3 || (minors == 2 && (both_colors || (bishops != 0 && knights != 0)))
+        };
+
+        if can_force_mate(white_knights, white_bishops)
+            || can_force_mate(black_knights, black_bishops)
+        {
+            MatingMaterial::Sufficient
+        } else {
+            // KNN v K, KN v KN, KB v KB on opposite colors...
+            MatingMaterial::Drawish
+        }
+    }
 
-        either_bare && (have_one_minor || (bishop == 0 && knight.count_ones() <= 2))
+    /// Checks for material that can't lead to a checkmate by any sequence of legal moves (KvK,
+    /// KvN, KvB and same colored bishops)
+    pub fn is_insufficient_material(&self) -> bool {
+        self.mating_material() == MatingMaterial::Insufficient
     }
 
     pub fn is_fifty_move(&self) -> bool {
@@ -320,6 +517,46 @@ impl Board {
             ^ ZOBRIST_CASTLING[self.castling_rights as usize]
             ^ en_passant_zobrist
     }
+
+    /// Hash of the position using the Polyglot random numbers, as found in `.bin` opening books.
+    ///
+    /// Unlike `zobrist`, it isn't updated incrementally, so it's meant for book lookups only.
+    pub fn polyglot_key(&self) -> u64 {
+        let mut key = 0u64;
+        for color in [Color::White, Color::Black] {
+            for piece_type in PIECE_TYPES {
+                // polyglot interleaves colors, black first: bp, wp, bn, wn, ...
+                let kind = piece_type as usize * 2 + (color == Color::White) as usize;
+                for square in self.bitboards[color as usize][piece_type as usize].ones_iter() {
+                    key ^= POLYGLOT_RANDOM[POLYGLOT_PIECE + kind * BOARD_SIZE + square as usize];
+                }
+            }
+        }
+
+        // the rights bits are in the same KQkq order polyglot uses
+        for right in (self.castling_rights as u64).ones_iter() {
+            key ^= POLYGLOT_RANDOM[POLYGLOT_CASTLING + right as usize];
+        }
+
+        // polyglot only hashes the en passant file if a pawn can actually capture there
+        if let Some(en_passant) = self.en_passant_square {
+            let attackers = match self.side_to_move {
+                Color::White => tables::BPAWN_ATTACKS[en_passant as usize],
+                Color::Black => tables::WPAWN_ATTACKS[en_passant as usize],
+            };
+            let pawns = self.bitboards[self.side_to_move as usize][Piece::Pawn as usize];
+            if attackers & pawns != 0 {
+                key ^= POLYGLOT_RANDOM
+                    [POLYGLOT_EN_PASSANT + (en_passant % BOARD_WIDTH as Square) as usize];
+            }
+        }
+
+        if self.side_to_move == Color::White {
+            key ^= POLYGLOT_RANDOM[POLYGLOT_TURN];
+        }
+
+        key
+    }
 }
 
 pub const RANKS: [u64; BOARD_WIDTH] = [
@@ -333,6 +570,20 @@ pub const RANKS: [u64; BOARD_WIDTH] = [
     0xFF00000000000000,
 ];
 
+pub const FILES: [u64; BOARD_WIDTH] = [
+    0x0101010101010101,
+    0x0202020202020202,
+    0x0404040404040404,
+    0x0808080808080808,
+    0x1010101010101010,
+    0x2020202020202020,
+    0x4040404040404040,
+    0x8080808080808080,
+];
+
+pub const LIGHT_SQUARES: u64 = 0x55AA55AA55AA55AA;
+pub const DARK_SQUARES: u64 = !LIGHT_SQUARES;
+
 pub fn to_square(rank: i8, file: i8) -> Square {
     ((rank * BOARD_WIDTH as i8) + file) as Square
 }
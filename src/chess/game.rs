@@ -0,0 +1,166 @@
+use crate::chess::*;
+
+/// Why a game ended (or can be claimed to have ended), following the FIDE Laws of Chess
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Checkmate {
+        winner: Color,
+    },
+    Stalemate,
+    /// No sequence of legal moves can lead to a checkmate (dead position)
+    InsufficientMaterial,
+    /// Automatic draw: the same position appeared five times
+    FivefoldRepetition,
+    /// Automatic draw: 75 moves by each side without a capture or a pawn move
+    SeventyFiveMoveRule,
+    /// Claimable draw: the same position appeared three times
+    ThreefoldRepetition,
+    /// Claimable draw: 50 moves by each side without a capture or a pawn move
+    FiftyMoveRule,
+}
+
+impl Outcome {
+    pub fn winner(self) -> Option<Color> {
+        match self {
+            Outcome::Checkmate { winner } => Some(winner),
+            _ => None,
+        }
+    }
+
+    pub fn is_draw(self) -> bool {
+        self.winner().is_none()
+    }
+
+    /// Whether the game ends by itself, without a player having to claim it
+    pub fn is_automatic(self) -> bool {
+        !matches!(self, Outcome::ThreefoldRepetition | Outcome::FiftyMoveRule)
+    }
+}
+
+/// A `Board` together with the moves that led to it.
+///
+/// Keeps the undo stack and the zobrist key of every position reached, so it can take moves
+/// back and detect repetitions and the end of the game.
+pub struct Game {
+    board: Board,
+    undos: Vec<Undo>,
+    // zobrist of every position reached, including the initial one
+    positions: Vec<u64>,
+}
+
+impl Game {
+    pub fn new(fen: &str) -> Result<Game, &'static str> {
+        Ok(Game::from_board(Board::new(fen)?))
+    }
+
+    pub fn from_board(board: Board) -> Game {
+        Game {
+            positions: vec![board.zobrist],
+            undos: Vec::new(),
+            board,
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Zobrist keys of every position of the game, from the initial one to the current one
+    pub fn positions(&self) -> &[u64] {
+        &self.positions
+    }
+
+    pub fn moves(&self) -> impl ExactSizeIterator<Item = Move> + '_ {
+        self.undos.iter().map(Undo::get_move)
+    }
+
+    /// All the legal moves in the current position
+    pub fn legal_moves(&self) -> MoveList {
+        let mut board = self.board.clone();
+
+        gen_color_moves(&self.board)
+            .into_iter()
+            .filter(|&mov| {
+                let undo = board.make_move(mov);
+                let legal = is_legal_move(mov, &board);
+                board.undo_move(&undo);
+                legal
+            })
+            .collect()
+    }
+
+    /// # Preconditions
+    /// - `mov` must be a legal move in the current position
+    pub fn make_move(&mut self, mov: Move) {
+        let undo = self.board.make_move(mov);
+        self.undos.push(undo);
+        self.positions.push(self.board.zobrist);
+    }
+
+    /// Parses and plays a move in UCI notation, rejecting it if it's not legal
+    pub fn make_uci_move(&mut self, uci: &str) -> Result<Move, &'static str> {
+        let mov = self
+            .legal_moves()
+            .into_iter()
+            .find(|&mov| self.board.move_to_uci(mov) == uci)
+            .ok_or("illegal move")?;
+
+        self.make_move(mov);
+        Ok(mov)
+    }
+
+    /// Takes back the last move, returning it
+    pub fn undo_move(&mut self) -> Option<Move> {
+        let undo = self.undos.pop()?;
+        self.board.undo_move(&undo);
+        self.positions.pop();
+
+        Some(undo.get_move())
+    }
+
+    /// How many times the current position has appeared in the game, counting this one
+    pub fn repetitions(&self) -> usize {
+        // positions before the last capture or pawn move can't be repeated
+        self.positions
+            .iter()
+            .rev()
+            .take(self.board.halfmove_clock as usize + 1)
+            .step_by(2) // check only positions with same side to move
+            .filter(|&&zobrist| zobrist == self.board.zobrist)
+            .count()
+    }
+
+    /// Returns how the game ended, or `None` if it's still going on.
+    ///
+    /// Automatic endings take precedence over claimable draws, and checkmate takes precedence
+    /// over any move-count rule.
+    pub fn outcome(&self) -> Option<Outcome> {
+        if self.legal_moves().is_empty() {
+            let color = self.board.side_to_move;
+            return Some(if is_king_attcked(color, &self.board) {
+                Outcome::Checkmate {
+                    winner: color.toggle(),
+                }
+            } else {
+                Outcome::Stalemate
+            });
+        }
+
+        if self.board.is_insufficient_material() {
+            return Some(Outcome::InsufficientMaterial);
+        }
+
+        let repetitions = self.repetitions();
+        if repetitions >= 5 {
+            Some(Outcome::FivefoldRepetition)
+        } else if self.board.halfmove_clock >= 150 {
+            Some(Outcome::SeventyFiveMoveRule)
+        } else if repetitions >= 3 {
+            Some(Outcome::ThreefoldRepetition)
+        } else if self.board.is_fifty_move() {
+            Some(Outcome::FiftyMoveRule)
+        } else {
+            None
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::chess::{zobrist::*, *};
+use crate::chess::{attacks::tables, zobrist::*, *};
 
 pub struct Undo {
     mov: Move,
@@ -9,15 +9,20 @@ pub struct Undo {
     zobrist: u64,
 }
 
+impl Undo {
+    pub fn get_move(&self) -> Move {
+        self.mov
+    }
+}
+
 impl Board {
     fn update_rights_on_rook_change(&mut self, square: Square, color: Color) {
-        self.castling_rights &= !(match (square, color) {
-            (0, Color::White) => Castling::WQ,  // a1
-            (7, Color::White) => Castling::WK,  // h1
-            (56, Color::Black) => Castling::BQ, // a8
-            (63, Color::Black) => Castling::BK, // h8
-            _ => 0,
-        });
+        for move_type in [MoveType::KingSideCastle, MoveType::QueenSideCastle] {
+            let right = Castling::right(color, move_type);
+            if self.castling_rook(right) == square {
+                self.castling_rights &= !right;
+            }
+        }
     }
 
     /// Returns the square of the pawn captured by an en passant move
@@ -51,6 +56,63 @@ impl Board {
         }
     }
 
+    /// Calls `change` with every piece `make_move` would add (`true`) or remove (`false`) for
+    /// `mov`, so state kept outside the board can follow the moves.
+    ///
+    /// # Preconditions
+    /// - `mov` must be a legal move in the current position
+    pub fn piece_changes(&self, mov: Move, mut change: impl FnMut(Piece, Color, Square, bool)) {
+        let from = mov.get_from();
+        let to = mov.get_to();
+        let flags = mov.get_flags();
+        let color = self.side_to_move;
+        let (piece_type, _) = self.pieces[from as usize];
+
+        change(piece_type, color, from, false);
+
+        let landing = match flags.move_type {
+            MoveType::Capture => {
+                let (captured_piece, captured_color) = self.pieces[to as usize];
+                change(captured_piece, captured_color, to, false);
+                to
+            }
+            MoveType::EnPassantCapture => {
+                let target = Board::get_en_passant_target(to, color);
+                change(Piece::Pawn, color.toggle(), target, false);
+                to
+            }
+            MoveType::KingSideCastle | MoveType::QueenSideCastle => {
+                let (king_to, rook_to) = Castling::destinations(from, flags.move_type);
+                change(Piece::Rook, color, to, false);
+                change(Piece::Rook, color, rook_to, true);
+                king_to
+            }
+            _ => to,
+        };
+
+        let final_type = match flags.promotion {
+            Piece::None => piece_type,
+            promotion => promotion,
+        };
+        change(final_type, color, landing, true);
+    }
+
+    /// The Zobrist key after `mov` without making it, for prefetching. Castling rights and the
+    /// new en passant square are left out, so it's only exact for the moves that change neither
+    ///
+    /// # Preconditions
+    /// - `mov` must be a legal move in the current position
+    pub fn key_after(&self, mov: Move) -> u64 {
+        let mut key = self.zobrist ^ *ZOBRIST_SIDE;
+        if let Some(en_passant) = self.en_passant_square {
+            key ^= ZOBRIST_EN_PASSANT[(en_passant % BOARD_WIDTH as Square) as usize];
+        }
+        self.piece_changes(mov, |piece_type, color, square, _| {
+            key ^= ZOBRIST_PIECE[color as usize][piece_type as usize][square as usize];
+        });
+        key
+    }
+
     /// Makes a move on the board, updating all internal state.
     /// Returns an `Undo` object that can restore the exact previous state.
     ///
@@ -61,8 +123,15 @@ impl Board {
         let to: Square = mov.get_to();
         let flags: MoveFlag = mov.get_flags();
         let move_type: MoveType = flags.move_type;
+        let is_castling =
+            move_type == MoveType::KingSideCastle || move_type == MoveType::QueenSideCastle;
         // `captured_color` is white if square is empty (captured_piece = Piece::None)
-        let (captured_piece, captured_color): (Piece, Color) = self.pieces[to as usize];
+        // castling is encoded as king takes rook, but the own rook is not captured
+        let (captured_piece, captured_color): (Piece, Color) = if is_castling {
+            (Piece::None, Color::White)
+        } else {
+            self.pieces[to as usize]
+        };
         // I ignore the color since is the same to self.side_to_move
         let (piece_type, _): (Piece, Color) = self.pieces[from as usize];
         let final_type: Piece = if flags.promotion != Piece::None {
@@ -83,31 +152,35 @@ impl Board {
             MoveType::EnPassantCapture => {
                 self.toggle_piece(Board::get_en_passant_target(to, color), Piece::Pawn, enemy);
             }
-            MoveType::KingSideCastle => {
-                let (rook_from, rook_to) = match color {
-                    Color::White => (7, 5),   // h1 -> f1
-                    Color::Black => (63, 61), // h8 -> f1
-                };
-                self.toggle_piece(rook_from, Piece::Rook, color);
-                self.toggle_piece(rook_to, Piece::Rook, color);
-            }
-            MoveType::QueenSideCastle => {
-                let (rook_from, rook_to) = match color {
-                    Color::White => (0, 3),   // a1 -> d1
-                    Color::Black => (56, 59), // a8 -> d8
-                };
-                self.toggle_piece(rook_from, Piece::Rook, color);
+            MoveType::KingSideCastle | MoveType::QueenSideCastle => {
+                // `to` is the rook's square
+                let (_, rook_to) = Castling::destinations(from, move_type);
+                self.toggle_piece(to, Piece::Rook, color);
                 self.toggle_piece(rook_to, Piece::Rook, color);
             }
             _ => {}
         }
 
         // Land the moved piece
-        self.toggle_piece(to, final_type, color);
+        let landing = if is_castling {
+            Castling::destinations(from, move_type).0
+        } else {
+            to
+        };
+        self.toggle_piece(landing, final_type, color);
 
         let old_en_passant = self.en_passant_square;
         self.en_passant_square = if move_type == MoveType::DoublePawnPush {
-            Some(Board::get_en_passant_target(to, color))
+            let en_passant = Board::get_en_passant_target(to, color);
+
+            // only record the square when an enemy pawn can actually capture on it, so the
+            // same position reached by different move orders hashes (and repeats) the same
+            let attackers = match color {
+                Color::White => tables::WPAWN_ATTACKS[en_passant as usize],
+                Color::Black => tables::BPAWN_ATTACKS[en_passant as usize],
+            };
+            (attackers & self.bitboards[enemy as usize][Piece::Pawn as usize] != 0)
+                .then_some(en_passant)
         } else {
             None
         };
@@ -116,7 +189,8 @@ impl Board {
         if piece_type == Piece::Pawn || move_type == MoveType::Capture {
             self.halfmove_clock = 0;
         } else {
-            self.halfmove_clock += 1;
+            // saturate so long shuffling games past the 75-move rule can't overflow
+            self.halfmove_clock = self.halfmove_clock.saturating_add(1);
         };
 
         let old_rights = self.castling_rights;
@@ -161,6 +235,21 @@ impl Board {
         let to: Square = mov.get_to();
         let flags: MoveFlag = mov.get_flags();
         let move_type: MoveType = flags.move_type;
+        let color = self.side_to_move;
+
+        if move_type == MoveType::KingSideCastle || move_type == MoveType::QueenSideCastle {
+            // take both pieces out before putting them back, in Chess960 their squares can
+            // overlap
+            let (king_to, rook_to) = Castling::destinations(from, move_type);
+            self.toggle_piece(king_to, Piece::King, color);
+            self.toggle_piece(rook_to, Piece::Rook, color);
+            self.toggle_piece(to, Piece::Rook, color);
+            self.toggle_piece(from, Piece::King, color);
+
+            self.zobrist = undo.zobrist;
+            return;
+        }
+
         let (piece_type, _): (Piece, Color) = self.pieces[to as usize];
         let final_type: Piece = if flags.promotion != Piece::None {
             flags.promotion
@@ -172,7 +261,6 @@ impl Board {
         } else {
             piece_type
         };
-        let color = self.side_to_move;
 
         // Clear the moved piece
         self.toggle_piece(to, final_type, color);
@@ -187,22 +275,6 @@ impl Board {
                     color.toggle(),
                 );
             }
-            MoveType::KingSideCastle => {
-                let (rook_from, rook_to) = match color {
-                    Color::White => (7, 5),   // h1 -> f1
-                    Color::Black => (63, 61), // h8 -> f1
-                };
-                self.toggle_piece(rook_from, Piece::Rook, color);
-                self.toggle_piece(rook_to, Piece::Rook, color);
-            }
-            MoveType::QueenSideCastle => {
-                let (rook_from, rook_to) = match color {
-                    Color::White => (0, 3),   // a1 -> d1
-                    Color::Black => (56, 59), // a8 -> d8
-                };
-                self.toggle_piece(rook_from, Piece::Rook, color);
-                self.toggle_piece(rook_to, Piece::Rook, color);
-            }
             _ => {}
         }
 
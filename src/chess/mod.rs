@@ -1,10 +1,16 @@
 pub mod attacks;
 pub mod board;
+pub mod cuckoo;
+pub mod game;
 pub mod make_move;
 pub mod moves;
+pub mod pgn;
 mod zobrist;
 
 pub use attacks::movegen::*;
 pub use board::*;
+pub use game::*;
 pub use make_move::*;
 pub use moves::*;
+pub use pgn::*;
+pub use zobrist::zobrist_signature;
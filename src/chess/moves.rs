@@ -72,9 +72,18 @@ impl Move {
         move_flag
     }
 
-    pub fn to_uci(self) -> String {
+    /// Castling moves are encoded as king takes rook. In UCI they are written as the king's two
+    /// square move (e1g1), unless `chess960` is set, where they stay as king takes rook (e1h1)
+    pub fn to_uci(self, chess960: bool) -> String {
         let from_square = self.get_from();
-        let to_square = self.get_to();
+        let move_flags = self.get_flags();
+
+        let to_square = match move_flags.move_type {
+            MoveType::KingSideCastle | MoveType::QueenSideCastle if !chess960 => {
+                Castling::destinations(from_square, move_flags.move_type).0
+            }
+            _ => self.get_to(),
+        };
 
         let from_rank = from_square / BOARD_WIDTH as u8;
         let from_file = from_square % BOARD_WIDTH as u8;
@@ -82,8 +91,6 @@ impl Move {
         let to_rank = to_square / BOARD_WIDTH as u8;
         let to_file = to_square % BOARD_WIDTH as u8;
 
-        let move_flags = self.get_flags();
-
         if move_flags.promotion != Piece::None {
             format!(
                 "{}{}{}{}{}",
@@ -0,0 +1,219 @@
+use crate::chess::*;
+
+/// A game as written in a PGN file: its tag pairs and the moves of the main line in SAN
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    /// `1-0`, `0-1`, `1/2-1/2` or `*`
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The game with its moves played, starting from the `FEN` tag if there's one
+    pub fn replay(&self) -> Result<Game, &'static str> {
+        let mut game = Game::new(self.tag("FEN").unwrap_or(STARTPOS_FEN))?;
+        for san in &self.moves {
+            game.make_san_move(san)?;
+        }
+
+        Ok(game)
+    }
+}
+
+/// Splits a PGN collection into its games.
+///
+/// Comments, variations, NAGs and move numbers are skipped, so only the main line is kept.
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut in_movetext = false;
+
+    // nesting of `(...)` variations and whether we're inside a `{...}` comment
+    let mut variation_depth = 0usize;
+    let mut in_comment = false;
+
+    for line in text.lines() {
+        let line = line.trim();
+
+        if !in_comment && variation_depth == 0 && line.starts_with('[') {
+            // a tag after some moves means a new game without a result token
+            if in_movetext {
+                games.push(std::mem::take(&mut game));
+                in_movetext = false;
+            }
+            if let Some(tag) = parse_tag(line) {
+                game.tags.push(tag);
+            }
+            continue;
+        }
+
+        let mut rest = line;
+        while !rest.is_empty() {
+            if in_comment {
+                match rest.find('}') {
+                    Some(end) => {
+                        in_comment = false;
+                        rest = &rest[end + 1..];
+                    }
+                    None => break,
+                }
+                continue;
+            }
+
+            let chr = rest.chars().next().unwrap();
+            match chr {
+                '{' => in_comment = true,
+                ';' | '%' => break, // comment or escape until the end of the line
+                '(' => variation_depth += 1,
+                ')' => variation_depth = variation_depth.saturating_sub(1),
+                '}' => {} // stray, there's no comment to close
+                c if c.is_whitespace() => {}
+                _ => {
+                    let end = rest
+                        .find(|c: char| c.is_whitespace() || "{}();".contains(c))
+                        .unwrap_or(rest.len());
+                    let token = &rest[..end];
+                    rest = &rest[end..];
+
+                    if variation_depth == 0 {
+                        in_movetext = true;
+                        if let Some(result) = push_token(&mut game, token) {
+                            game.result = result.to_string();
+                            games.push(std::mem::take(&mut game));
+                            in_movetext = false;
+                        }
+                    }
+                    continue;
+                }
+            }
+            rest = &rest[chr.len_utf8()..];
+        }
+    }
+
+    if in_movetext {
+        games.push(game);
+    }
+
+    games
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    Some((name.to_string(), value.replace("\\\"", "\"")))
+}
+
+/// Adds a movetext token to the game, returning it if it's the game termination marker
+fn push_token<'a>(game: &mut PgnGame, token: &'a str) -> Option<&'a str> {
+    if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") {
+        return Some(token);
+    }
+
+    if token.starts_with('$') {
+        return None;
+    }
+
+    // move numbers, which may be glued to the move as in `1.e4` or `12...Nf6`
+    let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if !san.is_empty() {
+        game.moves.push(san.to_string());
+    }
+
+    None
+}
+
+impl Game {
+    /// Finds the legal move written in Standard Algebraic Notation (e.g. `Nbd7`, `exd6`, `O-O`
+    /// or `e8=Q+`)
+    pub fn parse_san(&self, san: &str) -> Result<Move, &'static str> {
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        let legal_moves = self.legal_moves();
+
+        let castle = match san {
+            "O-O" | "0-0" => Some(MoveType::KingSideCastle),
+            "O-O-O" | "0-0-0" => Some(MoveType::QueenSideCastle),
+            _ => None,
+        };
+        if let Some(castle) = castle {
+            return legal_moves
+                .into_iter()
+                .find(|mov| mov.get_flags().move_type == castle)
+                .ok_or("illegal castling");
+        }
+
+        let mut chars: Vec<char> = san.chars().filter(|&c| c != 'x' && c != '=').collect();
+
+        let piece_type = match chars.first() {
+            Some(c @ ('N' | 'B' | 'R' | 'Q' | 'K')) => {
+                let piece_type = Piece::from_char(*c)?;
+                chars.remove(0);
+                piece_type
+            }
+            _ => Piece::Pawn,
+        };
+
+        let promotion = match chars.last() {
+            Some(c @ ('N' | 'B' | 'R' | 'Q')) => {
+                let promotion = Piece::from_char(*c)?;
+                chars.pop();
+                promotion
+            }
+            _ => Piece::None,
+        };
+
+        if chars.len() < 2 {
+            return Err("invalid SAN move");
+        }
+        let to_uci: String = chars[chars.len() - 2..].iter().collect();
+        let to = square_from_uci(&to_uci)?;
+
+        // whatever is left between the piece and the destination disambiguates the origin
+        let mut from_file = None;
+        let mut from_rank = None;
+        for &c in &chars[..chars.len() - 2] {
+            match c {
+                'a'..='h' => from_file = Some(c as u8 - b'a'),
+                '1'..='8' => from_rank = Some(c as u8 - b'1'),
+                _ => return Err("invalid SAN move"),
+            }
+        }
+
+        let mut candidates = legal_moves.into_iter().filter(|&mov| {
+            let from = mov.get_from();
+            let flags = mov.get_flags();
+
+            self.board().pieces[from as usize].0 == piece_type
+                && mov.get_to() == to
+                && !matches!(
+                    flags.move_type,
+                    MoveType::KingSideCastle | MoveType::QueenSideCastle
+                )
+                && flags.promotion == promotion
+                && from_file.is_none_or(|file| from % BOARD_WIDTH as Square == file)
+                && from_rank.is_none_or(|rank| from / BOARD_WIDTH as Square == rank)
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mov), None) => Ok(mov),
+            (None, _) => Err("illegal SAN move"),
+            (Some(_), Some(_)) => Err("ambiguous SAN move"),
+        }
+    }
+
+    /// Parses and plays a move in SAN, rejecting it if it's not legal
+    pub fn make_san_move(&mut self, san: &str) -> Result<Move, &'static str> {
+        let mov = self.parse_san(san)?;
+        self.make_move(mov);
+        Ok(mov)
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    time::Instant,
+};
+
+use sand::engine::{
+    datagen::{Settings, generate},
+    evaluation::EvaluatorKind,
+};
+
+const USAGE: &str = "usage: datagen <output> [--games N] [--nodes N] [--random-plies N] \
+                     [--threads N] [--seed N] [--hash MB] [--openings <starts.epd>] \
+                     [--format text|binary] [--evaluator classical|material|nnue]";
+
+// how often the progress is reported, in games
+const REPORT_INTERVAL: u64 = 100;
+
+struct Options {
+    output_path: String,
+    games: u64,
+    threads: usize,
+    seed: u64,
+    /// one FEN or EPD per line, each game starts from one of them picked at random
+    openings_path: Option<String>,
+    binary: bool,
+    settings: Settings,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, &'static str> {
+        let mut options = Options {
+            output_path: args.next().ok_or(USAGE)?,
+            games: 100,
+            threads: std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+            seed: 0,
+            openings_path: None,
+            binary: false,
+            settings: Settings {
+                nodes: 5000,
+                random_plies: 8,
+                hash_mb: 8,
+                evaluator: EvaluatorKind::Classical,
+            },
+        };
+
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or(USAGE)?;
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| "option values must be numbers")
+            };
+
+            match flag.as_str() {
+                "--games" => options.games = number()?,
+                "--nodes" => options.settings.nodes = number()? as usize,
+                "--random-plies" => options.settings.random_plies = number()? as usize,
+                "--threads" => options.threads = number()? as usize,
+                "--seed" => options.seed = number()?,
+                "--hash" => options.settings.hash_mb = number()? as usize,
+                "--openings" => options.openings_path = Some(value),
+                "--format" => {
+                    options.binary = match value.as_str() {
+                        "text" => false,
+                        "binary" => true,
+                        _ => return Err(USAGE),
+                    }
+                }
+                "--evaluator" => {
+                    options.settings.evaluator = EvaluatorKind::from_name(&value).ok_or(USAGE)?
+                }
+                _ => return Err(USAGE),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+pub fn main() -> Result<(), &'static str> {
+    let options = Options::parse(std::env::args().skip(1))?;
+
+    let openings: Vec<String> = match &options.openings_path {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|_| "can't read openings")?
+            .lines()
+            // EPD operations after the FEN fields are dropped
+            .map(|line| {
+                line.split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string()
+            })
+            .filter(|line| !line.is_empty())
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let file = File::create(&options.output_path).map_err(|_| "can't create output file")?;
+    let mut output = BufWriter::new(file);
+    let mut positions = 0;
+    let mut write_error = false;
+    let start = Instant::now();
+
+    generate(
+        options.games,
+        options.threads,
+        options.seed,
+        &openings,
+        &options.settings,
+        |game, samples| {
+            for sample in &samples {
+                let written = match options.binary {
+                    true => output.write_all(&sample.to_bytes()),
+                    false => writeln!(output, "{}", sample.to_text()),
+                };
+                write_error |= written.is_err();
+            }
+            positions += samples.len();
+
+            if (game + 1) % REPORT_INTERVAL == 0 {
+                println!(
+                    "{} games, {positions} positions, {:.0} positions/s",
+                    game + 1,
+                    positions as f64 / start.elapsed().as_secs_f64()
+                );
+            }
+        },
+    );
+
+    output.flush().map_err(|_| "can't write output file")?;
+    if write_error {
+        return Err("can't write output file");
+    }
+    println!(
+        "{} games, {positions} positions written to {}",
+        options.games, options.output_path
+    );
+
+    Ok(())
+}
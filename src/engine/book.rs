@@ -0,0 +1,148 @@
+use crate::chess::*;
+use rand::Rng;
+
+const ENTRY_SIZE: usize = 16;
+
+/// One move of a Polyglot book, stored big endian as key (8), move (2), weight (2), learn (4)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub mov: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    pub fn from_bytes(bytes: &[u8; ENTRY_SIZE]) -> BookEntry {
+        BookEntry {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mov: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0u8; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mov.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+
+    /// Packs a move the way Polyglot does, the inverse of `to_move`
+    pub fn encode_move(mov: Move) -> u16 {
+        let promotion = mov.get_flags().promotion;
+        // the promotion pieces have the same values as in polyglot (knight = 1 .. queen = 4)
+        let promotion_bits = if promotion == Piece::None {
+            0
+        } else {
+            promotion as u16
+        };
+
+        promotion_bits << 12 | (mov.get_from() as u16) << 6 | mov.get_to() as u16
+    }
+
+    /// Finds the legal move this entry stands for, if any.
+    ///
+    /// Polyglot moves are packed as to (6 bits), from (6 bits) and promotion (3 bits), with
+    /// castling written as king takes rook, just like `Move` does.
+    pub fn to_move(self, game: &Game) -> Option<Move> {
+        let to = (self.mov & 0x3f) as Square;
+        let from = (self.mov >> 6 & 0x3f) as Square;
+        let promotion = match self.mov >> 12 & 0x7 {
+            0 => Piece::None,
+            1 => Piece::Knight,
+            2 => Piece::Bishop,
+            3 => Piece::Rook,
+            4 => Piece::Queen,
+            _ => return None,
+        };
+
+        game.legal_moves().into_iter().find(|&mov| {
+            mov.get_from() == from && mov.get_to() == to && mov.get_flags().promotion == promotion
+        })
+    }
+}
+
+/// A Polyglot `.bin` opening book, with its entries sorted by key
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn open(path: &str) -> Result<Book, &'static str> {
+        let bytes = std::fs::read(path).map_err(|_| "can't read book file")?;
+        Book::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, &'static str> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err("invalid book file size");
+        }
+
+        let entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| BookEntry::from_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        if !entries.is_sorted_by_key(|entry| entry.key) {
+            return Err("book entries aren't sorted by key");
+        }
+
+        Ok(Book { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All the entries stored for a position key
+    pub fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    /// Picks a legal book move for the current position.
+    ///
+    /// With `best` the highest weighted move is played, otherwise moves are chosen at random
+    /// with a probability proportional to their weight. Zero weight moves are never played.
+    pub fn pick_move(&self, game: &Game, best: bool) -> Option<Move> {
+        let candidates: Vec<(Move, u16)> = self
+            .entries(game.board().polyglot_key())
+            .iter()
+            .filter(|entry| entry.weight > 0)
+            .filter_map(|entry| Some((entry.to_move(game)?, entry.weight)))
+            .collect();
+
+        if best {
+            // keep the first of equally weighted moves, like the order in the file
+            return candidates
+                .iter()
+                .rev()
+                .max_by_key(|&&(_, weight)| weight)
+                .map(|&(mov, _)| mov);
+        }
+
+        let total: u32 = candidates.iter().map(|&(_, weight)| weight as u32).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut pick = rand::rng().random_range(0..total);
+        for (mov, weight) in candidates {
+            if pick < weight as u32 {
+                return Some(mov);
+            }
+            pick -= weight as u32;
+        }
+
+        unreachable!()
+    }
+}
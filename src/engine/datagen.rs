@@ -0,0 +1,347 @@
+//! Self-play games for training data. Each game starts from a few random moves (after an
+//! opening position, if any), every move is a search of a fixed number of nodes, and games
+//! are adjudicated once the result is clear.
+//!
+//! Positions in check or where the best move is a capture or a promotion are left out, so
+//! what's kept is quiet enough for the static evaluation.
+//!
+//! Text format, one position per line: `<fen> | <score> | <result>`, with the score in
+//! centipawns and the result (`1.0`, `0.5` or `0.0`) both from white's point of view. The
+//! tuner reads it as is.
+//!
+//! Binary format, 32 bytes per position, little endian:
+//! - 0..8: occupancy, bit 0 being a1
+//! - 8..24: a nibble per occupied square in occupancy order (low nibble first), the piece type
+//!   (pawn 0 to king 5) with the color in the high bit (set for black)
+//! - 24: side to move in the high bit (set for black), en passant square below (64 if none)
+//! - 25: halfmove clock
+//! - 26..28: score, white's point of view
+//! - 28: result, 0 black wins, 1 draw, 2 white wins
+//! - 29: castling rights in the low 4 bits, white kingside, white queenside, black kingside
+//!   and black queenside from bit 0, with the rooks on their standard squares
+//! - 30..32: zero
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    chess::*,
+    engine::{
+        evaluation::{Classical, Evaluator, EvaluatorKind, Material},
+        nnue::Nnue,
+        ordering::HistoryHeuristics,
+        search::{self, AtomicSearchMode, SearchMode, Searcher, TimeControl},
+        syzygy::Tablebases,
+        transposition::TT,
+    },
+};
+
+// games whose first search is this lopsided are thrown away, the random moves ruined them
+const MAX_OPENING_SCORE: i16 = 1000;
+// both sides agree on a win for this many plies in a row
+const WIN_SCORE: i16 = 1000;
+const WIN_PLIES: usize = 4;
+// a draw needs a long enough game and a score stuck near zero
+const DRAW_SCORE: i16 = 10;
+const DRAW_PLIES: usize = 10;
+const DRAW_MIN_PLY: usize = 80;
+const MAX_PLIES: usize = 400;
+
+pub const SAMPLE_SIZE: usize = 32;
+
+pub struct Settings {
+    /// Searched for every move
+    pub nodes: usize,
+    /// Played at random before the search takes over
+    pub random_plies: usize,
+    pub hash_mb: usize,
+    pub evaluator: EvaluatorKind,
+}
+
+/// A position from a finished game
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub board: Board,
+    /// From white's point of view
+    pub score: i16,
+    /// 1 for a white win, 0.5 for a draw and 0 for a black win
+    pub result: f32,
+}
+
+impl Sample {
+    pub fn to_text(&self) -> String {
+        format!(
+            "{} | {} | {:.1}",
+            self.board.to_fen(),
+            self.score,
+            self.result
+        )
+    }
+
+    pub fn to_bytes(&self) -> [u8; SAMPLE_SIZE] {
+        let mut bytes = [0; SAMPLE_SIZE];
+        let occupancy = self.board.occupancies[Color::White as usize]
+            | self.board.occupancies[Color::Black as usize];
+        bytes[0..8].copy_from_slice(&occupancy.to_le_bytes());
+
+        for (i, square) in occupancy.ones_iter().enumerate() {
+            let (piece_type, color) = self.board.pieces[square as usize];
+            let nibble = piece_type as u8 | (color as u8) << 3;
+            bytes[8 + i / 2] |= nibble << (4 * (i % 2));
+        }
+
+        bytes[24] = (self.board.side_to_move as u8) << 7
+            | self.board.en_passant_square.unwrap_or(BOARD_SIZE as Square);
+        bytes[25] = self.board.halfmove_clock;
+        bytes[26..28].copy_from_slice(&self.score.to_le_bytes());
+        bytes[28] = (self.result * 2.0) as u8;
+        bytes[29] = self.board.castling_rights;
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; SAMPLE_SIZE]) -> Result<Sample, &'static str> {
+        let occupancy = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        if occupancy.count_ones() > 32 {
+            return Err("too many pieces");
+        }
+
+        let mut board = Board::new("8/8/8/8/8/8/8/8 w - - 0 1")?;
+        for (i, square) in occupancy.ones_iter().enumerate() {
+            let nibble = (bytes[8 + i / 2] >> (4 * (i % 2))) & 0xf;
+            let piece_type = *PIECE_TYPES
+                .get((nibble & 0x7) as usize)
+                .ok_or("invalid piece")?;
+            let color = match nibble >> 3 {
+                0 => Color::White,
+                _ => Color::Black,
+            };
+            board.toggle_piece(square, piece_type, color);
+        }
+
+        board.side_to_move = match bytes[24] >> 7 {
+            0 => Color::White,
+            _ => Color::Black,
+        };
+        let en_passant = bytes[24] & 0x7f;
+        board.en_passant_square = (en_passant < BOARD_SIZE as u8).then_some(en_passant);
+        board.halfmove_clock = bytes[25];
+        if bytes[29] > 0xf {
+            return Err("invalid castling rights");
+        }
+        board.castling_rights = bytes[29];
+
+        let result = match bytes[28] {
+            0 => 0.0,
+            1 => 0.5,
+            2 => 1.0,
+            _ => return Err("invalid result"),
+        };
+
+        Ok(Sample {
+            // through a FEN so the keys match the position
+            board: Board::new(&board.to_fen())?,
+            score: i16::from_le_bytes([bytes[26], bytes[27]]),
+            result,
+        })
+    }
+}
+
+/// What the searches of a game share
+struct Engine {
+    search_mode: Arc<AtomicSearchMode>,
+    history_heuristic: Arc<HistoryHeuristics>,
+    tt: Arc<TT>,
+    tablebases: Arc<Tablebases>,
+}
+
+impl Engine {
+    /// The best move and its score from the point of view of the side to move
+    fn search<E: Evaluator>(&self, game: &Game, age: u8, nodes: usize) -> (Move, i16) {
+        let mut searcher = Searcher::<E>::new(
+            game,
+            &self.search_mode,
+            &self.history_heuristic,
+            age,
+            &self.tt,
+            &self.tablebases,
+        );
+        searcher.set_silent(true);
+        let (mov, _) = searcher.start_search(TimeControl::Nodes(nodes));
+        (mov, searcher.score())
+    }
+}
+
+/// Plays one game, the same one for the same seed. Nothing comes out of openings that are
+/// invalid, over after the random moves or too lopsided. `tt` is cleared first, so a worker
+/// can reuse one for all its games instead of allocating a table for each
+pub fn play_game(
+    seed: u64,
+    openings: &[String],
+    settings: &Settings,
+    tt: &mut Arc<TT>,
+) -> Vec<Sample> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let fen = match openings.len() {
+        0 => STARTPOS_FEN,
+        len => &openings[rng.random_range(0..len)],
+    };
+    let Ok(mut game) = Game::new(fen) else {
+        return Vec::new();
+    };
+
+    for _ in 0..settings.random_plies {
+        let moves = game.legal_moves();
+        if moves.is_empty() {
+            return Vec::new();
+        }
+        game.make_move(moves[rng.random_range(0..moves.len())]);
+    }
+
+    Arc::get_mut(tt)
+        .expect("no search is using the table")
+        .clear();
+    let engine = Engine {
+        search_mode: Arc::new(AtomicSearchMode::new(SearchMode::Normal)),
+        history_heuristic: Arc::new(HistoryHeuristics::new()),
+        tt: Arc::clone(tt),
+        tablebases: Arc::new(Tablebases::default()),
+    };
+
+    let mut samples: Vec<Sample> = Vec::new();
+    let (mut win_plies, mut draw_plies) = (0, 0);
+    let mut white_winning = false;
+    let mut age: u8 = 1;
+
+    let result = loop {
+        if let Some(outcome) = game.outcome() {
+            break match outcome.winner() {
+                Some(Color::White) => 1.0,
+                Some(Color::Black) => 0.0,
+                None => 0.5,
+            };
+        }
+        let ply = game.moves().len();
+        if ply >= settings.random_plies + MAX_PLIES {
+            break 0.5;
+        }
+
+        let (mov, score) = match settings.evaluator {
+            EvaluatorKind::Classical => engine.search::<Classical>(&game, age, settings.nodes),
+            EvaluatorKind::Material => engine.search::<Material>(&game, age, settings.nodes),
+            EvaluatorKind::Nnue => engine.search::<Nnue>(&game, age, settings.nodes),
+        };
+        age = age.wrapping_add(1);
+
+        let board = game.board();
+        let score = match board.side_to_move {
+            Color::White => score,
+            Color::Black => -score,
+        };
+
+        if ply == settings.random_plies && score.abs() > MAX_OPENING_SCORE {
+            return Vec::new();
+        }
+
+        let flags = mov.get_flags();
+        let quiet = !matches!(
+            flags.move_type,
+            MoveType::Capture | MoveType::EnPassantCapture
+        ) && flags.promotion == Piece::None;
+        if quiet
+            && !is_king_attcked(board.side_to_move, board)
+            && score.abs() < search::CHECKMATE_THRESHOLD
+        {
+            samples.push(Sample {
+                board: board.clone(),
+                score,
+                result: 0.5,
+            });
+        }
+
+        // the winning side has to stay the same for the streak to count
+        win_plies = if score.abs() < WIN_SCORE {
+            0
+        } else if win_plies > 0 && (score > 0) != white_winning {
+            1
+        } else {
+            win_plies + 1
+        };
+        white_winning = score > 0;
+        if win_plies >= WIN_PLIES {
+            break if white_winning { 1.0 } else { 0.0 };
+        }
+
+        draw_plies = match ply >= DRAW_MIN_PLY && score.abs() <= DRAW_SCORE {
+            true => draw_plies + 1,
+            false => 0,
+        };
+        if draw_plies >= DRAW_PLIES {
+            break 0.5;
+        }
+
+        game.make_move(mov);
+    };
+
+    for sample in &mut samples {
+        sample.result = result;
+    }
+    samples
+}
+
+/// Plays `games` games over `threads` threads, handing the samples of each game to `write` in
+/// the order the games were started. Game `i` is seeded with `seed + i`, so the output only
+/// depends on the seed and the settings
+pub fn generate(
+    games: u64,
+    threads: usize,
+    seed: u64,
+    openings: &[String],
+    settings: &Settings,
+    mut write: impl FnMut(u64, Vec<Sample>),
+) {
+    let next_game = AtomicU64::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            let sender = sender.clone();
+            let next_game = &next_game;
+            scope.spawn(move || {
+                let mut tt = Arc::new(TT::new(settings.hash_mb));
+                loop {
+                    let game = next_game.fetch_add(1, Ordering::Relaxed);
+                    if game >= games {
+                        break;
+                    }
+                    let samples = play_game(seed.wrapping_add(game), openings, settings, &mut tt);
+                    if sender.send((game, samples)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // games finish out of order, the early ones wait here for the ones before them
+        let mut pending = BTreeMap::new();
+        let mut next_write = 0;
+        for (game, samples) in receiver {
+            pending.insert(game, samples);
+            while let Some(samples) = pending.remove(&next_write) {
+                write(next_write, samples);
+                next_write += 1;
+            }
+        }
+    });
+}
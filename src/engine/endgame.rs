@@ -0,0 +1,417 @@
+//! Knowledge of endgames the general evaluation gets wrong: the KPK bitbase, mating patterns
+//! against a bare king, a few known draws and the scaling of drawish endings.
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use crate::chess::{attacks::tables, *};
+
+/// Score of a won ending, below mate scores but above anything the material could be worth
+pub const KNOWN_WIN: i16 = 10_000;
+/// Scale factor that leaves the evaluation as it is
+pub const SCALE_NORMAL: i16 = 64;
+
+#[derive(Clone, Copy)]
+pub enum Endgame {
+    /// Replaces the evaluation, from the point of view of the strong side
+    Value(fn(&Board, Color) -> i16),
+    /// Scales the evaluation down when it favors the strong side, from 0 (draw) to
+    /// `SCALE_NORMAL`
+    Scale(fn(&Board, Color) -> i16),
+}
+
+/// Material key of the pieces in a name like `KBNvK`
+fn material_key_from_name(name: &str, strong: Color) -> u64 {
+    let (strong_pieces, weak_pieces) = name.split_once('v').unwrap();
+
+    let mut key = 0;
+    for (pieces, color) in [(strong_pieces, strong), (weak_pieces, strong.toggle())] {
+        for chr in pieces.chars() {
+            key += material_key_unit(Piece::from_char(chr).unwrap(), color);
+        }
+    }
+
+    key
+}
+
+/// Endgames recognized by their exact material, with the strong side
+static ENDGAMES: LazyLock<HashMap<u64, (Endgame, Color)>> = LazyLock::new(|| {
+    let endgames = [
+        ("KPvK", Endgame::Value(kpk)),
+        ("KBNvK", Endgame::Value(kbnk)),
+        ("KNNvK", Endgame::Value(|_, _| 0)),
+    ];
+
+    let mut map = HashMap::new();
+    for (name, endgame) in endgames {
+        for strong in [Color::White, Color::Black] {
+            map.insert(material_key_from_name(name, strong), (endgame, strong));
+        }
+    }
+
+    map
+});
+
+/// Finds the specialized evaluation of the position and its strong side, if there's one.
+///
+/// Only the material is looked at, so the result can be cached by material key
+pub fn find(board: &Board) -> Option<(Endgame, Color)> {
+    // every endgame known is against a bare king
+    let strong = match board.occupancies.map(u64::count_ones) {
+        [_, 1] => Color::White,
+        [1, _] => Color::Black,
+        _ => return None,
+    };
+
+    if let Some(&endgame) = ENDGAMES.get(&board.material_key) {
+        return Some(endgame);
+    }
+
+    let pieces = &board.bitboards[strong as usize];
+    let non_pawn_material = non_pawn_material(board, strong);
+
+    if non_pawn_material >= Board::PIECE_VALUES[Piece::Rook as usize] {
+        Some((Endgame::Value(kxk), strong))
+    } else if pieces[Piece::Pawn as usize] != 0
+        && (non_pawn_material == 0
+            || (non_pawn_material == Board::PIECE_VALUES[Piece::Bishop as usize]
+                && pieces[Piece::Bishop as usize] != 0))
+    {
+        Some((Endgame::Scale(rook_pawns), strong))
+    } else {
+        None
+    }
+}
+
+/// Scale factor of the general evaluation when it favors `strong`, for the drawish endings no
+/// specialized evaluation covers: a small edge without pawns and opposite colored bishops
+pub fn scale_factor(board: &Board, strong: Color) -> i16 {
+    const BISHOP: i16 = Board::PIECE_VALUES[Piece::Bishop as usize];
+    const ROOK: i16 = Board::PIECE_VALUES[Piece::Rook as usize];
+
+    let weak = strong.toggle();
+    let [strong_material, weak_material] =
+        [strong, weak].map(|color| non_pawn_material(board, color));
+    let [strong_pawns, weak_pawns] = [strong, weak]
+        .map(|color| board.bitboards[color as usize][Piece::Pawn as usize].count_ones());
+
+    // without pawns a minor piece more doesn't win, and a lone minor piece can't win at all
+    if strong_pawns == 0 && strong_material - weak_material <= BISHOP {
+        return if strong_material < ROOK {
+            0
+        } else if weak_material <= BISHOP {
+            4
+        } else {
+            14
+        };
+    }
+
+    let bishops =
+        [strong, weak].map(|color| board.bitboards[color as usize][Piece::Bishop as usize]);
+    let opposite_bishops = bishops.iter().all(|bishops| bishops.count_ones() == 1)
+        && ((bishops[0] | bishops[1]) & LIGHT_SQUARES).count_ones() == 1;
+    if opposite_bishops {
+        // with nothing else on the board the weak side blockades the pawns on the other color
+        if strong_material == BISHOP && weak_material == BISHOP {
+            return if strong_pawns <= weak_pawns + 1 {
+                16
+            } else {
+                32
+            };
+        }
+        return 46;
+    }
+
+    SCALE_NORMAL
+}
+
+fn non_pawn_material(board: &Board, color: Color) -> i16 {
+    [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]
+        .iter()
+        .map(|&piece_type| {
+            board.bitboards[color as usize][piece_type as usize].count_ones() as i16
+                * Board::PIECE_VALUES[piece_type as usize]
+        })
+        .sum()
+}
+
+fn king_square(board: &Board, color: Color) -> Square {
+    board.bitboards[color as usize][Piece::King as usize].trailing_zeros() as Square
+}
+
+fn rank_of(square: Square) -> i16 {
+    (square / BOARD_WIDTH as Square) as i16
+}
+
+fn file_of(square: Square) -> i16 {
+    (square % BOARD_WIDTH as Square) as i16
+}
+
+fn distance(a: Square, b: Square) -> i16 {
+    (rank_of(a) - rank_of(b))
+        .abs()
+        .max((file_of(a) - file_of(b)).abs())
+}
+
+fn edge_distance(coordinate: i16) -> i16 {
+    coordinate.min(7 - coordinate)
+}
+
+/// Bonus for the weak king being near the edge of the board
+fn push_to_edge(square: Square) -> i16 {
+    let rank = edge_distance(rank_of(square));
+    let file = edge_distance(file_of(square));
+    90 - (7 * file * file / 2 + 7 * rank * rank / 2)
+}
+
+/// Bonus for the a1 and h8 corners, 0 on the a8-h1 diagonal
+fn push_to_corner(square: Square) -> i16 {
+    (7 - rank_of(square) - file_of(square)).abs()
+}
+
+/// Bonus for the kings being close
+fn push_close(a: Square, b: Square) -> i16 {
+    140 - 20 * distance(a, b)
+}
+
+/// Enough material to mate a bare king: drive it to the edge and bring the king closer
+fn kxk(board: &Board, strong: Color) -> i16 {
+    let pieces = &board.bitboards[strong as usize];
+    let strong_king = king_square(board, strong);
+    let weak_king = king_square(board, strong.toggle());
+
+    let material: i16 = board.occupancies[strong as usize]
+        .ones_iter()
+        .map(|square| Board::PIECE_VALUES[board.pieces[square as usize].0 as usize])
+        .sum();
+    let mut value = material - Board::PIECE_VALUES[Piece::King as usize]
+        + push_to_edge(weak_king)
+        + push_close(strong_king, weak_king);
+
+    let bishops = pieces[Piece::Bishop as usize];
+    if pieces[Piece::Queen as usize] != 0
+        || pieces[Piece::Rook as usize] != 0
+        || (bishops != 0 && pieces[Piece::Knight as usize] != 0)
+        || (bishops & LIGHT_SQUARES != 0 && bishops & DARK_SQUARES != 0)
+    {
+        value += KNOWN_WIN;
+    }
+
+    value
+}
+
+/// Bishop and knight mate, only possible in the corners of the bishop's color
+fn kbnk(board: &Board, strong: Color) -> i16 {
+    let strong_king = king_square(board, strong);
+    let weak_king = king_square(board, strong.toggle());
+    let bishop = board.bitboards[strong as usize][Piece::Bishop as usize];
+
+    // push_to_corner goes for a1 and h8, so the board is mirrored for light squared bishops
+    let corner_king = if bishop & DARK_SQUARES != 0 {
+        weak_king
+    } else {
+        weak_king ^ 7
+    };
+
+    KNOWN_WIN + 3520 + push_close(strong_king, weak_king) + 420 * push_to_corner(corner_king)
+}
+
+fn kpk(board: &Board, strong: Color) -> i16 {
+    // seen as white, with the pawn on the a-d files
+    let pawn = board.bitboards[strong as usize][Piece::Pawn as usize].trailing_zeros() as Square;
+    let flip =
+        (if strong == Color::Black { 56 } else { 0 }) ^ (if file_of(pawn) > 3 { 7 } else { 0 });
+
+    let pawn = pawn ^ flip;
+    let strong_king = king_square(board, strong) ^ flip;
+    let weak_king = king_square(board, strong.toggle()) ^ flip;
+
+    if !probe_kpk(strong_king, pawn, weak_king, board.side_to_move == strong) {
+        return 0;
+    }
+
+    KNOWN_WIN + Board::PIECE_VALUES[Piece::Pawn as usize] + rank_of(pawn)
+}
+
+/// Rook pawns (with or without a bishop that doesn't control the promotion square) can't win
+/// if the defending king gets in front of them
+fn rook_pawns(board: &Board, strong: Color) -> i16 {
+    let pieces = &board.bitboards[strong as usize];
+    let pawns = pieces[Piece::Pawn as usize];
+    let weak_king = king_square(board, strong.toggle());
+
+    if pawns & !FILES[0] != 0 && pawns & !FILES[7] != 0 {
+        return SCALE_NORMAL;
+    }
+
+    let file = file_of(pawns.trailing_zeros() as Square);
+    let queening_square = match strong {
+        Color::White => to_square(7, file as i8),
+        Color::Black => to_square(0, file as i8),
+    };
+
+    let bishops = pieces[Piece::Bishop as usize];
+    let queening_color = if bit(queening_square) & LIGHT_SQUARES != 0 {
+        LIGHT_SQUARES
+    } else {
+        DARK_SQUARES
+    };
+    if bishops & queening_color != 0 {
+        return SCALE_NORMAL;
+    }
+
+    if distance(queening_square, weak_king) <= 1 {
+        0
+    } else {
+        SCALE_NORMAL
+    }
+}
+
+// 24 pawn squares (a-d files, ranks 2 to 7), 64 squares for each king and the side to move
+const KPK_SIZE: usize = 2 * 24 * BOARD_SIZE * BOARD_SIZE;
+
+/// Index of a KPK position: white king (6 bits), black king (6), side to move (1), pawn file
+/// (2) and 7th rank minus pawn rank (3)
+fn kpk_index(white_to_move: bool, white_king: Square, pawn: Square, black_king: Square) -> usize {
+    white_king as usize
+        | (black_king as usize) << 6
+        | (!white_to_move as usize) << 12
+        | (file_of(pawn) as usize) << 13
+        | ((6 - rank_of(pawn)) as usize) << 15
+}
+
+/// Tells if white wins a KPK position, with the pawn on the a-d files
+pub fn probe_kpk(
+    white_king: Square,
+    pawn: Square,
+    black_king: Square,
+    white_to_move: bool,
+) -> bool {
+    debug_assert!(file_of(pawn) <= 3);
+    let idx = kpk_index(white_to_move, white_king, pawn, black_king);
+    KPK_BITBASE[idx / 64] & (1 << (idx % 64)) != 0
+}
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+/// Every KPK position where white wins, found by retrograde analysis
+static KPK_BITBASE: LazyLock<Vec<u64>> = LazyLock::new(|| {
+    let positions: Vec<_> = (0..KPK_SIZE).map(kpk_position).collect();
+    let mut results: Vec<u8> = positions.iter().map(|&(.., result)| result).collect();
+
+    // classify the unknown positions with the ones around them until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (idx, &(white_to_move, white_king, pawn, black_king, _)) in positions.iter().enumerate()
+        {
+            if results[idx] != UNKNOWN {
+                continue;
+            }
+
+            let result = kpk_classify(&results, white_to_move, white_king, pawn, black_king);
+            if result != UNKNOWN {
+                results[idx] = result;
+                changed = true;
+            }
+        }
+    }
+
+    let mut bitbase = vec![0u64; KPK_SIZE / 64];
+    for (idx, _) in results
+        .iter()
+        .enumerate()
+        .filter(|&(_, &result)| result == WIN)
+    {
+        bitbase[idx / 64] |= 1 << (idx % 64);
+    }
+
+    bitbase
+});
+
+/// Decodes a KPK index and classifies the positions decided right away
+fn kpk_position(idx: usize) -> (bool, Square, Square, Square, u8) {
+    let white_king = (idx & 0x3F) as Square;
+    let black_king = (idx >> 6 & 0x3F) as Square;
+    let white_to_move = idx >> 12 & 1 == 0;
+    let pawn = to_square(6 - (idx >> 15 & 0x7) as i8, (idx >> 13 & 0x3) as i8);
+    let push = pawn + BOARD_WIDTH as Square;
+
+    let white_king_attacks = tables::KING_ATTACKS[white_king as usize];
+    let black_king_attacks = tables::KING_ATTACKS[black_king as usize];
+    let pawn_attacks = tables::WPAWN_ATTACKS[pawn as usize];
+
+    let result = if distance(white_king, black_king) <= 1
+        || white_king == pawn
+        || black_king == pawn
+        || (white_to_move && pawn_attacks & bit(black_king) != 0)
+    {
+        INVALID
+    } else if white_to_move
+        && rank_of(pawn) == 6
+        && white_king != push
+        && (distance(black_king, push) > 1 || distance(white_king, push) == 1)
+    {
+        WIN // promotes without being captured
+    } else if !white_to_move
+        && (black_king_attacks & !(white_king_attacks | pawn_attacks) == 0
+            || black_king_attacks & !white_king_attacks & bit(pawn) != 0)
+    {
+        DRAW // stalemate, or the pawn is captured
+    } else {
+        UNKNOWN
+    };
+
+    (white_to_move, white_king, pawn, black_king, result)
+}
+
+/// White wins if a move wins and black draws if a move draws, otherwise it's decided once
+/// every move is
+fn kpk_classify(
+    results: &[u8],
+    white_to_move: bool,
+    white_king: Square,
+    pawn: Square,
+    black_king: Square,
+) -> u8 {
+    let (good, bad) = if white_to_move {
+        (WIN, DRAW)
+    } else {
+        (DRAW, WIN)
+    };
+
+    let mut result = INVALID;
+    if white_to_move {
+        for to in tables::KING_ATTACKS[white_king as usize].ones_iter() {
+            result |= results[kpk_index(false, to, pawn, black_king)];
+        }
+
+        let push = pawn + BOARD_WIDTH as Square;
+        if rank_of(pawn) < 6 {
+            result |= results[kpk_index(false, white_king, push, black_king)];
+        }
+        if rank_of(pawn) == 1 && push != white_king && push != black_king {
+            let double_push = push + BOARD_WIDTH as Square;
+            result |= results[kpk_index(false, white_king, double_push, black_king)];
+        }
+    } else {
+        for to in tables::KING_ATTACKS[black_king as usize].ones_iter() {
+            result |= results[kpk_index(true, white_king, pawn, to)];
+        }
+    }
+
+    if result & good != 0 {
+        good
+    } else if result & UNKNOWN != 0 {
+        UNKNOWN
+    } else {
+        bad
+    }
+}
+
+/// Builds the KPK bitbase now instead of on the first KPK position
+pub fn init() {
+    LazyLock::force(&KPK_BITBASE);
+}
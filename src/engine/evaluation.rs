@@ -1,4 +1,17 @@
-use crate::chess::*;
+use crate::{
+    chess::{
+        attacks::{magics, movegen, tables},
+        *,
+    },
+    engine::{
+        endgame::{self, Endgame},
+        material::{self, MaterialEntry, MaterialTable},
+        nnue::{self, Nnue},
+        params::{Param, params},
+        pawns::{self, PawnEntry, PawnTable},
+        trace::{Term, Trace},
+    },
+};
 use std::ops::{AddAssign, SubAssign};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -24,119 +37,620 @@ impl Board {
         + Board::PHASE_VALUE[Piece::Bishop as usize] * 4
         + Board::PHASE_VALUE[Piece::Rook as usize] * 4
         + Board::PHASE_VALUE[Piece::Queen as usize] * 2;
-    const PHASE_SCALE: usize = 256;
-
-    // stolen from PeSTO
-#[rustfmt::skip]
-    pub const PST: [[W; BOARD_SIZE]; PIECE_TYPES.len()] = [
-        // pawn
-        [
-            W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0),
-            W(98, 178), W(134, 173), W(61, 158), W(95, 134), W(68, 147), W(126, 132), W(34, 165), W(-11, 187),
-            W(-6, 94), W(7, 100), W(26, 85), W(31, 67), W(65, 56), W(56, 53), W(25, 82), W(-20, 84),
-            W(-14, 32), W(13, 24), W(6, 13), W(21, 5), W(23, -2), W(12, 4), W(17, 17), W(-23, 17),
-            W(-27, 13), W(-2, 9), W(-5, -3), W(12, -7), W(17, -7), W(6, -8), W(10, 3), W(-25, -1),
-            W(-26, 4), W(-4, 7), W(-4, -6), W(-10, 1), W(3, 0), W(3, -5), W(33, -1), W(-12, -8),
-            W(-35, 13), W(-1, 8), W(-20, 8), W(-23, 10), W(-15, 13), W(24, 0), W(38, 2), W(-22, -7),
-            W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0),
-        ],
-        // knight
-        [
-            W(-167, -58), W(-89, -38), W(-34, -13), W(-49, -28), W(61, -31), W(-97, -27), W(-15, -63), W(-107, -99),
-            W(-73, -25), W(-41, -8), W(72, -25), W(36, -2), W(23, -9), W(62, -25), W(7, -24), W(-17, -52),
-            W(-47, -24), W(60, -20), W(37, 10), W(65, 9), W(84, -1), W(129, -9), W(73, -19), W(44, -41),
-            W(-9, -17), W(17, 3), W(19, 22), W(53, 22), W(37, 22), W(69, 11), W(18, 8), W(22, -18),
-            W(-13, -18), W(4, -6), W(16, 16), W(13, 25), W(28, 16), W(19, 17), W(21, 4), W(-8, -18),
-            W(-23, -23), W(-9, -3), W(12, -1), W(10, 15), W(19, 10), W(17, -3), W(25, -20), W(-16, -22),
-            W(-29, -42), W(-53, -20), W(-12, -10), W(-3, -5), W(-1, -2), W(18, -20), W(-14, -23), W(-19, -44),
-            W(-105, -29), W(-21, -51), W(-58, -23), W(-33, -15), W(-17, -22), W(-28, -18), W(-19, -50), W(-23, -64),
-        ],
-        // bishop
-        [
-            W(-29, -14), W(4, -21), W(-82, -11), W(-37, -8), W(-25, -7), W(-42, -9), W(7, -17), W(-8, -24),
-            W(-26, -8), W(16, -4), W(-18, 7), W(-13, -12), W(30, -3), W(59, -13), W(18, -4), W(-47, -14),
-            W(-16, 2), W(37, -8), W(43, 0), W(40, -1), W(35, -2), W(50, 6), W(37, 0), W(-2, 4),
-            W(-4, -3), W(5, 9), W(19, 12), W(50, 9), W(37, 14), W(37, 10), W(7, 3), W(-2, 2),
-            W(-6, -6), W(13, 3), W(13, 13), W(26, 19), W(34, 7), W(12, 10), W(10, -3), W(4, -9),
-            W(0, -12), W(15, -3), W(15, 8), W(15, 10), W(14, 13), W(27, 3), W(18, -7), W(10, -15),
-            W(4, -14), W(15, -18), W(16, -7), W(0, -1), W(7, 4), W(21, -9), W(33, -15), W(1, -27),
-            W(-33, -23), W(-3, -9), W(-14, -23), W(-21, -5), W(-13, -9), W(-12, -16), W(-39, -5), W(-21, -17),
-        ],
-        // rook
-        [
-            W(32, 13), W(42, 10), W(32, 18), W(51, 15), W(63, 12), W(9, 12), W(31, 8), W(43, 5),
-            W(27, 11), W(32, 13), W(58, 13), W(62, 11), W(80, -3), W(67, 3), W(26, 8), W(44, 3),
-            W(-5, 7), W(19, 7), W(26, 7), W(36, 5), W(17, 4), W(45, -3), W(61, -5), W(16, -3),
-            W(-24, 4), W(-11, 3), W(7, 13), W(26, 1), W(24, 2), W(35, 1), W(-8, -1), W(-20, 2),
-            W(-36, 3), W(-26, 5), W(-12, 8), W(-1, 4), W(9, -5), W(-7, -6), W(6, -8), W(-23, -11),
-            W(-45, -4), W(-25, 0), W(-16, -5), W(-17, -1), W(3, -7), W(0, -12), W(-5, -8), W(-33, -16),
-            W(-44, -6), W(-16, -6), W(-20, 0), W(-9, 2), W(-1, -9), W(11, -9), W(-6, -11), W(-71, -3),
-            W(-19, -9), W(-13, 2), W(1, 3), W(17, -1), W(16, -5), W(7, -13), W(-37, 4), W(-26, -20),
-        ],
-        // queen
-        [
-            W(-28, -9), W(0, 22), W(29, 22), W(12, 27), W(59, 27), W(44, 19), W(43, 10), W(45, 20),
-            W(-24, -17), W(-39, 20), W(-5, 32), W(1, 41), W(-16, 58), W(57, 25), W(28, 30), W(54, 0),
-            W(-13, -20), W(-17, 6), W(7, 9), W(8, 49), W(29, 47), W(56, 35), W(47, 19), W(57, 9),
-            W(-27, 3), W(-27, 22), W(-16, 24), W(-16, 45), W(-1, 57), W(17, 40), W(-2, 57), W(1, 36),
-            W(-9, -18), W(-26, 28), W(-9, 19), W(-10, 47), W(-2, 31), W(-4, 34), W(3, 39), W(-3, 23),
-            W(-14, -16), W(2, -27), W(-11, 15), W(-2, 6), W(-5, 9), W(2, 17), W(14, 10), W(5, 5),
-            W(-35, -22), W(-8, -23), W(11, -30), W(2, -16), W(8, -16), W(15, -23), W(-3, -36), W(1, -32),
-            W(-1, -33), W(-18, -28), W(-9, -22), W(10, -43), W(-15, -5), W(-25, -32), W(-31, -20), W(-50, -41),
-        ],
-        // king
-        [
-            W(-65, -74), W(23, -35), W(16, -18), W(-15, -18), W(-56, -11), W(-34, 15), W(2, 4), W(13, -17),
-            W(29, -12), W(-1, 17), W(-20, 14), W(-7, 17), W(-8, 17), W(-4, 38), W(-38, 23), W(-29, 11),
-            W(-9, 10), W(24, 17), W(2, 23), W(-16, 15), W(-20, 20), W(6, 45), W(22, 44), W(-22, 13),
-            W(-17, -8), W(-20, 22), W(-12, 24), W(-27, 27), W(-30, 26), W(-25, 33), W(-14, 26), W(-36, 3),
-            W(-49, -18), W(-1, -4), W(-27, 21), W(-39, 24), W(-46, 27), W(-44, 23), W(-33, 9), W(-51, -11),
-            W(-14, -19), W(-14, -3), W(-22, 11), W(-46, 21), W(-44, 23), W(-30, 16), W(-15, 7), W(-27, -9),
-            W(1, -27), W(7, -11), W(-8, 4), W(-64, 13), W(-43, 14), W(-16, 4), W(9, -5), W(8, -17),
-            W(-15, -53), W(36, -34), W(12, -21), W(-54, -11), W(8, -28), W(-28, -14), W(24, -24), W(14, -43),
-        ],
-    ];
+    pub(crate) const PHASE_SCALE: usize = 256;
+    // mating is possible but can't be forced, so an edge in material is worth very little
+    pub(crate) const DRAWISH_DIVISOR: i16 = 8;
+    // the evaluation fades linearly over the last plies before the 50-move rule
+    pub(crate) const FIFTY_MOVE_DAMPING: i32 = 80;
 
-    fn calculate_bonus(&self) -> [W; 2] {
+    /// Piece-square table sums of each side, from scratch
+    pub(crate) fn pst_bonus(&self) -> [W; 2] {
         [Color::White, Color::Black].map(|color| {
             self.occupancies[color as usize]
                 .ones_iter()
-                .filter_map(|square| {
-                    let (piece_type, piece_color) = self.pieces[square as usize];
-                    (piece_color == color && piece_type != Piece::None).then_some(
-                        Board::PST[piece_type as usize][match color {
-                            // mirror for whites because:
-                            // table index    -> 0=a8 63=h1
-                            // engine square  -> 0=a1 63=h8
-                            Color::White => square as usize ^ 56, // ^ 56 mirrors vertically
-                            Color::Black => square as usize,
-                        }],
-                    )
+                .map(|square| {
+                    let (piece_type, _) = self.pieces[square as usize];
+                    pst(piece_type, color, square)
                 })
                 .fold(W(0, 0), |acc, curr| W(acc.0 + curr.0, acc.1 + curr.1))
         })
     }
 
-    /// from whites perspective in centipawns
+    fn phase(&self) -> usize {
+        PIECE_TYPES
+            .iter()
+            .map(|&piece_type| {
+                let pieces = self.bitboards[Color::White as usize][piece_type as usize]
+                    | self.bitboards[Color::Black as usize][piece_type as usize];
+                pieces.count_ones() as usize * Board::PHASE_VALUE[piece_type as usize]
+            })
+            .sum()
+    }
+
+    pub(crate) fn phase_ratio(&self) -> i32 {
+        ((self.phase() * Board::PHASE_SCALE + (Board::TOTAL_PHASE / 2)) / Board::TOTAL_PHASE) as i32
+    }
+
+    fn calculate_material_key(&self) -> u64 {
+        PIECE_TYPES
+            .iter()
+            .flat_map(|&piece_type| [(piece_type, Color::White), (piece_type, Color::Black)])
+            .map(|(piece_type, color)| {
+                self.bitboards[color as usize][piece_type as usize].count_ones() as u64
+                    * material_key_unit(piece_type, color)
+            })
+            .sum()
+    }
+
+    /// from whites perspective in centipawns, computed from scratch. The search uses a
+    /// `Classical` evaluator instead, which keeps the piece-square sums and caches the rest
     pub fn evaluate(&self) -> i16 {
-        debug_assert_eq!(self.bonus, self.calculate_bonus(), "bonus mismatch");
+        self.evaluate_with(&MaterialEntry::new(self), &PawnEntry::new(self))
+    }
+
+    /// Same as `evaluate`, with the material and pawn structure terms coming from a
+    /// `MaterialTable` and a `PawnTable`
+    pub fn evaluate_with(&self, material: &MaterialEntry, pawns: &PawnEntry) -> i16 {
+        self.evaluate_terms(material, pawns, EvalTerms::ALL)
+    }
+
+    /// Same as `evaluate_with`, with only the `EvalTerms` in `terms` switched on
+    pub fn evaluate_terms(&self, material: &MaterialEntry, pawns: &PawnEntry, terms: u16) -> i16 {
+        self.evaluate_traced(material, pawns, &self.pst_bonus(), terms, &mut ())
+    }
+
+    /// The evaluation, reporting every term to `trace` on the way. The normal path traces to
+    /// `()`, which compiles to nothing. `pst` holds the piece-square table sums of each side
+    pub(crate) fn evaluate_traced<T: Trace>(
+        &self,
+        material: &MaterialEntry,
+        pawns: &PawnEntry,
+        pst: &[W; 2],
+        terms: u16,
+        trace: &mut T,
+    ) -> i16 {
+        debug_assert_eq!(*pst, self.pst_bonus(), "piece-square sums mismatch");
+        debug_assert_eq!(
+            self.material_key,
+            self.calculate_material_key(),
+            "material key mismatch"
+        );
+        debug_assert_eq!(material.key(), self.material_key, "wrong material entry");
+        debug_assert_eq!(pawns.key(), self.pawn_key, "wrong pawn entry");
+
+        if let Some((Endgame::Value(value), strong)) = material.endgame {
+            let score = match strong {
+                Color::White => value(self, strong),
+                Color::Black => -value(self, strong),
+            };
+            return self.fifty_move_damped(score);
+        }
 
-        let material_score =
-            self.material[Color::White as usize] - self.material[Color::Black as usize];
+        let phase_ratio = material.phase_ratio;
 
-        let phase_ratio = ((self.phase * Board::PHASE_SCALE + (Board::TOTAL_PHASE / 2))
-            / Board::TOTAL_PHASE) as i32;
+        let mut bonus = W(
+            pst[Color::White as usize].0 - pst[Color::Black as usize].0,
+            pst[Color::White as usize].1 - pst[Color::Black as usize].1,
+        );
+        bonus += material.material;
+        bonus += material.imbalance;
+        bonus += pawns.score;
+        if terms & EvalTerms::BISHOP_PAIR != 0 {
+            bonus += material.bishop_pair;
+        }
 
-        let midgame_bonus: i32 =
-            (self.bonus[Color::White as usize].0 - self.bonus[Color::Black as usize].0) as i32;
+        if T::ENABLED {
+            // the cached terms only keep the difference between the sides
+            for color in [Color::White, Color::Black] {
+                trace.add(Term::Material, color, material::material(self, color));
+                trace.add(Term::Pst, color, pst[color as usize]);
+                trace.add(Term::Imbalance, color, material::imbalance(self, color));
+                let (pawn_score, _) = pawns::evaluate_pawns(self, color, trace);
+                trace.add(Term::Pawns, color, pawn_score);
+                if terms & EvalTerms::BISHOP_PAIR != 0 {
+                    let bishop_pair = material::bishop_pair(self, color);
+                    trace.add(Term::BishopPair, color, bishop_pair);
+                    let pair =
+                        self.bitboards[color as usize][Piece::Bishop as usize].count_ones() >= 2;
+                    trace.feature(Param::BishopPair, 0, color, pair as i16);
+                }
 
-        let endgame_bonus =
-            (self.bonus[Color::White as usize].1 - self.bonus[Color::Black as usize].1) as i32;
+                for square in self.occupancies[color as usize].ones_iter() {
+                    let (piece_type, _) = self.pieces[square as usize];
+                    let square_lookup = match color {
+                        Color::White => square as usize ^ 56,
+                        Color::Black => square as usize,
+                    };
+                    trace.feature(Param::PieceValues, piece_type as usize, color, 1);
+                    trace.feature(Param::pst(piece_type), square_lookup, color, 1);
+                }
+            }
+        }
+
+        let attacks = [Color::White, Color::Black].map(|color| Attacks::new(self, color));
+        for color in [Color::White, Color::Black] {
+            let activity = self.activity(color, &attacks, terms, trace);
+            match color {
+                Color::White => bonus += activity,
+                Color::Black => bonus -= activity,
+            }
+        }
+
+        let midgame_bonus = bonus.0 as i32;
+        let endgame_bonus = bonus.1 as i32;
 
         let positional = ((midgame_bonus * phase_ratio)
             + (endgame_bonus * (Board::PHASE_SCALE as i32 - phase_ratio)))
             / Board::PHASE_SCALE as i32;
 
-        (positional as i16) + material_score
+        let score = positional as i16;
+        let strong = if score > 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let score = (score as i32 * self.scale_factor(material, strong) as i32
+            / endgame::SCALE_NORMAL as i32) as i16;
+
+        let mating_material = material
+            .mating_material
+            .unwrap_or_else(|| self.mating_material());
+        let score = if mating_material == MatingMaterial::Drawish {
+            score / Board::DRAWISH_DIVISOR
+        } else {
+            score
+        };
+
+        self.fifty_move_damped(score)
+    }
+
+    /// What the evaluation is multiplied by when it favors `strong`, from 0 (draw) to
+    /// `endgame::SCALE_NORMAL`
+    pub(crate) fn scale_factor(&self, material: &MaterialEntry, strong: Color) -> i16 {
+        match material.endgame {
+            Some((Endgame::Scale(scale), side)) if side == strong => scale(self, strong),
+            _ => endgame::scale_factor(self, strong),
+        }
+    }
+
+    /// Plies left before the evaluation reaches zero, out of `FIFTY_MOVE_DAMPING`. Nothing is
+    /// damped until the 50-move counter gets going, so shuffling in a won-looking but drawn
+    /// ending looks like the draw it is
+    pub(crate) fn fifty_move_left(&self) -> i32 {
+        const LIMIT: i32 = 100;
+        LIMIT - (self.halfmove_clock as i32).clamp(LIMIT - Board::FIFTY_MOVE_DAMPING, LIMIT)
+    }
+
+    fn fifty_move_damped(&self, score: i16) -> i16 {
+        (score as i32 * self.fifty_move_left() / Board::FIFTY_MOVE_DAMPING) as i16
+    }
+}
+
+/// Piece-square table value of a piece
+#[inline]
+fn pst(piece_type: Piece, color: Color, square: Square) -> W {
+    // mirror for whites because:
+    // table index    -> 0=a8 63=h1
+    // engine square  -> 0=a1 63=h8
+    let square_lookup = match color {
+        Color::White => square as usize ^ 56, // ^ 56 mirrors vertically
+        Color::Black => square as usize,
+    };
+    params().pst[piece_type as usize][square_lookup]
+}
+
+/// An evaluation the search can run on. It's told about every piece the moves add and remove,
+/// so it can keep its own incremental state without the board knowing about it
+pub trait Evaluator {
+    /// The state for `board`, computed from scratch
+    fn new(board: &Board) -> Self;
+
+    fn add_piece(&mut self, piece_type: Piece, color: Color, square: Square);
+
+    fn remove_piece(&mut self, piece_type: Piece, color: Color, square: Square);
+
+    /// Called before `board.make_move(mov)`, with the position the move is played from
+    fn make_move(&mut self, board: &Board, mov: Move) {
+        board.piece_changes(mov, |piece_type, color, square, added| match added {
+            true => self.add_piece(piece_type, color, square),
+            false => self.remove_piece(piece_type, color, square),
+        });
+    }
+
+    /// Called after `board.undo_move`, with the position the move was played from
+    fn undo_move(&mut self, board: &Board, mov: Move) {
+        board.piece_changes(mov, |piece_type, color, square, added| match added {
+            true => self.remove_piece(piece_type, color, square),
+            false => self.add_piece(piece_type, color, square),
+        });
+    }
+
+    /// From white's point of view, in centipawns
+    fn evaluate(&mut self, board: &Board) -> i16;
+}
+
+/// Hand-crafted: PeSTO tables, material and pawn structure, piece activity. Keeps the
+/// piece-square sums of each side and caches the material and pawn terms
+pub struct Classical {
+    pst: [W; 2],
+    material_table: MaterialTable,
+    pawn_table: PawnTable,
+}
+
+impl Evaluator for Classical {
+    fn new(board: &Board) -> Classical {
+        Classical {
+            pst: board.pst_bonus(),
+            material_table: MaterialTable::new(),
+            pawn_table: PawnTable::new(),
+        }
+    }
+
+    #[inline]
+    fn add_piece(&mut self, piece_type: Piece, color: Color, square: Square) {
+        self.pst[color as usize] += pst(piece_type, color, square);
+    }
+
+    #[inline]
+    fn remove_piece(&mut self, piece_type: Piece, color: Color, square: Square) {
+        self.pst[color as usize] -= pst(piece_type, color, square);
+    }
+
+    fn evaluate(&mut self, board: &Board) -> i16 {
+        let material = self.material_table.probe(board);
+        let pawns = self.pawn_table.probe(board);
+        board.evaluate_traced(material, pawns, &self.pst, EvalTerms::ALL, &mut ())
+    }
+}
+
+/// Counts the material with the search's piece values and nothing else
+pub struct Material {
+    material: [i16; 2],
+}
+
+impl Material {
+    // kings are always there, and counting them would only get close to overflowing
+    #[inline]
+    fn value(piece_type: Piece) -> i16 {
+        match piece_type {
+            Piece::King => 0,
+            _ => Board::PIECE_VALUES[piece_type as usize],
+        }
+    }
+}
+
+impl Evaluator for Material {
+    fn new(board: &Board) -> Material {
+        Material {
+            material: [Color::White, Color::Black].map(|color| {
+                board.occupancies[color as usize]
+                    .ones_iter()
+                    .map(|square| Material::value(board.pieces[square as usize].0))
+                    .sum()
+            }),
+        }
+    }
+
+    #[inline]
+    fn add_piece(&mut self, piece_type: Piece, color: Color, _: Square) {
+        self.material[color as usize] += Material::value(piece_type);
+    }
+
+    #[inline]
+    fn remove_piece(&mut self, piece_type: Piece, color: Color, _: Square) {
+        self.material[color as usize] -= Material::value(piece_type);
+    }
+
+    fn evaluate(&mut self, _: &Board) -> i16 {
+        self.material[Color::White as usize] - self.material[Color::Black as usize]
+    }
+}
+
+/// Which evaluation the search uses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvaluatorKind {
+    Classical,
+    Material,
+    Nnue,
+}
+
+impl EvaluatorKind {
+    pub const ALL: [EvaluatorKind; 3] = [
+        EvaluatorKind::Classical,
+        EvaluatorKind::Material,
+        EvaluatorKind::Nnue,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EvaluatorKind::Classical => "Classical",
+            EvaluatorKind::Material => "Material",
+            EvaluatorKind::Nnue => "NNUE",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EvaluatorKind> {
+        EvaluatorKind::ALL
+            .into_iter()
+            .find(|evaluator| evaluator.name().eq_ignore_ascii_case(name))
+    }
+
+    /// From white's point of view in centipawns, computed from scratch
+    pub fn evaluate(self, board: &Board) -> i16 {
+        match self {
+            EvaluatorKind::Classical => board.evaluate(),
+            EvaluatorKind::Material => Material::new(board).evaluate(board),
+            EvaluatorKind::Nnue => Nnue::new(board).evaluate(board),
+        }
+    }
+
+    /// Tells apart what the scores in a saved hash table were computed with: the kind of
+    /// evaluator and the weights or network it's using (FNV-1a of them)
+    pub fn signature(self) -> u64 {
+        let bytes = match self {
+            EvaluatorKind::Classical => Param::ALL
+                .iter()
+                .flat_map(|&param| params().weights(param))
+                .flat_map(|w| [w.0.to_le_bytes(), w.1.to_le_bytes()])
+                .flatten()
+                .collect(),
+            EvaluatorKind::Material => Vec::new(),
+            EvaluatorKind::Nnue => nnue::network().to_bytes(),
+        };
+
+        [self as u8]
+            .iter()
+            .chain(&bytes)
+            .fold(0xcbf29ce484222325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+}
+
+/// The terms of the evaluation beyond material, piece-square tables and pawn structure, as bit
+/// flags so they can be switched off one by one when testing
+pub struct EvalTerms;
+
+impl EvalTerms {
+    pub const MOBILITY: u16 = 1;
+    pub const KING_SAFETY: u16 = 2;
+    pub const BISHOP_PAIR: u16 = 4;
+    pub const ROOK_FILES: u16 = 8;
+    pub const OUTPOSTS: u16 = 16;
+    pub const THREATS: u16 = 32;
+    pub const HANGING: u16 = 64;
+    pub const ALL: u16 = 127;
+}
+
+// the usual number of squares reached (not taken by our pieces or attacked by enemy pawns),
+// indexed by piece type
+const MOBILITY_BASELINE: [i16; PIECE_TYPES.len()] = [0, 4, 7, 7, 14, 0];
+
+// attack units for each square of the king zone a piece attacks, indexed by piece type
+const KING_ATTACK_WEIGHT: [i16; PIECE_TYPES.len()] = [0, 2, 2, 3, 5, 0];
+// one piece near the king is no attack yet
+const KING_MIN_ATTACKERS: u32 = 2;
+const KING_ATTACK_DIVISOR: i16 = 8;
+const KING_ATTACK_MAX: i16 = 400;
+
+/// Squares attacked by the pieces of one side
+struct Attacks {
+    by_piece: [u64; PIECE_TYPES.len()],
+    all: u64,
+}
+
+impl Attacks {
+    fn new(board: &Board, color: Color) -> Attacks {
+        let occupancy =
+            board.occupancies[Color::White as usize] | board.occupancies[Color::Black as usize];
+
+        let mut by_piece = [0; PIECE_TYPES.len()];
+        for piece_type in PIECE_TYPES {
+            for square in board.bitboards[color as usize][piece_type as usize].ones_iter() {
+                by_piece[piece_type as usize] |=
+                    piece_attacks(piece_type, color, square, occupancy);
+            }
+        }
+
+        Attacks {
+            by_piece,
+            all: by_piece.iter().fold(0, |all, attacks| all | attacks),
+        }
+    }
+}
+
+fn piece_attacks(piece_type: Piece, color: Color, square: Square, occupancy: u64) -> u64 {
+    match piece_type {
+        Piece::Pawn => pawns::pawn_attacks(color, square),
+        Piece::Knight => tables::KNIGHT_ATTACKS[square as usize],
+        Piece::Bishop => magics::SLIDING_ATTACKS[movegen::get_bishop_index(square, occupancy)],
+        Piece::Rook => magics::SLIDING_ATTACKS[movegen::get_rook_index(square, occupancy)],
+        Piece::Queen => {
+            magics::SLIDING_ATTACKS[movegen::get_bishop_index(square, occupancy)]
+                | magics::SLIDING_ATTACKS[movegen::get_rook_index(square, occupancy)]
+        }
+        Piece::King => tables::KING_ATTACKS[square as usize],
+        Piece::None => 0,
+    }
+}
+
+impl Board {
+    /// Mobility, king safety and piece activity of one side, from its point of view
+    fn activity<T: Trace>(
+        &self,
+        color: Color,
+        attacks: &[Attacks; 2],
+        terms: u16,
+        trace: &mut T,
+    ) -> W {
+        let us = &self.bitboards[color as usize];
+        let them = &self.bitboards[color.toggle() as usize];
+        let our_attacks = &attacks[color as usize];
+        let their_attacks = &attacks[color.toggle() as usize];
+        let occupancy =
+            self.occupancies[Color::White as usize] | self.occupancies[Color::Black as usize];
+
+        let params = params();
+        let mut score = W(0, 0);
+
+        if terms & EvalTerms::MOBILITY != 0 {
+            let mut mobility = W(0, 0);
+            let area =
+                !self.occupancies[color as usize] & !their_attacks.by_piece[Piece::Pawn as usize];
+            for piece_type in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+                let weight = params.mobility[piece_type as usize];
+                for square in us[piece_type as usize].ones_iter() {
+                    let moves = piece_attacks(piece_type, color, square, occupancy) & area;
+                    let extra = moves.count_ones() as i16 - MOBILITY_BASELINE[piece_type as usize];
+                    mobility += W(weight.0 * extra, weight.1 * extra);
+                    trace.feature(Param::Mobility, piece_type as usize, color, extra);
+                }
+            }
+            score += mobility;
+            trace.add(Term::Mobility, color, mobility);
+        }
+
+        if terms & EvalTerms::KING_SAFETY != 0 {
+            let king_safety = self.king_safety(color, occupancy, trace);
+            score += king_safety;
+            trace.add(Term::KingSafety, color, king_safety);
+        }
+
+        if terms & EvalTerms::ROOK_FILES != 0 {
+            let mut rook_files = W(0, 0);
+            let all_pawns = us[Piece::Pawn as usize] | them[Piece::Pawn as usize];
+            for square in us[Piece::Rook as usize].ones_iter() {
+                let file = FILES[square as usize % BOARD_WIDTH];
+                if all_pawns & file == 0 {
+                    rook_files += params.rook_open_file;
+                    trace.feature(Param::RookOpenFile, 0, color, 1);
+                } else if us[Piece::Pawn as usize] & file == 0 {
+                    rook_files += params.rook_semi_open_file;
+                    trace.feature(Param::RookSemiOpenFile, 0, color, 1);
+                }
+            }
+            score += rook_files;
+            trace.add(Term::RookFiles, color, rook_files);
+        }
+
+        if terms & EvalTerms::OUTPOSTS != 0 {
+            let mut outposts = W(0, 0);
+            for (piece_type, param) in [
+                (Piece::Knight, Param::KnightOutpost),
+                (Piece::Bishop, Param::BishopOutpost),
+            ] {
+                for square in (us[piece_type as usize] & our_attacks.by_piece[Piece::Pawn as usize])
+                    .ones_iter()
+                {
+                    let rank = pawns::relative_rank(color, square);
+                    let reach = pawns::forward_ranks(color, square) & pawns::adjacent_files(square);
+                    if (3..=5).contains(&rank) && them[Piece::Pawn as usize] & reach == 0 {
+                        outposts += params.weights(param)[0];
+                        trace.feature(param, 0, color, 1);
+                    }
+                }
+            }
+            score += outposts;
+            trace.add(Term::Outposts, color, outposts);
+        }
+
+        // the king can't be won, so it's left out of threats
+        let their_pieces = them[Piece::Knight as usize]
+            | them[Piece::Bishop as usize]
+            | them[Piece::Rook as usize]
+            | them[Piece::Queen as usize];
+
+        if terms & EvalTerms::THREATS != 0 {
+            let mut threats = W(0, 0);
+            let minor_attacks = our_attacks.by_piece[Piece::Knight as usize]
+                | our_attacks.by_piece[Piece::Bishop as usize];
+            let major = them[Piece::Rook as usize] | them[Piece::Queen as usize];
+
+            for (targets, param) in [
+                (
+                    our_attacks.by_piece[Piece::Pawn as usize] & their_pieces,
+                    Param::ThreatByPawn,
+                ),
+                (minor_attacks & major, Param::ThreatByMinor),
+                (
+                    our_attacks.by_piece[Piece::Rook as usize] & them[Piece::Queen as usize],
+                    Param::ThreatByRook,
+                ),
+            ] {
+                let count = targets.count_ones() as i16;
+                let bonus = params.weights(param)[0];
+                threats += W(bonus.0 * count, bonus.1 * count);
+                trace.feature(param, 0, color, count);
+            }
+            score += threats;
+            trace.add(Term::Threats, color, threats);
+        }
+
+        if terms & EvalTerms::HANGING != 0 {
+            let count = (their_pieces & our_attacks.all & !their_attacks.all).count_ones() as i16;
+            let hanging = W(params.hanging.0 * count, params.hanging.1 * count);
+            trace.feature(Param::Hanging, 0, color, count);
+            score += hanging;
+            trace.add(Term::Hanging, color, hanging);
+        }
+
+        score
+    }
+
+    /// Attacks on the squares around the king, and the pawns in front of it. Only matters in
+    /// the middlegame
+    fn king_safety<T: Trace>(&self, color: Color, occupancy: u64, trace: &mut T) -> W {
+        let enemy = color.toggle();
+        let king = self.bitboards[color as usize][Piece::King as usize].trailing_zeros() as Square;
+        let zone = tables::KING_ATTACKS[king as usize] | bit(king);
+
+        let mut attackers = 0;
+        let mut attack_units = 0;
+        for piece_type in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+            for square in self.bitboards[enemy as usize][piece_type as usize].ones_iter() {
+                let attacked = piece_attacks(piece_type, enemy, square, occupancy) & zone;
+                if attacked != 0 {
+                    attackers += 1;
+                    attack_units +=
+                        KING_ATTACK_WEIGHT[piece_type as usize] * attacked.count_ones() as i16;
+                }
+            }
+        }
+
+        let mut safety = W(0, 0);
+        if attackers >= KING_MIN_ATTACKERS {
+            safety.0 -= (attack_units * attack_units / KING_ATTACK_DIVISOR).min(KING_ATTACK_MAX);
+        }
+
+        let front = pawns::forward_ranks(color, king);
+        let king_rank = (king as usize / BOARD_WIDTH) as i16;
+        let files = FILES[king as usize % BOARD_WIDTH] | pawns::adjacent_files(king);
+        for file in FILES.iter().filter(|&&file| file & files != 0) {
+            // the pawn closest to the king on that file
+            let closest = |pawns: u64| {
+                let pawns = pawns & file & front;
+                (pawns != 0).then(|| {
+                    let square = match color {
+                        Color::White => pawns.trailing_zeros(),
+                        Color::Black => 63 - pawns.leading_zeros(),
+                    };
+                    (square as i16 / BOARD_WIDTH as i16 - king_rank).unsigned_abs()
+                })
+            };
+
+            let shield = closest(self.bitboards[color as usize][Piece::Pawn as usize]);
+            let shield = shield.map_or(0, |distance| distance.min(3) as usize);
+            safety += params().pawn_shield[shield];
+            trace.feature(Param::PawnShield, shield, color, 1);
+
+            let storm = closest(self.bitboards[enemy as usize][Piece::Pawn as usize]);
+            let storm_len = params().pawn_storm.len() as u16;
+            if let Some(distance) = storm.filter(|&distance| distance < storm_len) {
+                safety += params().pawn_storm[distance as usize];
+                trace.feature(Param::PawnStorm, distance as usize, color, 1);
+            }
+        }
+
+        safety
     }
 }
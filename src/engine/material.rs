@@ -0,0 +1,148 @@
+//! The parts of the evaluation that only depend on the material, cached by material key so
+//! they're computed once per material configuration instead of once per node.
+
+use crate::{
+    chess::*,
+    engine::{
+        endgame::{self, Endgame},
+        evaluation::W,
+        params::params,
+    },
+};
+
+// Kaufman: knights get better with more pawns on the board and rooks get worse
+const KNIGHT_PAWN_BONUS: i16 = 6;
+const ROOK_PAWN_PENALTY: i16 = 12;
+const IMBALANCE_PAWNS: i16 = 5;
+
+pub struct MaterialEntry {
+    key: u64,
+    /// From 0 (endgame) to `Board::PHASE_SCALE` (opening)
+    pub phase_ratio: i32,
+    /// From white's point of view
+    pub material: W,
+    /// From white's point of view
+    pub imbalance: W,
+    /// From white's point of view, kept apart so it can be switched off
+    pub bishop_pair: W,
+    pub endgame: Option<(Endgame, Color)>,
+    /// `Board::mating_material`, or `None` when it depends on the colors of the bishops' squares
+    pub mating_material: Option<MatingMaterial>,
+}
+
+impl MaterialEntry {
+    pub fn new(board: &Board) -> MaterialEntry {
+        let [white_material, black_material] =
+            [Color::White, Color::Black].map(|color| material(board, color));
+        let [white, black] = [Color::White, Color::Black].map(|color| imbalance(board, color));
+        let [white_pair, black_pair] =
+            [Color::White, Color::Black].map(|color| bishop_pair(board, color));
+
+        // with pawns, rooks or queens there's enough to mate, without them only a second bishop
+        // can make the squares matter
+        let count = |piece_type: Piece| {
+            [Color::White, Color::Black]
+                .map(|color| board.bitboards[color as usize][piece_type as usize].count_ones())
+                .iter()
+                .sum::<u32>()
+        };
+        let heavy = count(Piece::Pawn) + count(Piece::Rook) + count(Piece::Queen);
+        let mating_material =
+            (heavy > 0 || count(Piece::Bishop) <= 1).then(|| board.mating_material());
+
+        MaterialEntry {
+            key: board.material_key,
+            phase_ratio: board.phase_ratio(),
+            material: W(
+                white_material.0 - black_material.0,
+                white_material.1 - black_material.1,
+            ),
+            imbalance: W(white.0 - black.0, white.1 - black.1),
+            bishop_pair: W(white_pair.0 - black_pair.0, white_pair.1 - black_pair.1),
+            endgame: endgame::find(board),
+            mating_material,
+        }
+    }
+
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+}
+
+/// What the pieces of the side are worth, with the tunable values rather than the ones the
+/// search uses
+pub(crate) fn material(board: &Board, color: Color) -> W {
+    PIECE_TYPES
+        .iter()
+        .fold(W(0, 0), |mut material, &piece_type| {
+            let count = board.bitboards[color as usize][piece_type as usize].count_ones() as i16;
+            let value = params().piece_values[piece_type as usize];
+            material += W(value.0 * count, value.1 * count);
+            material
+        })
+}
+
+pub(crate) fn bishop_pair(board: &Board, color: Color) -> W {
+    match board.bitboards[color as usize][Piece::Bishop as usize].count_ones() >= 2 {
+        true => params().bishop_pair,
+        false => W(0, 0),
+    }
+}
+
+/// Adjustments to the piece values depending on the rest of the material of the side
+pub(crate) fn imbalance(board: &Board, color: Color) -> W {
+    let count = |piece_type: Piece| {
+        board.bitboards[color as usize][piece_type as usize].count_ones() as i16
+    };
+
+    let extra_pawns = count(Piece::Pawn) - IMBALANCE_PAWNS;
+    let pawn_adjustment = count(Piece::Knight) * extra_pawns * KNIGHT_PAWN_BONUS
+        - count(Piece::Rook) * extra_pawns * ROOK_PAWN_PENALTY;
+
+    W(pawn_adjustment, pawn_adjustment)
+}
+
+/// Each search thread keeps its own, small enough to stay in cache: a game goes through few
+/// material configurations
+pub struct MaterialTable {
+    entries: Vec<MaterialEntry>,
+}
+
+impl Default for MaterialTable {
+    fn default() -> Self {
+        MaterialTable::new()
+    }
+}
+
+impl MaterialTable {
+    const SIZE_BITS: u32 = 13;
+
+    pub fn new() -> MaterialTable {
+        MaterialTable {
+            entries: (0..1 << MaterialTable::SIZE_BITS)
+                .map(|_| MaterialEntry {
+                    key: u64::MAX, // no position has this much material
+                    phase_ratio: 0,
+                    material: W(0, 0),
+                    imbalance: W(0, 0),
+                    bishop_pair: W(0, 0),
+                    endgame: None,
+                    mating_material: None,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn probe(&mut self, board: &Board) -> &MaterialEntry {
+        // the key is made of small counts, so it's spread over the table by multiplying it
+        let idx = (board.material_key.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            >> (u64::BITS - MaterialTable::SIZE_BITS)) as usize;
+
+        let entry = &mut self.entries[idx];
+        if entry.key != board.material_key {
+            *entry = MaterialEntry::new(board);
+        }
+
+        entry
+    }
+}
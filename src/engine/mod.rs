@@ -1,5 +1,17 @@
+pub mod book;
+pub mod datagen;
+pub mod endgame;
 pub mod evaluation;
+pub mod material;
+pub mod nnue;
 mod ordering;
-mod search;
+pub mod params;
+pub mod pawns;
+pub mod search;
+pub mod syzygy;
+pub mod trace;
 pub mod transposition;
+pub mod tune;
 pub mod uci;
+
+pub use ordering::HistoryHeuristics;
@@ -0,0 +1,301 @@
+//! Efficiently updatable neural network evaluation: `(768 -> HIDDEN)x2 -> 1` with clipped ReLU.
+//!
+//! The `Nnue` evaluator keeps the first layer (the accumulator) for both perspectives, updated
+//! on every piece the moves add and remove, so evaluating only takes the output layer.
+//!
+//! Network files are little endian: the magic `SNNU`, the hidden size as a `u32`, then `i16`s
+//! for the feature weights (input by input), the feature biases, the output weights (side to
+//! move first, then the other side) and the output bias.
+
+use std::sync::{Arc, LazyLock, RwLock};
+
+use crate::{
+    chess::*,
+    engine::{
+        evaluation::{Evaluator, W},
+        params::EvalParams,
+    },
+};
+
+/// Piece type, whether it's ours or theirs, and square, as seen by each side
+pub const INPUTS: usize = 2 * PIECE_TYPES.len() * BOARD_SIZE;
+pub const HIDDEN: usize = 128;
+// quantization of the hidden layer and of the output weights
+const QA: i32 = 255;
+const QB: i32 = 64;
+// network output to centipawns
+const SCALE: i32 = 400;
+
+const MAGIC: &[u8; 4] = b"SNNU";
+
+static EMBEDDED: &[u8] = include_bytes!("../../nets/bootstrap.nnue");
+
+/// A row of hidden neurons, aligned so the loops over it vectorize well
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(C, align(64))]
+pub struct Neurons(pub [i16; HIDDEN]);
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Network {
+    pub feature_weights: Vec<Neurons>,
+    pub feature_bias: Neurons,
+    /// For the side to move, then for the other side
+    pub output_weights: [Neurons; 2],
+    pub output_bias: i16,
+}
+
+/// First layer of the network for each perspective, indexed by color
+#[derive(Clone, PartialEq, Debug)]
+pub struct Accumulator {
+    pub values: [Neurons; 2],
+}
+
+/// Input of a piece as seen by `perspective`: its own pieces come first and the board is flipped
+/// for black, so both sides look at the position the same way
+pub fn feature(perspective: Color, piece_type: Piece, color: Color, square: Square) -> usize {
+    let (side, square) = match perspective {
+        Color::White => (color as usize, square as usize),
+        Color::Black => (color.toggle() as usize, square as usize ^ 56),
+    };
+    (side * PIECE_TYPES.len() + piece_type as usize) * BOARD_SIZE + square
+}
+
+impl Network {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, &'static str> {
+        let (magic, bytes) = bytes.split_at_checked(4).ok_or("network file too short")?;
+        if magic != MAGIC {
+            return Err("not a network file");
+        }
+        let (hidden, bytes) = bytes.split_at_checked(4).ok_or("network file too short")?;
+        if u32::from_le_bytes(hidden.try_into().unwrap()) as usize != HIDDEN {
+            return Err("network has the wrong hidden size");
+        }
+        if bytes.len() != 2 * ((INPUTS + 3) * HIDDEN + 1) {
+            return Err("network file has the wrong size");
+        }
+
+        let mut values = bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]));
+        let mut neurons = || Neurons(std::array::from_fn(|_| values.next().unwrap()));
+
+        let feature_weights = (0..INPUTS).map(|_| neurons()).collect();
+        let feature_bias = neurons();
+        let output_weights = [neurons(), neurons()];
+
+        Ok(Network {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias: values.next().unwrap(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((HIDDEN as u32).to_le_bytes());
+
+        let rows = self
+            .feature_weights
+            .iter()
+            .chain([&self.feature_bias])
+            .chain(&self.output_weights);
+        for value in rows.flat_map(|row| row.0).chain([self.output_bias]) {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn load(path: &str) -> Result<Network, &'static str> {
+        let bytes = std::fs::read(path).map_err(|_| "can't read network file")?;
+        Network::from_bytes(&bytes)
+    }
+
+    /// A network that plays like the piece values and piece-square tables of `params`, with
+    /// midgame and endgame averaged. Starting point until one is trained.
+    ///
+    /// Each hidden neuron adds up the pieces of one type and side on one file, with a bias
+    /// keeping it inside the linear part of the clipped ReLU
+    pub fn from_params(params: &EvalParams) -> Network {
+        const BIAS: i16 = 32;
+        // centipawns per unit of a neuron, coarser for the pieces that pile up on a file
+        let resolution = |piece_type: Piece| match piece_type {
+            Piece::Rook | Piece::Queen => 8,
+            _ => 4,
+        };
+        let neuron = |side: usize, piece_type: Piece, square: usize| {
+            (side * PIECE_TYPES.len() + piece_type as usize) * BOARD_WIDTH + square % BOARD_WIDTH
+        };
+
+        let mut feature_weights = vec![Neurons([0; HIDDEN]); INPUTS];
+        let mut output_weights = [Neurons([0; HIDDEN]); 2];
+        for side in 0..2 {
+            for piece_type in PIECE_TYPES {
+                for square in 0..BOARD_SIZE {
+                    // tables are laid out with a8 first, and their pieces are seen flipped
+                    let table_square = match side {
+                        0 => square ^ 56,
+                        _ => square,
+                    };
+                    let W(mg, eg) = params.piece_values[piece_type as usize];
+                    let W(pst_mg, pst_eg) = params.pst[piece_type as usize][table_square];
+                    let value = (mg + eg + pst_mg + pst_eg) as f32 / 2.0;
+
+                    let input =
+                        (side * PIECE_TYPES.len() + piece_type as usize) * BOARD_SIZE + square;
+                    feature_weights[input].0[neuron(side, piece_type, square)] =
+                        (value / resolution(piece_type) as f32).round() as i16;
+                }
+
+                for file in 0..BOARD_WIDTH {
+                    let weight = (resolution(piece_type) * QA * QB) as f32 / SCALE as f32;
+                    let sign = if side == 0 { 1.0 } else { -1.0 };
+                    output_weights[0].0[neuron(side, piece_type, file)] =
+                        (sign * weight).round() as i16;
+                }
+            }
+        }
+
+        let mut feature_bias = Neurons([0; HIDDEN]);
+        for index in 0..2 * PIECE_TYPES.len() * BOARD_WIDTH {
+            feature_bias.0[index] = BIAS;
+        }
+
+        // the biases of our neurons and theirs cancel out
+        Network {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias: 0,
+        }
+    }
+
+    /// From the point of view of the side to move, in centipawns
+    pub fn evaluate(&self, accumulator: &Accumulator, side_to_move: Color) -> i16 {
+        let perspectives = [side_to_move, side_to_move.toggle()];
+        let mut output = 0;
+        for (perspective, weights) in perspectives.into_iter().zip(&self.output_weights) {
+            output += accumulator.values[perspective as usize]
+                .0
+                .iter()
+                .zip(&weights.0)
+                .map(|(&value, &weight)| (value as i32).clamp(0, QA) * weight as i32)
+                .sum::<i32>();
+        }
+
+        ((output + self.output_bias as i32) * SCALE / (QA * QB)) as i16
+    }
+}
+
+static NETWORK: RwLock<Option<Arc<Network>>> = RwLock::new(None);
+static DEFAULT_NETWORK: LazyLock<Arc<Network>> =
+    LazyLock::new(|| Arc::new(Network::from_bytes(EMBEDDED).expect("embedded network is valid")));
+
+/// The network in use, the embedded one unless another was loaded
+pub fn network() -> Arc<Network> {
+    let loaded = NETWORK.read().unwrap();
+    Arc::clone(loaded.as_ref().unwrap_or(&DEFAULT_NETWORK))
+}
+
+/// Replaces the network for the evaluators created from now on. A search still reading the
+/// old one keeps it alive until it's done
+pub fn set_network(network: Network) {
+    *NETWORK.write().unwrap() = Some(Arc::new(network));
+}
+
+/// Goes back to the embedded network
+pub fn reset_network() {
+    *NETWORK.write().unwrap() = None;
+}
+
+impl Accumulator {
+    /// Nothing on the board yet
+    pub fn new(network: &Network) -> Accumulator {
+        Accumulator {
+            values: [network.feature_bias; 2],
+        }
+    }
+
+    /// The accumulator of `board` computed from scratch
+    pub fn from_board(network: &Network, board: &Board) -> Accumulator {
+        let mut accumulator = Accumulator::new(network);
+        let occupancy =
+            board.occupancies[Color::White as usize] | board.occupancies[Color::Black as usize];
+        for square in occupancy.ones_iter() {
+            let (piece_type, color) = board.pieces[square as usize];
+            accumulator.add(network, piece_type, color, square);
+        }
+        accumulator
+    }
+
+    #[inline]
+    pub fn add(&mut self, network: &Network, piece_type: Piece, color: Color, square: Square) {
+        for perspective in [Color::White, Color::Black] {
+            let weights = &network.feature_weights[feature(perspective, piece_type, color, square)];
+            for (value, weight) in self.values[perspective as usize]
+                .0
+                .iter_mut()
+                .zip(&weights.0)
+            {
+                *value = value.wrapping_add(*weight);
+            }
+        }
+    }
+
+    #[inline]
+    pub fn remove(&mut self, network: &Network, piece_type: Piece, color: Color, square: Square) {
+        for perspective in [Color::White, Color::Black] {
+            let weights = &network.feature_weights[feature(perspective, piece_type, color, square)];
+            for (value, weight) in self.values[perspective as usize]
+                .0
+                .iter_mut()
+                .zip(&weights.0)
+            {
+                *value = value.wrapping_sub(*weight);
+            }
+        }
+    }
+}
+
+/// Evaluates with the network in use when it was created, so loading another one doesn't
+/// affect a search in progress
+pub struct Nnue {
+    network: Arc<Network>,
+    pub accumulator: Accumulator,
+}
+
+impl Evaluator for Nnue {
+    fn new(board: &Board) -> Nnue {
+        let network = network();
+        Nnue {
+            accumulator: Accumulator::from_board(&network, board),
+            network,
+        }
+    }
+
+    #[inline]
+    fn add_piece(&mut self, piece_type: Piece, color: Color, square: Square) {
+        self.accumulator
+            .add(&self.network, piece_type, color, square);
+    }
+
+    #[inline]
+    fn remove_piece(&mut self, piece_type: Piece, color: Color, square: Square) {
+        self.accumulator
+            .remove(&self.network, piece_type, color, square);
+    }
+
+    fn evaluate(&mut self, board: &Board) -> i16 {
+        debug_assert_eq!(
+            self.accumulator,
+            Accumulator::from_board(&self.network, board),
+            "accumulator mismatch"
+        );
+
+        let score = self.network.evaluate(&self.accumulator, board.side_to_move);
+        match board.side_to_move {
+            Color::White => score,
+            Color::Black => -score,
+        }
+    }
+}
@@ -6,14 +6,14 @@ use std::{
     },
 };
 
-use crate::{chess::*, engine::search::Searcher};
+use crate::{chess::*, engine::search};
 use tinyvec::ArrayVec;
 
 pub(crate) type ScoredMoveList = ArrayVec<[(Move, i16); MAX_MOVES]>;
 pub(crate) struct SearchContext<'a> {
     pub board: &'a Board,
     pub pv_line: &'a [Move],
-    pub killers: &'a [[Option<Move>; 2]; Searcher::MAX_PLY],
+    pub killers: &'a [[Option<Move>; 2]; search::MAX_PLY],
     pub history_heuristic: &'a HistoryHeuristics,
     pub hash_move: Option<Move>,
     pub ply: usize,
@@ -58,7 +58,7 @@ fn get_least_valuable_attacker(
         }
     }
 
-    return None;
+    None
 }
 
 fn consider_x_rays(square: Square, side_to_move: Color, occupancy: u64, board: &Board) -> u64 {
@@ -167,10 +167,10 @@ fn see_ge(
 
 fn score_move(mov: Move, search_ctx: &SearchContext) -> i16 {
     if search_ctx.hash_move == Some(mov) {
-        return Searcher::INF;
+        return search::INF;
     }
     if search_ctx.pv_line.get(search_ctx.ply) == Some(&mov) {
-        return Searcher::INF - 1;
+        return search::INF - 1;
     }
 
     let flags = mov.get_flags();
@@ -178,11 +178,11 @@ fn score_move(mov: Move, search_ctx: &SearchContext) -> i16 {
     // short-cut promotions
     if flags.promotion != Piece::None {
         let promoted_value = Board::PIECE_VALUES[flags.promotion as usize];
-        return match flags.promotion {
+        match flags.promotion {
             Piece::Queen | Piece::Knight => MoveBuckets::CAPTURES_PROMOTIONS + promoted_value,
             Piece::Bishop | Piece::Rook => MoveBuckets::UNDER_PROMOTIONS + promoted_value,
             _ => unreachable!(),
-        };
+        }
     } else {
         match flags.move_type {
             MoveType::Capture => {
@@ -232,6 +232,12 @@ pub struct HistoryHeuristics {
     table: [[[AtomicI16; BOARD_SIZE]; BOARD_SIZE]; 2],
 }
 
+impl Default for HistoryHeuristics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HistoryHeuristics {
     const HISTORY_MAX: i32 = 20_000;
 
@@ -241,7 +247,7 @@ impl HistoryHeuristics {
 
     // gravity formula
     pub fn update(&self, color: Color, from: Square, to: Square, bonus: i32) {
-        let clamped_bonus = bonus.clamp(-Self::HISTORY_MAX, Self::HISTORY_MAX) as i32;
+        let clamped_bonus = bonus.clamp(-Self::HISTORY_MAX, Self::HISTORY_MAX);
 
         self.table[color as usize][from as usize][to as usize]
             .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| {
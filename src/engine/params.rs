@@ -0,0 +1,446 @@
+//! The weights of the evaluation. The values below are compiled in, a file in the format written
+//! by `Display` (the one the tuner writes) can replace them at startup.
+
+use std::{fmt, sync::OnceLock};
+
+use crate::{chess::*, engine::evaluation::W};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    /// Indexed by piece type, the king is never traded so it's worth nothing here
+    pub piece_values: [W; PIECE_TYPES.len()],
+    /// Indexed by piece type and square as seen by white, with 0=a8 and 63=h1
+    pub pst: [[W; BOARD_SIZE]; PIECE_TYPES.len()],
+
+    pub doubled: W,
+    pub isolated: W,
+    pub backward: W,
+    // indexed by the rank as seen by the side owning the pawn
+    pub supported: [W; BOARD_WIDTH],
+    pub phalanx: [W; BOARD_WIDTH],
+    pub passed: [W; BOARD_WIDTH],
+    pub candidate: [W; BOARD_WIDTH],
+
+    pub bishop_pair: W,
+    /// Per square reached above or below the usual number, indexed by piece type
+    pub mobility: [W; PIECE_TYPES.len()],
+    /// Own pawns in front of the king, by distance to the king (0 when there's none)
+    pub pawn_shield: [W; 4],
+    /// Enemy pawns coming at the king, by distance
+    pub pawn_storm: [W; 5],
+    pub rook_open_file: W,
+    pub rook_semi_open_file: W,
+    pub knight_outpost: W,
+    pub bishop_outpost: W,
+    pub threat_by_pawn: W,
+    pub threat_by_minor: W,
+    pub threat_by_rook: W,
+    pub hanging: W,
+}
+
+impl Default for EvalParams {
+    #[rustfmt::skip]
+    fn default() -> Self {
+        EvalParams {
+            piece_values: [
+                W(100, 100),
+                W(320, 320),
+                W(330, 330),
+                W(500, 500),
+                W(900, 900),
+                W(0, 0),
+            ],
+            pst: DEFAULT_PST,
+
+            doubled: W(-11, -56),
+            isolated: W(-5, -15),
+            // can't advance safely and no pawn behind on the adjacent files can come to support it
+            backward: W(-9, -24),
+            supported: [
+                W(0, 0), W(7, 0), W(8, 3), W(12, 6), W(29, 15), W(48, 36), W(86, 86), W(0, 0),
+            ],
+            phalanx: [
+                W(0, 0), W(4, 0), W(5, 2), W(8, 4), W(18, 10), W(30, 25), W(55, 55), W(0, 0),
+            ],
+            passed: [
+                W(0, 0), W(10, 28), W(17, 33), W(15, 41), W(62, 72), W(168, 177), W(276, 260), W(0, 0),
+            ],
+            // about half the bonus of a passed pawn on the same rank
+            candidate: [
+                W(0, 0), W(5, 14), W(8, 16), W(7, 20), W(31, 36), W(84, 88), W(138, 130), W(0, 0),
+            ],
+
+            bishop_pair: W(25, 50),
+            mobility: [W(0, 0), W(4, 4), W(5, 5), W(2, 4), W(1, 2), W(0, 0)],
+            pawn_shield: [W(-20, 0), W(15, 0), W(8, 0), W(0, 0)],
+            pawn_storm: [W(0, 0), W(-10, 0), W(-30, 0), W(-15, 0), W(-5, 0)],
+            rook_open_file: W(44, 20),
+            rook_semi_open_file: W(19, 7),
+            // minor pieces on the enemy side, defended by a pawn and out of reach of the enemy pawns
+            knight_outpost: W(30, 20),
+            bishop_outpost: W(18, 10),
+            threat_by_pawn: W(48, 30),
+            threat_by_minor: W(35, 30),
+            threat_by_rook: W(30, 30),
+            // attacked and not defended
+            hanging: W(35, 20),
+        }
+    }
+}
+
+// stolen from PeSTO
+#[rustfmt::skip]
+const DEFAULT_PST: [[W; BOARD_SIZE]; PIECE_TYPES.len()] = [
+    // pawn
+    [
+        W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0),
+        W(98, 178), W(134, 173), W(61, 158), W(95, 134), W(68, 147), W(126, 132), W(34, 165), W(-11, 187),
+        W(-6, 94), W(7, 100), W(26, 85), W(31, 67), W(65, 56), W(56, 53), W(25, 82), W(-20, 84),
+        W(-14, 32), W(13, 24), W(6, 13), W(21, 5), W(23, -2), W(12, 4), W(17, 17), W(-23, 17),
+        W(-27, 13), W(-2, 9), W(-5, -3), W(12, -7), W(17, -7), W(6, -8), W(10, 3), W(-25, -1),
+        W(-26, 4), W(-4, 7), W(-4, -6), W(-10, 1), W(3, 0), W(3, -5), W(33, -1), W(-12, -8),
+        W(-35, 13), W(-1, 8), W(-20, 8), W(-23, 10), W(-15, 13), W(24, 0), W(38, 2), W(-22, -7),
+        W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0), W(0, 0),
+    ],
+    // knight
+    [
+        W(-167, -58), W(-89, -38), W(-34, -13), W(-49, -28), W(61, -31), W(-97, -27), W(-15, -63), W(-107, -99),
+        W(-73, -25), W(-41, -8), W(72, -25), W(36, -2), W(23, -9), W(62, -25), W(7, -24), W(-17, -52),
+        W(-47, -24), W(60, -20), W(37, 10), W(65, 9), W(84, -1), W(129, -9), W(73, -19), W(44, -41),
+        W(-9, -17), W(17, 3), W(19, 22), W(53, 22), W(37, 22), W(69, 11), W(18, 8), W(22, -18),
+        W(-13, -18), W(4, -6), W(16, 16), W(13, 25), W(28, 16), W(19, 17), W(21, 4), W(-8, -18),
+        W(-23, -23), W(-9, -3), W(12, -1), W(10, 15), W(19, 10), W(17, -3), W(25, -20), W(-16, -22),
+        W(-29, -42), W(-53, -20), W(-12, -10), W(-3, -5), W(-1, -2), W(18, -20), W(-14, -23), W(-19, -44),
+        W(-105, -29), W(-21, -51), W(-58, -23), W(-33, -15), W(-17, -22), W(-28, -18), W(-19, -50), W(-23, -64),
+    ],
+    // bishop
+    [
+        W(-29, -14), W(4, -21), W(-82, -11), W(-37, -8), W(-25, -7), W(-42, -9), W(7, -17), W(-8, -24),
+        W(-26, -8), W(16, -4), W(-18, 7), W(-13, -12), W(30, -3), W(59, -13), W(18, -4), W(-47, -14),
+        W(-16, 2), W(37, -8), W(43, 0), W(40, -1), W(35, -2), W(50, 6), W(37, 0), W(-2, 4),
+        W(-4, -3), W(5, 9), W(19, 12), W(50, 9), W(37, 14), W(37, 10), W(7, 3), W(-2, 2),
+        W(-6, -6), W(13, 3), W(13, 13), W(26, 19), W(34, 7), W(12, 10), W(10, -3), W(4, -9),
+        W(0, -12), W(15, -3), W(15, 8), W(15, 10), W(14, 13), W(27, 3), W(18, -7), W(10, -15),
+        W(4, -14), W(15, -18), W(16, -7), W(0, -1), W(7, 4), W(21, -9), W(33, -15), W(1, -27),
+        W(-33, -23), W(-3, -9), W(-14, -23), W(-21, -5), W(-13, -9), W(-12, -16), W(-39, -5), W(-21, -17),
+    ],
+    // rook
+    [
+        W(32, 13), W(42, 10), W(32, 18), W(51, 15), W(63, 12), W(9, 12), W(31, 8), W(43, 5),
+        W(27, 11), W(32, 13), W(58, 13), W(62, 11), W(80, -3), W(67, 3), W(26, 8), W(44, 3),
+        W(-5, 7), W(19, 7), W(26, 7), W(36, 5), W(17, 4), W(45, -3), W(61, -5), W(16, -3),
+        W(-24, 4), W(-11, 3), W(7, 13), W(26, 1), W(24, 2), W(35, 1), W(-8, -1), W(-20, 2),
+        W(-36, 3), W(-26, 5), W(-12, 8), W(-1, 4), W(9, -5), W(-7, -6), W(6, -8), W(-23, -11),
+        W(-45, -4), W(-25, 0), W(-16, -5), W(-17, -1), W(3, -7), W(0, -12), W(-5, -8), W(-33, -16),
+        W(-44, -6), W(-16, -6), W(-20, 0), W(-9, 2), W(-1, -9), W(11, -9), W(-6, -11), W(-71, -3),
+        W(-19, -9), W(-13, 2), W(1, 3), W(17, -1), W(16, -5), W(7, -13), W(-37, 4), W(-26, -20),
+    ],
+    // queen
+    [
+        W(-28, -9), W(0, 22), W(29, 22), W(12, 27), W(59, 27), W(44, 19), W(43, 10), W(45, 20),
+        W(-24, -17), W(-39, 20), W(-5, 32), W(1, 41), W(-16, 58), W(57, 25), W(28, 30), W(54, 0),
+        W(-13, -20), W(-17, 6), W(7, 9), W(8, 49), W(29, 47), W(56, 35), W(47, 19), W(57, 9),
+        W(-27, 3), W(-27, 22), W(-16, 24), W(-16, 45), W(-1, 57), W(17, 40), W(-2, 57), W(1, 36),
+        W(-9, -18), W(-26, 28), W(-9, 19), W(-10, 47), W(-2, 31), W(-4, 34), W(3, 39), W(-3, 23),
+        W(-14, -16), W(2, -27), W(-11, 15), W(-2, 6), W(-5, 9), W(2, 17), W(14, 10), W(5, 5),
+        W(-35, -22), W(-8, -23), W(11, -30), W(2, -16), W(8, -16), W(15, -23), W(-3, -36), W(1, -32),
+        W(-1, -33), W(-18, -28), W(-9, -22), W(10, -43), W(-15, -5), W(-25, -32), W(-31, -20), W(-50, -41),
+    ],
+    // king
+    [
+        W(-65, -74), W(23, -35), W(16, -18), W(-15, -18), W(-56, -11), W(-34, 15), W(2, 4), W(13, -17),
+        W(29, -12), W(-1, 17), W(-20, 14), W(-7, 17), W(-8, 17), W(-4, 38), W(-38, 23), W(-29, 11),
+        W(-9, 10), W(24, 17), W(2, 23), W(-16, 15), W(-20, 20), W(6, 45), W(22, 44), W(-22, 13),
+        W(-17, -8), W(-20, 22), W(-12, 24), W(-27, 27), W(-30, 26), W(-25, 33), W(-14, 26), W(-36, 3),
+        W(-49, -18), W(-1, -4), W(-27, 21), W(-39, 24), W(-46, 27), W(-44, 23), W(-33, 9), W(-51, -11),
+        W(-14, -19), W(-14, -3), W(-22, 11), W(-46, 21), W(-44, 23), W(-30, 16), W(-15, 7), W(-27, -9),
+        W(1, -27), W(7, -11), W(-8, 4), W(-64, 13), W(-43, 14), W(-16, 4), W(9, -5), W(8, -17),
+        W(-15, -53), W(36, -34), W(12, -21), W(-54, -11), W(8, -28), W(-28, -14), W(24, -24), W(14, -43),
+    ],
+];
+
+/// A group of weights, under its own name in the parameter file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    PieceValues,
+    PawnPst,
+    KnightPst,
+    BishopPst,
+    RookPst,
+    QueenPst,
+    KingPst,
+    Doubled,
+    Isolated,
+    Backward,
+    Supported,
+    Phalanx,
+    Passed,
+    Candidate,
+    BishopPair,
+    Mobility,
+    PawnShield,
+    PawnStorm,
+    RookOpenFile,
+    RookSemiOpenFile,
+    KnightOutpost,
+    BishopOutpost,
+    ThreatByPawn,
+    ThreatByMinor,
+    ThreatByRook,
+    Hanging,
+}
+
+impl Param {
+    pub const ALL: [Param; 26] = [
+        Param::PieceValues,
+        Param::PawnPst,
+        Param::KnightPst,
+        Param::BishopPst,
+        Param::RookPst,
+        Param::QueenPst,
+        Param::KingPst,
+        Param::Doubled,
+        Param::Isolated,
+        Param::Backward,
+        Param::Supported,
+        Param::Phalanx,
+        Param::Passed,
+        Param::Candidate,
+        Param::BishopPair,
+        Param::Mobility,
+        Param::PawnShield,
+        Param::PawnStorm,
+        Param::RookOpenFile,
+        Param::RookSemiOpenFile,
+        Param::KnightOutpost,
+        Param::BishopOutpost,
+        Param::ThreatByPawn,
+        Param::ThreatByMinor,
+        Param::ThreatByRook,
+        Param::Hanging,
+    ];
+
+    /// Number of weights in all the groups
+    pub const COUNT: usize =
+        Param::ALL[Param::ALL.len() - 1].offset() + Param::ALL[Param::ALL.len() - 1].size();
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Param::PieceValues => "piece_values",
+            Param::PawnPst => "pawn_pst",
+            Param::KnightPst => "knight_pst",
+            Param::BishopPst => "bishop_pst",
+            Param::RookPst => "rook_pst",
+            Param::QueenPst => "queen_pst",
+            Param::KingPst => "king_pst",
+            Param::Doubled => "doubled",
+            Param::Isolated => "isolated",
+            Param::Backward => "backward",
+            Param::Supported => "supported",
+            Param::Phalanx => "phalanx",
+            Param::Passed => "passed",
+            Param::Candidate => "candidate",
+            Param::BishopPair => "bishop_pair",
+            Param::Mobility => "mobility",
+            Param::PawnShield => "pawn_shield",
+            Param::PawnStorm => "pawn_storm",
+            Param::RookOpenFile => "rook_open_file",
+            Param::RookSemiOpenFile => "rook_semi_open_file",
+            Param::KnightOutpost => "knight_outpost",
+            Param::BishopOutpost => "bishop_outpost",
+            Param::ThreatByPawn => "threat_by_pawn",
+            Param::ThreatByMinor => "threat_by_minor",
+            Param::ThreatByRook => "threat_by_rook",
+            Param::Hanging => "hanging",
+        }
+    }
+
+    /// The piece-square table of a piece type
+    pub fn pst(piece_type: Piece) -> Param {
+        match piece_type {
+            Piece::Pawn => Param::PawnPst,
+            Piece::Knight => Param::KnightPst,
+            Piece::Bishop => Param::BishopPst,
+            Piece::Rook => Param::RookPst,
+            Piece::Queen => Param::QueenPst,
+            Piece::King | Piece::None => Param::KingPst,
+        }
+    }
+
+    /// Number of weights in the group
+    pub const fn size(self) -> usize {
+        match self {
+            Param::PieceValues | Param::Mobility => PIECE_TYPES.len(),
+            Param::PawnPst
+            | Param::KnightPst
+            | Param::BishopPst
+            | Param::RookPst
+            | Param::QueenPst
+            | Param::KingPst => BOARD_SIZE,
+            Param::Supported | Param::Phalanx | Param::Passed | Param::Candidate => BOARD_WIDTH,
+            Param::PawnShield => 4,
+            Param::PawnStorm => 5,
+            _ => 1,
+        }
+    }
+
+    /// Where the group starts when all the weights are laid out one after the other
+    pub const fn offset(self) -> usize {
+        let mut offset = 0;
+        let mut i = 0;
+        while i < self as usize {
+            offset += Param::ALL[i].size();
+            i += 1;
+        }
+        offset
+    }
+}
+
+static PARAMS: OnceLock<EvalParams> = OnceLock::new();
+
+/// The weights in use, the compiled defaults unless others were set before the first evaluation
+pub fn params() -> &'static EvalParams {
+    PARAMS.get_or_init(EvalParams::default)
+}
+
+/// Replaces the weights, only possible before anything is evaluated since evaluators keep sums
+/// of them
+pub fn set_params(params: EvalParams) -> Result<(), &'static str> {
+    PARAMS
+        .set(params)
+        .map_err(|_| "evaluation parameters are already in use")
+}
+
+impl EvalParams {
+    pub fn weights(&self, param: Param) -> &[W] {
+        match param {
+            Param::PieceValues => &self.piece_values,
+            Param::PawnPst => &self.pst[Piece::Pawn as usize],
+            Param::KnightPst => &self.pst[Piece::Knight as usize],
+            Param::BishopPst => &self.pst[Piece::Bishop as usize],
+            Param::RookPst => &self.pst[Piece::Rook as usize],
+            Param::QueenPst => &self.pst[Piece::Queen as usize],
+            Param::KingPst => &self.pst[Piece::King as usize],
+            Param::Doubled => std::slice::from_ref(&self.doubled),
+            Param::Isolated => std::slice::from_ref(&self.isolated),
+            Param::Backward => std::slice::from_ref(&self.backward),
+            Param::Supported => &self.supported,
+            Param::Phalanx => &self.phalanx,
+            Param::Passed => &self.passed,
+            Param::Candidate => &self.candidate,
+            Param::BishopPair => std::slice::from_ref(&self.bishop_pair),
+            Param::Mobility => &self.mobility,
+            Param::PawnShield => &self.pawn_shield,
+            Param::PawnStorm => &self.pawn_storm,
+            Param::RookOpenFile => std::slice::from_ref(&self.rook_open_file),
+            Param::RookSemiOpenFile => std::slice::from_ref(&self.rook_semi_open_file),
+            Param::KnightOutpost => std::slice::from_ref(&self.knight_outpost),
+            Param::BishopOutpost => std::slice::from_ref(&self.bishop_outpost),
+            Param::ThreatByPawn => std::slice::from_ref(&self.threat_by_pawn),
+            Param::ThreatByMinor => std::slice::from_ref(&self.threat_by_minor),
+            Param::ThreatByRook => std::slice::from_ref(&self.threat_by_rook),
+            Param::Hanging => std::slice::from_ref(&self.hanging),
+        }
+    }
+
+    pub fn weights_mut(&mut self, param: Param) -> &mut [W] {
+        match param {
+            Param::PieceValues => &mut self.piece_values,
+            Param::PawnPst => &mut self.pst[Piece::Pawn as usize],
+            Param::KnightPst => &mut self.pst[Piece::Knight as usize],
+            Param::BishopPst => &mut self.pst[Piece::Bishop as usize],
+            Param::RookPst => &mut self.pst[Piece::Rook as usize],
+            Param::QueenPst => &mut self.pst[Piece::Queen as usize],
+            Param::KingPst => &mut self.pst[Piece::King as usize],
+            Param::Doubled => std::slice::from_mut(&mut self.doubled),
+            Param::Isolated => std::slice::from_mut(&mut self.isolated),
+            Param::Backward => std::slice::from_mut(&mut self.backward),
+            Param::Supported => &mut self.supported,
+            Param::Phalanx => &mut self.phalanx,
+            Param::Passed => &mut self.passed,
+            Param::Candidate => &mut self.candidate,
+            Param::BishopPair => std::slice::from_mut(&mut self.bishop_pair),
+            Param::Mobility => &mut self.mobility,
+            Param::PawnShield => &mut self.pawn_shield,
+            Param::PawnStorm => &mut self.pawn_storm,
+            Param::RookOpenFile => std::slice::from_mut(&mut self.rook_open_file),
+            Param::RookSemiOpenFile => std::slice::from_mut(&mut self.rook_semi_open_file),
+            Param::KnightOutpost => std::slice::from_mut(&mut self.knight_outpost),
+            Param::BishopOutpost => std::slice::from_mut(&mut self.bishop_outpost),
+            Param::ThreatByPawn => std::slice::from_mut(&mut self.threat_by_pawn),
+            Param::ThreatByMinor => std::slice::from_mut(&mut self.threat_by_minor),
+            Param::ThreatByRook => std::slice::from_mut(&mut self.threat_by_rook),
+            Param::Hanging => std::slice::from_mut(&mut self.hanging),
+        }
+    }
+
+    /// Reads a parameter file. Groups missing from it keep their default values
+    pub fn parse(text: &str) -> Result<EvalParams, &'static str> {
+        let mut params = EvalParams::default();
+        let mut group: Option<(Param, Vec<i16>)> = None;
+
+        let mut finish = |group: Option<(Param, Vec<i16>)>| {
+            if let Some((param, values)) = group {
+                let weights = params.weights_mut(param);
+                if values.len() != 2 * weights.len() {
+                    return Err("wrong number of values for a parameter");
+                }
+                for (weight, pair) in weights.iter_mut().zip(values.chunks(2)) {
+                    *weight = W(pair[0], pair[1]);
+                }
+            }
+            Ok(())
+        };
+
+        let tokens = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(str::split_whitespace);
+        for token in tokens {
+            if let Some(name) = token.strip_suffix(':') {
+                let param = Param::ALL
+                    .into_iter()
+                    .find(|param| param.name() == name)
+                    .ok_or("unknown parameter")?;
+                finish(group.replace((param, Vec::new())))?;
+            } else {
+                let value = token
+                    .parse()
+                    .map_err(|_| "parameter values must be numbers")?;
+                match &mut group {
+                    Some((_, values)) => values.push(value),
+                    None => return Err("value outside of a parameter"),
+                }
+            }
+        }
+        finish(group)?;
+
+        Ok(params)
+    }
+
+    pub fn load(path: &str) -> Result<EvalParams, &'static str> {
+        let text = std::fs::read_to_string(path).map_err(|_| "can't read parameter file")?;
+        EvalParams::parse(&text)
+    }
+}
+
+/// Every group as its name followed by midgame and endgame pairs, 8 pairs per line
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "# sand evaluation parameters: midgame and endgame value pairs"
+        )?;
+        for param in Param::ALL {
+            writeln!(f, "{}:", param.name())?;
+            for row in self.weights(param).chunks(BOARD_WIDTH) {
+                let row: Vec<String> = row
+                    .iter()
+                    .map(|W(mg, eg)| format!("{mg:>4} {eg:>4}"))
+                    .collect();
+                writeln!(f, "   {}", row.join("   "))?;
+            }
+        }
+        Ok(())
+    }
+}
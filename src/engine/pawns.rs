@@ -0,0 +1,198 @@
+//! Pawn structure evaluation. It only depends on where the pawns are, which changes rarely in
+//! a search, so it's cached by pawn key.
+
+use crate::{
+    chess::{attacks::tables, *},
+    engine::{
+        evaluation::W,
+        params::{Param, params},
+        trace::Trace,
+    },
+};
+
+pub struct PawnEntry {
+    key: u64,
+    /// From white's point of view
+    pub score: W,
+    pub passed: [u64; 2],
+}
+
+impl PawnEntry {
+    pub fn new(board: &Board) -> PawnEntry {
+        let mut score = W(0, 0);
+        let mut passed = [0; 2];
+
+        for color in [Color::White, Color::Black] {
+            let (color_score, color_passed) = evaluate_pawns(board, color, &mut ());
+            match color {
+                Color::White => score += color_score,
+                Color::Black => score -= color_score,
+            }
+            passed[color as usize] = color_passed;
+        }
+
+        PawnEntry {
+            key: board.pawn_key,
+            score,
+            passed,
+        }
+    }
+
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+}
+
+pub(crate) fn relative_rank(color: Color, square: Square) -> usize {
+    let rank = square as usize / BOARD_WIDTH;
+    match color {
+        Color::White => rank,
+        Color::Black => BOARD_WIDTH - 1 - rank,
+    }
+}
+
+/// Every square on the ranks in front of the square, from the point of view of `color`
+pub(crate) fn forward_ranks(color: Color, square: Square) -> u64 {
+    let rank = square as usize / BOARD_WIDTH;
+    match color {
+        Color::White if rank == BOARD_WIDTH - 1 => 0,
+        Color::White => u64::MAX << (BOARD_WIDTH * (rank + 1)),
+        Color::Black => (1 << (BOARD_WIDTH * rank)) - 1,
+    }
+}
+
+pub(crate) fn adjacent_files(square: Square) -> u64 {
+    let file = square as usize % BOARD_WIDTH;
+    let left = if file > 0 { FILES[file - 1] } else { 0 };
+    let right = if file < BOARD_WIDTH - 1 {
+        FILES[file + 1]
+    } else {
+        0
+    };
+    left | right
+}
+
+/// Squares attacked by a pawn of `color` standing on `square`
+pub(crate) fn pawn_attacks(color: Color, square: Square) -> u64 {
+    match color {
+        Color::White => tables::WPAWN_ATTACKS[square as usize],
+        Color::Black => tables::BPAWN_ATTACKS[square as usize],
+    }
+}
+
+/// Scores the pawns of one side and finds which of them are passed
+pub(crate) fn evaluate_pawns<T: Trace>(board: &Board, color: Color, trace: &mut T) -> (W, u64) {
+    let params = params();
+    let us = board.bitboards[color as usize][Piece::Pawn as usize];
+    let them = board.bitboards[color.toggle() as usize][Piece::Pawn as usize];
+    let them_attacks = them.ones_iter().fold(0, |attacks, square| {
+        attacks | pawn_attacks(color.toggle(), square)
+    });
+
+    let mut score = W(0, 0);
+    let mut passed = 0;
+
+    for square in us.ones_iter() {
+        let rank = relative_rank(color, square);
+        let forward = forward_ranks(color, square);
+        let file = FILES[square as usize % BOARD_WIDTH];
+        let neighbour_files = adjacent_files(square);
+
+        let front_span = forward & file;
+        let attack_span = forward & neighbour_files;
+        let neighbours = us & neighbour_files;
+
+        let supporters = us & pawn_attacks(color.toggle(), square);
+        let phalanx = neighbours & RANKS[square as usize / BOARD_WIDTH];
+        let blocked_by_own = us & front_span != 0;
+
+        if blocked_by_own {
+            score += params.doubled;
+            trace.feature(Param::Doubled, 0, color, 1);
+        }
+
+        if supporters != 0 {
+            score += params.supported[rank];
+            trace.feature(Param::Supported, rank, color, 1);
+        }
+        if phalanx != 0 {
+            score += params.phalanx[rank];
+            trace.feature(Param::Phalanx, rank, color, 1);
+        }
+
+        if neighbours == 0 {
+            score += params.isolated;
+            trace.feature(Param::Isolated, 0, color, 1);
+        } else if neighbours & !forward == 0 {
+            // every neighbour is ahead, so if it can't push it can't be defended either
+            let stop = match color {
+                Color::White => bit(square) << BOARD_WIDTH,
+                Color::Black => bit(square) >> BOARD_WIDTH,
+            };
+            if them_attacks & stop != 0 {
+                score += params.backward;
+                trace.feature(Param::Backward, 0, color, 1);
+            }
+        }
+
+        // only the frontmost of doubled pawns can be passed
+        if blocked_by_own || them & front_span != 0 {
+            continue;
+        }
+
+        if them & attack_span == 0 {
+            passed |= bit(square);
+            score += params.passed[rank];
+            trace.feature(Param::Passed, rank, color, 1);
+        } else {
+            // the pawns that can stop it are outnumbered by the ones that can help it through
+            let sentries = (them & attack_span).count_ones();
+            let helpers = (neighbours & !forward).count_ones();
+            if helpers >= sentries {
+                score += params.candidate[rank];
+                trace.feature(Param::Candidate, rank, color, 1);
+            }
+        }
+    }
+
+    (score, passed)
+}
+
+/// Each search thread keeps its own, the pawn structure changes a lot less than the rest of the
+/// position so most lookups hit
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        PawnTable::new()
+    }
+}
+
+impl PawnTable {
+    const SIZE_BITS: u32 = 14;
+
+    pub fn new() -> PawnTable {
+        PawnTable {
+            entries: (0..1 << PawnTable::SIZE_BITS)
+                .map(|_| PawnEntry {
+                    key: u64::MAX, // never filled in practice
+                    score: W(0, 0),
+                    passed: [0; 2],
+                })
+                .collect(),
+        }
+    }
+
+    pub fn probe(&mut self, board: &Board) -> &PawnEntry {
+        let idx = (board.pawn_key >> (u64::BITS - PawnTable::SIZE_BITS)) as usize;
+
+        let entry = &mut self.entries[idx];
+        if entry.key != board.pawn_key {
+            *entry = PawnEntry::new(board);
+        }
+
+        entry
+    }
+}
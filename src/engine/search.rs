@@ -7,10 +7,12 @@ use std::{
 };
 
 use crate::{
-    chess::*,
+    chess::{cuckoo, *},
     engine::{
+        evaluation::Evaluator,
         ordering::*,
-        transposition::{Bound, TT},
+        syzygy::{Tablebases, Wdl},
+        transposition::{Bound, EVAL_NONE, TT, TTEntryData},
     },
     send,
 };
@@ -28,15 +30,23 @@ pub struct ClockTime {
 pub enum TimeControl {
     MoveTime(u64),
     Depth(usize),
+    Nodes(usize),
     ClockTime(ClockTime),
     Infinite,
 }
 
-type PvLine = ArrayVec<[Move; Searcher::MAX_PLY]>;
+pub const MAX_PLY: usize = 64;
+const CHECKMATE_SCORE: i16 = 30_000;
+pub const CHECKMATE_THRESHOLD: i16 = CHECKMATE_SCORE - 2 * MAX_PLY as i16;
+pub const INF: i16 = 32_000;
+// tablebase wins are scored below any mate found by search
+const TB_WIN: i16 = CHECKMATE_THRESHOLD - MAX_PLY as i16;
+
+type PvLine = ArrayVec<[Move; MAX_PLY]>;
 
 #[derive(Clone)]
 struct PvTable {
-    pv: [PvLine; Searcher::MAX_PLY],
+    pv: [PvLine; MAX_PLY],
 }
 
 impl PvTable {
@@ -50,7 +60,7 @@ impl PvTable {
         self.pv[ply].push(mov);
 
         // copy child PV (if any)
-        if ply + 1 < Searcher::MAX_PLY && !self.pv[ply + 1].is_empty() {
+        if ply + 1 < MAX_PLY && !self.pv[ply + 1].is_empty() {
             let (left, right) = self.pv.split_at_mut(ply + 1);
             let curr = &mut left[ply];
             let next = &right[0];
@@ -173,50 +183,135 @@ impl AtomicSearchMode {
 
 pub type ZobristHistory = ArrayVec<[u64; 1024]>;
 
-pub struct Searcher {
+/// Searches with the evaluation `E`, which follows every move made and undone
+pub struct Searcher<E: Evaluator> {
     board: Board,
     history: ZobristHistory,
     pv_table: PvTable,
     prev_pv: PvLine,
 
     nodes: usize,
+    // nodes of the iterations already done, `nodes` only counts the current one
+    searched_nodes: usize,
     seldepth: usize,
+    // score of the best move in the last iteration, from the point of view of the side to move
+    score: i16,
+    // no `info` output
+    silent: bool,
+    // what a draw is worth to the side to move at the root, negative to avoid them
+    contempt: i16,
+    root_color: Color,
 
     time: Option<TimeManagement>,
     time_control: TimeControl,
 
     search_mode: Arc<AtomicSearchMode>,
 
-    killers: [[Option<Move>; 2]; Searcher::MAX_PLY],
+    killers: [[Option<Move>; 2]; MAX_PLY],
     history_heuristic: Arc<HistoryHeuristics>,
     age: u8,
     tt: Arc<TT>,
+    evaluator: E,
+
+    tablebases: Arc<Tablebases>,
+    // pieces needed to probe in search, 0 when the root moves already come from the tables
+    tb_cardinality: usize,
+    tb_hits: usize,
 }
 
-impl Searcher {
-    pub const MAX_PLY: usize = 64;
-    const CHECKMATE_SCORE: i16 = 30_000;
-    pub const CHECKMATE_THRESHOLD: i16 = Searcher::CHECKMATE_SCORE - 2 * Searcher::MAX_PLY as i16;
-    pub const INF: i16 = 32_000;
+impl<E: Evaluator> Searcher<E> {
+    fn evaluate(&mut self) -> i16 {
+        self.evaluator.evaluate(&self.board)
+    }
 
-    fn is_three_fold_repetition(&self) -> bool {
-        self.history
+    /// Static evaluation from the side to move's point of view, from the TT if it's there
+    fn static_eval(&mut self, entry: Option<TTEntryData>) -> i16 {
+        match entry {
+            Some(e) if e.eval != EVAL_NONE => e.eval,
+            _ => match self.board.side_to_move {
+                Color::White => self.evaluate(),
+                Color::Black => -self.evaluate(),
+            },
+        }
+    }
+
+    /// Repeating a position from inside the search tree is already a draw, since the side that
+    /// could avoid it didn't. Positions from before the root have to be there twice, as the game
+    /// needs a threefold repetition
+    fn is_repetition(&self, ply: usize) -> bool {
+        let mut repetitions = 0;
+        for (distance, &zobrist) in self
+            .history
             .iter()
             .rev()
-            .take(self.board.halfmove_clock as usize)
-            .step_by(2) // check only positions with same side to move
-            .filter(|&&zobrist| zobrist == self.board.zobrist)
-            .count()
-            >= 3
+            .enumerate()
+            .take(self.board.halfmove_clock as usize + 1)
+            .skip(4) // at least two moves of each side to get back
+            .step_by(2)
+        {
+            if zobrist == self.board.zobrist {
+                repetitions += 1;
+                if distance < ply || repetitions >= 2 {
+                    return true;
+                }
+            }
+        }
+        false
     }
 
-    fn is_draw(&self) -> bool {
+    fn is_draw(&self, ply: usize) -> bool {
         self.board.is_fifty_move()
-            || self.is_three_fold_repetition()
+            || self.is_repetition(ply)
             || self.board.is_insufficient_material()
     }
 
+    /// Whether the side to move can repeat a position with a single move, so it's at least a
+    /// draw. The key difference with each earlier position is looked up in the cuckoo table of
+    /// reversible moves, and the move has to be possible on the current board
+    fn has_upcoming_repetition(&self, ply: usize) -> bool {
+        let end = (self.board.halfmove_clock as usize).min(self.history.len() - 1);
+        let occupancy = self.board.occupancies[Color::White as usize]
+            | self.board.occupancies[Color::Black as usize];
+        let position = |distance: usize| self.history[self.history.len() - 1 - distance];
+
+        for distance in (3..=end).step_by(2) {
+            let earlier = position(distance);
+            let Some(mov) = cuckoo::lookup(self.board.zobrist ^ earlier) else {
+                continue;
+            };
+            if mov.between & occupancy != 0 {
+                continue;
+            }
+
+            if distance < ply {
+                return true;
+            }
+
+            // before the root the move has to be ours, and the position it goes back to has to
+            // be there twice already
+            let (from, to) = mov.squares;
+            let square = match self.board.pieces[from as usize].0 {
+                Piece::None => to,
+                _ => from,
+            };
+            if self.board.pieces[square as usize].1 != self.board.side_to_move {
+                continue;
+            }
+            if (distance + 4..=end)
+                .step_by(2)
+                .any(|before| position(before) == earlier)
+            {
+                return true;
+            }
+        }
+
+        false
+    }
+
     fn push_move(&mut self, mov: Move) -> Undo {
+        // the child's cluster loads while the move is being made
+        self.tt.prefetch(self.board.key_after(mov));
+        self.evaluator.make_move(&self.board, mov);
         let undo = self.board.make_move(mov);
         self.history.push(self.board.zobrist);
 
@@ -225,6 +320,7 @@ impl Searcher {
 
     fn pop_move(&mut self, undo: &Undo) {
         self.board.undo_move(undo);
+        self.evaluator.undo_move(&self.board, undo.get_move());
         self.history.pop();
     }
 
@@ -264,14 +360,21 @@ impl Searcher {
                     Some(TimeManagement::from_clock(self.board.side_to_move, &ct))
                 }
                 TimeControl::MoveTime(mt) => Some(TimeManagement::from_millis(mt)),
-                TimeControl::Infinite | TimeControl::Depth(_) => None, // no time limit
+                // no time limit
+                TimeControl::Infinite | TimeControl::Depth(_) | TimeControl::Nodes(_) => None,
             };
 
             self.search_mode.store(SearchMode::Normal);
             return false;
         }
 
+        let nodes_exceeded = match self.time_control {
+            TimeControl::Nodes(limit) => self.searched_nodes + self.nodes >= limit,
+            _ => false,
+        };
+
         search_mode == SearchMode::Stop
+            || nodes_exceeded
             || (search_mode != SearchMode::Ponder
                 && self
                     .time
@@ -280,9 +383,13 @@ impl Searcher {
     }
 
     fn print_info(&self, searching_time: Duration, best_score: i16, current_depth: usize) {
-        let score_str = if best_score.abs() >= Searcher::CHECKMATE_THRESHOLD {
+        if self.silent {
+            return;
+        }
+
+        let score_str = if best_score.abs() >= CHECKMATE_THRESHOLD {
             // get the mate distance and convert to full moves
-            let mate_in = (Searcher::CHECKMATE_SCORE - best_score.abs() + 1) / 2;
+            let mate_in = (CHECKMATE_SCORE - best_score.abs() + 1) / 2;
             let mate_in = if best_score > 0 { mate_in } else { -mate_in };
 
             format!("mate {}", mate_in)
@@ -294,7 +401,7 @@ impl Searcher {
         let searching_time_ms = searching_time.as_millis();
 
         send!(
-            "info depth {} seldepth {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+            "info depth {} seldepth {} score {} nodes {} nps {} time {} hashfull {} tbhits {} pv {}",
             current_depth,
             self.seldepth,
             score_str,
@@ -305,11 +412,12 @@ impl Searcher {
             } else {
                 searching_time_ms
             },
-            self.tt.get_hashfull(),
+            self.tt.get_hashfull(self.age),
+            self.tb_hits,
             pv_line
                 .iter()
                 .take(current_depth)
-                .map(|mov| mov.to_uci())
+                .map(|&mov| self.board.move_to_uci(mov))
                 .reduce(|a, b| format!("{a} {b}"))
                 .unwrap_or_default()
         );
@@ -361,17 +469,27 @@ impl Searcher {
     }
 
     fn iterative_deepening(&mut self, depth: Option<usize>) -> (Move, Option<Move>) {
-        let move_list = gen_color_moves(&self.board);
+        // in tablebase positions only the moves keeping the best result are searched, and the
+        // tables aren't probed again in search
+        self.tb_cardinality = self.tablebases.cardinality();
+        let move_list = match self.tablebases.root_moves(&self.board, &self.history) {
+            Some(moves) if !moves.is_empty() => {
+                self.tb_hits += moves.len();
+                self.tb_cardinality = 0;
+                moves
+            }
+            _ => gen_color_moves(&self.board),
+        };
         let mut best_move: Move = move_list[0];
         let mut current_depth = 1;
         let mut ponder_move: Option<Move> = None;
         let search_start = Instant::now(); // used only for `info` updates
 
         loop {
-            let (mut alpha, beta) = (-Searcher::INF, Searcher::INF);
+            let (mut alpha, beta) = (-INF, INF);
 
             let mut step_best_move = best_move;
-            let mut best_score = -Searcher::INF;
+            let mut best_score = -INF;
             let mut last_info_time = Duration::ZERO;
 
             let mut scored_moves = score(&move_list, &self.ctx(0, None));
@@ -381,13 +499,14 @@ impl Searcher {
                 }
 
                 let elapsed = search_start.elapsed();
-                if elapsed
-                    .checked_sub(last_info_time)
-                    .is_some_and(|diff| diff >= Duration::from_secs(1))
+                if !self.silent
+                    && elapsed
+                        .checked_sub(last_info_time)
+                        .is_some_and(|diff| diff >= Duration::from_secs(1))
                 {
                     send!(
                         "info depth {current_depth} currmove {} currmovenumber {}",
-                        mov.to_uci(),
+                        self.board.move_to_uci(mov),
                         move_index + 1,
                     );
                     last_info_time = elapsed;
@@ -423,6 +542,7 @@ impl Searcher {
                 if current_depth <= 1 {
                     self.print_info(searching_time, best_score, current_depth);
                     best_move = step_best_move;
+                    self.score = best_score;
                 }
                 break;
             }
@@ -430,28 +550,37 @@ impl Searcher {
             let pv_line = self.pv_table.get(0);
 
             best_move = step_best_move;
+            self.score = best_score;
             ponder_move = pv_line.get(1).cloned();
             self.print_info(searching_time, best_score, current_depth);
             self.prev_pv = pv_line.try_into().unwrap_or_default();
 
-            if current_depth >= depth.unwrap_or(Searcher::MAX_PLY) {
+            if current_depth >= depth.unwrap_or(MAX_PLY) {
                 break;
             }
 
             current_depth += 1;
+            self.searched_nodes += self.nodes;
             self.nodes = 0;
         }
 
         (best_move, ponder_move)
     }
 
-    fn get_draw_score(eval: i16) -> i16 {
-        (-eval / 10).clamp(-100, 100)
+    /// The draw score for the side to move: the root side gives up the contempt and the
+    /// opponent gets it. It's off by one either way depending on the node count, so lines
+    /// repeating through different paths don't all look exactly the same
+    fn draw_score(&self) -> i16 {
+        let contempt = match self.board.side_to_move == self.root_color {
+            true => -self.contempt,
+            false => self.contempt,
+        };
+        contempt - 1 + (self.nodes & 2) as i16
     }
 
     fn mate_distance_pruning(ply: usize, alpha: i16, beta: i16) -> Option<i16> {
-        let max_mate = Searcher::CHECKMATE_SCORE - ply as i16;
-        let min_mate = -Searcher::CHECKMATE_SCORE + ply as i16;
+        let max_mate = CHECKMATE_SCORE - ply as i16;
+        let min_mate = -CHECKMATE_SCORE + ply as i16;
 
         if alpha >= max_mate {
             return Some(max_mate);
@@ -483,34 +612,82 @@ impl Searcher {
 
         self.pv_table.clear(ply);
 
-        if let Some(score) = Searcher::mate_distance_pruning(ply, alpha, beta) {
+        if let Some(score) = Self::mate_distance_pruning(ply, alpha, beta) {
             return score;
         }
 
-        let entry = self.tt.probe(self.board.zobrist, depth);
-        let hash_move = entry.and_then(|e| Some(e.best_move));
+        let pv_node = alpha + 1 < beta;
+
+        // shallower entries can't cut off but still have a move and an evaluation to reuse
+        let entry = self.tt.probe(self.board.zobrist, 0);
+        let hash_move = entry.map(|e| e.best_move);
+        let tt_pv = pv_node || entry.is_some_and(|e| e.pv);
 
         if let Some(e) = entry
+            && e.depth as usize >= depth
             && let Some(entry_score) = e.probe(&mut alpha, &mut beta, ply)
         {
             return entry_score;
         }
 
-        let color = self.board.side_to_move;
-        let max_mate = Searcher::CHECKMATE_SCORE - ply as i16;
-        let static_eval = match color {
-            Color::White => self.board.evaluate(),
-            Color::Black => -self.board.evaluate(),
-        };
+        let max_mate = CHECKMATE_SCORE - ply as i16;
 
-        if self.is_draw() {
-            return Searcher::get_draw_score(static_eval);
+        if self.is_draw(ply) {
+            return self.draw_score();
+        }
+
+        // the side to move can at least draw by repeating
+        let draw_score = self.draw_score();
+        if alpha < draw_score && self.has_upcoming_repetition(ply) {
+            alpha = draw_score;
+            if alpha >= beta {
+                return alpha;
+            }
         }
 
         let mut best_move = Move(0);
-        let mut best_score = -Searcher::INF;
+        let mut best_score = -INF;
         let mut found_legal_move = false;
 
+        // a tablebase win is a lower bound of the score and a loss an upper one, if they don't
+        // cut off they still bound what the search finds
+        let mut max_score = INF;
+        if let Some((tb_score, bound)) = self.probe_tablebases(depth, ply) {
+            let cutoff = match bound {
+                Bound::Lower => tb_score >= beta,
+                Bound::Upper => tb_score <= alpha,
+                Bound::Exact => true,
+            };
+
+            if cutoff {
+                self.tt.store(
+                    self.board.zobrist,
+                    (depth + 6).min(MAX_PLY - 1),
+                    tb_score,
+                    EVAL_NONE,
+                    Move(0),
+                    bound,
+                    tt_pv,
+                    self.age,
+                    ply,
+                );
+                return tb_score;
+            }
+
+            match bound {
+                Bound::Lower => {
+                    best_score = tb_score;
+                    alpha = alpha.max(tb_score);
+                }
+                _ => max_score = tb_score,
+            }
+        }
+
+        let static_eval = match in_check {
+            true => EVAL_NONE,
+            false => self.static_eval(entry),
+        };
+
         let mut first = true;
         let mut scored_moves = score(&gen_color_moves(&self.board), &self.ctx(ply, hash_move));
         for (move_index, mov) in scored_moves.scored_iter().enumerate() {
@@ -554,12 +731,15 @@ impl Searcher {
         }
 
         if found_legal_move {
+            best_score = best_score.min(max_score);
             self.tt.store(
                 self.board.zobrist,
                 depth,
                 best_score,
+                static_eval,
                 best_move,
                 Bound::from_score(best_score, alpha, beta),
+                tt_pv,
                 self.age,
                 ply,
             );
@@ -571,23 +751,51 @@ impl Searcher {
             if in_check {
                 -max_mate
             } else {
-                Searcher::get_draw_score(static_eval) // stalemate
+                self.draw_score() // stalemate
             }
         }
     }
 
+    /// Looks the position up in the WDL tables, returning its score and what kind of bound it is.
+    ///
+    /// Only positions right after a capture or pawn move are probed: the tables ignore the
+    /// 50-move counter, and it's where the material changes anyway.
+    fn probe_tablebases(&mut self, depth: usize, ply: usize) -> Option<(i16, Bound)> {
+        let pieces = (self.board.occupancies[0] | self.board.occupancies[1]).count_ones() as usize;
+        if pieces > self.tb_cardinality
+            || (pieces == self.tb_cardinality && depth < self.tablebases.probe_depth)
+            || self.board.halfmove_clock != 0
+        {
+            return None;
+        }
+
+        let wdl = self.tablebases.probe_wdl(&self.board)?;
+        self.tb_hits += 1;
+
+        // cursed wins and blessed losses are draws with the 50-move rule
+        let tb_win = TB_WIN - ply as i16;
+        Some(match wdl {
+            Wdl::Win => (tb_win, Bound::Lower),
+            Wdl::Loss => (-tb_win, Bound::Upper),
+            _ => (self.draw_score(), Bound::Exact),
+        })
+    }
+
     fn quiescence(&mut self, mut alpha: i16, mut beta: i16, ply: usize, in_check: bool) -> i16 {
         self.nodes += 1;
         if ply > self.seldepth {
             self.seldepth = ply;
         }
 
-        if let Some(score) = Searcher::mate_distance_pruning(ply, alpha, beta) {
+        if let Some(score) = Self::mate_distance_pruning(ply, alpha, beta) {
             return score;
         }
 
+        let pv_node = alpha + 1 < beta;
+
         let entry = self.tt.probe(self.board.zobrist, 0);
-        let hash_move = entry.and_then(|e| Some(e.best_move));
+        let hash_move = entry.map(|e| e.best_move);
+        let tt_pv = pv_node || entry.is_some_and(|e| e.pv);
 
         if let Some(e) = entry
             && let Some(entry_score) = e.probe(&mut alpha, &mut beta, ply)
@@ -595,22 +803,19 @@ impl Searcher {
             return entry_score;
         }
 
-        let color = self.board.side_to_move;
-        let static_eval = match color {
-            Color::White => self.board.evaluate(),
-            Color::Black => -self.board.evaluate(),
-        };
-
-        if self.is_draw() {
-            return Searcher::get_draw_score(static_eval);
+        if self.is_draw(ply) {
+            return self.draw_score();
         }
 
-        if self.time_to_stop(false) || ply >= Searcher::MAX_PLY {
-            return static_eval;
+        let static_eval = self.static_eval(entry);
+        let eval = entry.map_or(static_eval, |e| e.eval_estimate(static_eval));
+
+        if self.time_to_stop(false) || ply >= MAX_PLY {
+            return eval;
         }
 
         // stand-pat score
-        let mut best_score = static_eval;
+        let mut best_score = eval;
 
         // stand-pat cutoff
         if best_score >= beta {
@@ -622,11 +827,11 @@ impl Searcher {
 
         // delta pruning
         const DELTA_MARGIN: i16 = 75;
-        if static_eval + Board::PIECE_VALUES[Piece::Queen as usize] + DELTA_MARGIN < alpha {
+        if eval + Board::PIECE_VALUES[Piece::Queen as usize] + DELTA_MARGIN < alpha {
             return alpha;
         }
 
-        let max_mate = Searcher::CHECKMATE_SCORE - ply as i16;
+        let max_mate = CHECKMATE_SCORE - ply as i16;
 
         // if in check we must generate all evasions (not only captures)
         let move_list = if in_check {
@@ -681,8 +886,10 @@ impl Searcher {
                 self.board.zobrist,
                 0,
                 best_score,
+                static_eval,
                 best_move,
                 Bound::from_score(best_score, alpha, beta),
+                tt_pv,
                 self.age,
                 ply,
             );
@@ -698,35 +905,77 @@ impl Searcher {
     }
 
     pub fn new(
-        board: Board,
-        history: ZobristHistory,
+        game: &Game,
         search_mode: &Arc<AtomicSearchMode>,
         history_heuristic: &Arc<HistoryHeuristics>,
         age: u8,
         tt: &Arc<TT>,
-    ) -> Searcher {
+        tablebases: &Arc<Tablebases>,
+    ) -> Searcher<E> {
+        let board = game.board().clone();
+
+        // positions before the last capture or pawn move can't be repeated
+        let positions = game.positions();
+        let reversible = positions.len().min(board.halfmove_clock as usize + 1);
+        let history = positions[positions.len() - reversible..]
+            .iter()
+            .copied()
+            .collect();
+
         Searcher {
             board,
             history,
             pv_table: PvTable {
-                pv: [PvLine::new(); Searcher::MAX_PLY],
+                pv: [PvLine::new(); MAX_PLY],
             },
             prev_pv: PvLine::new(),
 
             nodes: 0,
+            searched_nodes: 0,
             seldepth: 0,
+            score: 0,
+            silent: false,
+            contempt: 0,
+            root_color: game.board().side_to_move,
 
             time: None,
             time_control: TimeControl::Infinite,
             search_mode: Arc::clone(search_mode),
 
-            killers: [[None; 2]; Searcher::MAX_PLY],
+            killers: [[None; 2]; MAX_PLY],
             history_heuristic: Arc::clone(history_heuristic),
             age,
             tt: Arc::clone(tt),
+            evaluator: E::new(game.board()),
+
+            tablebases: Arc::clone(tablebases),
+            tb_cardinality: 0,
+            tb_hits: 0,
         }
     }
 
+    /// Searches without sending `info`, for self-play
+    pub fn set_silent(&mut self, silent: bool) {
+        self.silent = silent;
+    }
+
+    /// What avoiding a draw is worth to the side to move, in centipawns. Positive contempt
+    /// plays on in equal positions, negative goes for the draw
+    pub fn set_contempt(&mut self, contempt: i16) {
+        self.contempt = contempt;
+    }
+
+    /// Score of the best move found by the last search, from the point of view of the side to
+    /// move
+    pub fn score(&self) -> i16 {
+        self.score
+    }
+
+    /// Nodes visited by the last search, over every iteration
+    pub fn nodes(&self) -> usize {
+        self.searched_nodes + self.nodes
+    }
+
     #[inline(always)]
     fn ctx(&self, ply: usize, hash_move: Option<Move>) -> SearchContext<'_> {
         SearchContext {
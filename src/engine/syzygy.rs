@@ -0,0 +1,1323 @@
+//! Syzygy endgame tablebase probing.
+//!
+//! This is a port of the probing code Stockfish uses (originally by Ronald de Man). WDL tables
+//! (`.rtbw`) store win/draw/loss for every position of a material configuration, DTZ tables
+//! (`.rtbz`) store the distance to the next capture or pawn move (zeroing move) needed to convert
+//! it. Neither stores positions where a capture is available or castling is possible, so captures
+//! are resolved with a small search before looking the position up.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{LazyLock, OnceLock},
+};
+
+use crate::chess::{attacks::tables, *};
+
+/// Biggest tables that exist (7 men)
+pub const TB_PIECES: usize = 7;
+
+// ranks the root moves so that certain wins outrank wins that might run into the 50-move rule
+const MAX_DTZ: i32 = 1 << 18;
+
+/// Result of a position from the point of view of the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(i8)]
+pub enum Wdl {
+    Loss = -2,
+    /// Loss, but the 50-move rule saves the game
+    BlessedLoss = -1,
+    Draw = 0,
+    /// Win, but the 50-move rule gets in the way
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_i32(value: i32) -> Wdl {
+        match value {
+            ..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            2.. => Wdl::Win,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TableType {
+    Wdl,
+    Dtz,
+}
+
+impl TableType {
+    fn magic(self) -> [u8; 4] {
+        match self {
+            TableType::Wdl => [0x71, 0xE8, 0x23, 0x5D],
+            TableType::Dtz => [0xD7, 0x66, 0x0C, 0xA5],
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            TableType::Wdl => "rtbw",
+            TableType::Dtz => "rtbz",
+        }
+    }
+}
+
+struct TbFlag;
+impl TbFlag {
+    const STM: u8 = 1;
+    const MAPPED: u8 = 2;
+    const WIN_PLIES: u8 = 4;
+    const LOSS_PLIES: u8 = 8;
+    const WIDE: u8 = 16;
+    const SINGLE_VALUE: u8 = 128;
+}
+
+/// Lookup tables used to turn piece placements into table indices
+struct Encoding {
+    binomial: [[u64; BOARD_SIZE]; TB_PIECES],
+    // squares below the a1-h8 diagonal to 0..27
+    map_b1h1h7: [u64; BOARD_SIZE],
+    // the a1-d1-d4 triangle to 0..9, diagonal last
+    map_a1d1d4: [u64; BOARD_SIZE],
+    // the 462 legal placements of two kings with the first one in the a1-d1-d4 triangle
+    map_kk: [[u64; BOARD_SIZE]; 10],
+    // a2-h7 to 0..47, higher towards the edges and the lower ranks
+    map_pawns: [usize; BOARD_SIZE],
+    lead_pawn_idx: [[u64; BOARD_SIZE]; TB_PIECES],
+    lead_pawns_size: [[u64; 4]; TB_PIECES],
+}
+
+fn rank_of(square: Square) -> i32 {
+    (square / BOARD_WIDTH as Square) as i32
+}
+
+fn file_of(square: Square) -> i32 {
+    (square % BOARD_WIDTH as Square) as i32
+}
+
+fn off_a1h8(square: Square) -> i32 {
+    rank_of(square) - file_of(square)
+}
+
+static ENCODING: LazyLock<Encoding> = LazyLock::new(|| {
+    let mut encoding = Encoding {
+        binomial: [[0; BOARD_SIZE]; TB_PIECES],
+        map_b1h1h7: [0; BOARD_SIZE],
+        map_a1d1d4: [0; BOARD_SIZE],
+        map_kk: [[0; BOARD_SIZE]; 10],
+        map_pawns: [0; BOARD_SIZE],
+        lead_pawn_idx: [[0; BOARD_SIZE]; TB_PIECES],
+        lead_pawns_size: [[0; 4]; TB_PIECES],
+    };
+
+    let mut code = 0;
+    for square in 0..BOARD_SIZE as Square {
+        if off_a1h8(square) < 0 {
+            encoding.map_b1h1h7[square as usize] = code;
+            code += 1;
+        }
+    }
+
+    let mut diagonal = Vec::new();
+    code = 0;
+    for square in 0..=27 as Square {
+        if off_a1h8(square) < 0 && file_of(square) <= 3 {
+            encoding.map_a1d1d4[square as usize] = code;
+            code += 1;
+        } else if off_a1h8(square) == 0 && file_of(square) <= 3 {
+            diagonal.push(square);
+        }
+    }
+    for square in diagonal {
+        encoding.map_a1d1d4[square as usize] = code;
+        code += 1;
+    }
+
+    let mut both_on_diagonal = Vec::new();
+    code = 0;
+    for idx in 0..10 {
+        for s1 in 0..=27 as Square {
+            // b1 is the only square mapped to 0
+            if encoding.map_a1d1d4[s1 as usize] != idx || (idx == 0 && s1 != 1) {
+                continue;
+            }
+
+            for s2 in 0..BOARD_SIZE as Square {
+                if (tables::KING_ATTACKS[s1 as usize] | bit(s1)) & bit(s2) != 0 {
+                    continue; // kings can't touch
+                } else if off_a1h8(s1) == 0 && off_a1h8(s2) > 0 {
+                    continue; // first on the diagonal, second above
+                } else if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {
+                    both_on_diagonal.push((idx, s2));
+                } else {
+                    encoding.map_kk[idx as usize][s2 as usize] = code;
+                    code += 1;
+                }
+            }
+        }
+    }
+    for (idx, s2) in both_on_diagonal {
+        encoding.map_kk[idx as usize][s2 as usize] = code;
+        code += 1;
+    }
+    debug_assert_eq!(code, 462);
+
+    encoding.binomial[0][0] = 1;
+    for n in 1..BOARD_SIZE {
+        for k in 0..TB_PIECES.min(n + 1) {
+            encoding.binomial[k][n] = if k > 0 {
+                encoding.binomial[k - 1][n - 1]
+            } else {
+                0
+            } + if k < n {
+                encoding.binomial[k][n - 1]
+            } else {
+                0
+            };
+        }
+    }
+
+    // squares left for the other pawns with the leading one on each square, 47 on a2
+    let mut available_squares = 48;
+    for lead_pawns in 1..TB_PIECES {
+        for file in 0..4 {
+            let mut idx = 0;
+            for rank in 1..7 {
+                let square = to_square(rank, file) as usize;
+                if lead_pawns == 1 {
+                    encoding.map_pawns[square] = available_squares - 1;
+                    encoding.map_pawns[square ^ 7] = available_squares - 2;
+                    available_squares -= 2;
+                }
+                encoding.lead_pawn_idx[lead_pawns][square] = idx;
+                idx += encoding.binomial[lead_pawns - 1][encoding.map_pawns[square]];
+            }
+            encoding.lead_pawns_size[lead_pawns][file as usize] = idx;
+        }
+    }
+
+    encoding
+});
+
+/// How the values of one side and file of a table are stored and compressed
+#[derive(Default, Clone)]
+struct PairsData {
+    flags: u8,
+    // table pieces in encoding order: 1..6 for the strong side, 9..14 for the weak one
+    pieces: [u8; TB_PIECES],
+    group_len: [usize; TB_PIECES + 1],
+    group_idx: [u64; TB_PIECES + 1],
+
+    sizeof_block: usize,
+    span: u64,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    block_length: usize,
+    block_length_size: usize,
+    blocks_num: usize,
+    data: usize,
+
+    max_sym_len: u8,
+    min_sym_len: u8, // or the value itself, for single value tables
+    lowest_sym: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u8>,
+    btree: usize,
+
+    map_idx: [usize; 4],
+}
+
+/// Material of a table, with the strong side (written first in the file name) as white
+struct TableMaterial {
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    // pawns of the leading color (the one with fewer, but some, pawns) and of the other one
+    pawn_count: [usize; 2],
+    // both sides have the same pieces, so only white to move is stored
+    symmetric: bool,
+}
+
+impl TableMaterial {
+    fn from_name(name: &str) -> Option<TableMaterial> {
+        let (white, black) = name.split_once('v')?;
+        let count = |side: &str, chr| side.chars().filter(|&c| c == chr).count();
+
+        let pawns = [count(white, 'P'), count(black, 'P')];
+        let lead_white = pawns[1] == 0 || (pawns[0] > 0 && pawns[1] >= pawns[0]);
+
+        Some(TableMaterial {
+            piece_count: white.len() + black.len(),
+            has_pawns: pawns[0] + pawns[1] > 0,
+            has_unique_pieces: "PNBRQ"
+                .chars()
+                .any(|chr| count(white, chr) == 1 || count(black, chr) == 1),
+            pawn_count: if lead_white {
+                pawns
+            } else {
+                [pawns[1], pawns[0]]
+            },
+            symmetric: white == black,
+        })
+    }
+}
+
+struct Table {
+    data: memmap2::Mmap,
+    table_type: TableType,
+    material: TableMaterial,
+    // [side to move][file of the leading pawn]
+    items: [[PairsData; 4]; 2],
+    map: usize,
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N], &'static str> {
+    data.get(offset..offset + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("truncated tablebase file")
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8, &'static str> {
+    Ok(read::<1>(data, offset)?[0])
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, &'static str> {
+    Ok(u16::from_le_bytes(read(data, offset)?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, &'static str> {
+    Ok(u32::from_le_bytes(read(data, offset)?))
+}
+
+impl Table {
+    fn new(name: &str, data: memmap2::Mmap, table_type: TableType) -> Result<Table, &'static str> {
+        let material = TableMaterial::from_name(name).ok_or("invalid tablebase name")?;
+        if read::<4>(&data, 0)? != table_type.magic() {
+            return Err("invalid tablebase magic");
+        }
+
+        let mut table = Table {
+            data,
+            table_type,
+            material,
+            items: Default::default(),
+            map: 0,
+        };
+        table.init()?;
+
+        Ok(table)
+    }
+
+    fn sides(&self) -> usize {
+        if self.table_type == TableType::Wdl && !self.material.symmetric {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn get(&self, stm: usize, file: usize) -> &PairsData {
+        &self.items[stm % self.sides()][if self.material.has_pawns { file } else { 0 }]
+    }
+
+    fn init(&mut self) -> Result<(), &'static str> {
+        let flags = read_u8(&self.data, 4)?;
+        let material = &self.material;
+        if (flags & 2 != 0) != material.has_pawns || (flags & 1 != 0) == material.symmetric {
+            return Err("tablebase doesn't match its name");
+        }
+
+        let mut offset = 5;
+        let sides = self.sides();
+        let files = if material.has_pawns { 4 } else { 1 };
+        let pawns_on_both_sides = material.has_pawns && material.pawn_count[1] > 0;
+
+        for file in 0..files {
+            let order_byte = read_u8(&self.data, offset)?;
+            let order_byte2 = if pawns_on_both_sides {
+                read_u8(&self.data, offset + 1)?
+            } else {
+                0xFF
+            };
+            let order = [
+                [order_byte & 0xF, order_byte2 & 0xF],
+                [order_byte >> 4, order_byte2 >> 4],
+            ];
+            offset += 1 + pawns_on_both_sides as usize;
+
+            for k in 0..self.material.piece_count {
+                let byte = read_u8(&self.data, offset)?;
+                for side in 0..sides {
+                    self.items[side][file].pieces[k] =
+                        if side == 1 { byte >> 4 } else { byte & 0xF };
+                }
+                offset += 1;
+            }
+
+            for (side, &order) in order.iter().enumerate().take(sides) {
+                self.set_groups(side, file, order);
+            }
+        }
+
+        offset += offset & 1; // word alignment
+
+        for file in 0..files {
+            for side in 0..sides {
+                offset = self.set_sizes(side, file, offset)?;
+            }
+        }
+
+        if self.table_type == TableType::Dtz {
+            offset = self.set_dtz_map(offset, files)?;
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                d.sparse_index = offset;
+                offset += d.sparse_index_size * 6;
+            }
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                d.block_length = offset;
+                offset += d.block_length_size * 2;
+            }
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                offset = (offset + 0x3F) & !0x3F; // 64 byte alignment
+                let d = &mut self.items[side][file];
+                d.data = offset;
+                offset += d.blocks_num * d.sizeof_block;
+
+                // single value tables have no blocks, so the padding may be missing
+                if d.blocks_num > 0 && offset > self.data.len() {
+                    return Err("truncated tablebase file");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Splits the pieces into groups of pieces encoded together (the leading pieces or pawns,
+    /// the remaining pawns and then every run of identical pieces), and computes the factor of
+    /// each group in the final index
+    fn set_groups(&mut self, side: usize, file: usize, order: [u8; 2]) {
+        let material = &self.material;
+        let d = &mut self.items[side][file];
+
+        let mut n = 0;
+        let mut first_len: i32 = if material.has_pawns {
+            0
+        } else if material.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        d.group_len[0] = 1;
+        for i in 1..material.piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let pawns_on_both_sides = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = if pawns_on_both_sides { 2 } else { 1 };
+        let mut free_squares = 64
+            - d.group_len[0]
+            - if pawns_on_both_sides {
+                d.group_len[1]
+            } else {
+                0
+            };
+        let mut idx: u64 = 1;
+
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                d.group_idx[0] = idx;
+                idx *= if material.has_pawns {
+                    ENCODING.lead_pawns_size[d.group_len[0]][file]
+                } else if material.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                d.group_idx[1] = idx;
+                idx *= ENCODING.binomial[d.group_len[1]][48 - d.group_len[0]];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= ENCODING.binomial[d.group_len[next]][free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        d.group_idx[n] = idx;
+    }
+
+    fn set_sizes(
+        &mut self,
+        side: usize,
+        file: usize,
+        offset: usize,
+    ) -> Result<usize, &'static str> {
+        let data = &self.data;
+        let d = &mut self.items[side][file];
+
+        d.flags = read_u8(data, offset)?;
+        if d.flags & TbFlag::SINGLE_VALUE != 0 {
+            d.min_sym_len = read_u8(data, offset + 1)?;
+            return Ok(offset + 2);
+        }
+
+        // the last group index is the number of positions in the table
+        let tb_size = d.group_idx[d.group_len.iter().position(|&len| len == 0).unwrap()];
+
+        d.sizeof_block = 1 << read_u8(data, offset + 1)?;
+        d.span = 1 << read_u8(data, offset + 2)?;
+        d.sparse_index_size = tb_size.div_ceil(d.span) as usize;
+        let padding = read_u8(data, offset + 3)? as usize;
+        d.blocks_num = read_u32(data, offset + 4)? as usize;
+        d.block_length_size = d.blocks_num + padding;
+        d.max_sym_len = read_u8(data, offset + 8)?;
+        d.min_sym_len = read_u8(data, offset + 9)?;
+        d.lowest_sym = offset + 10;
+
+        if d.min_sym_len == 0 || d.max_sym_len < d.min_sym_len {
+            return Err("invalid tablebase symbol lengths");
+        }
+
+        // canonical huffman code: longer symbols have lower values, so base64[i] is the lowest
+        // code of length min_sym_len + i, left aligned to 64 bits
+        let lengths = (d.max_sym_len - d.min_sym_len + 1) as usize;
+        d.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = read_u16(data, d.lowest_sym + 2 * i)? as u64;
+            let next_lowest = read_u16(data, d.lowest_sym + 2 * (i + 1))? as u64;
+            d.base64[i] = (d.base64[i + 1] + lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (i, base) in d.base64.iter_mut().enumerate() {
+            let shift = 64 - i as u32 - d.min_sym_len as u32;
+            *base = base.checked_shl(shift).unwrap_or(0);
+        }
+
+        let offset = d.lowest_sym + 2 * lengths;
+        let symbols = read_u16(data, offset)? as usize;
+        d.btree = offset + 2;
+        if d.btree + 3 * symbols > data.len() {
+            return Err("truncated tablebase file");
+        }
+
+        // every symbol stands for a pair of symbols (recursive pairing), symlen is how many
+        // values it expands to minus one
+        d.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                set_symlen(data, d.btree, &mut d.symlen, sym, &mut visited);
+            }
+        }
+
+        Ok(d.btree + 3 * symbols + (symbols & 1))
+    }
+
+    fn set_dtz_map(&mut self, mut offset: usize, files: usize) -> Result<usize, &'static str> {
+        self.map = offset;
+
+        for file in 0..files {
+            let flags = self.items[0][file].flags;
+            if flags & TbFlag::MAPPED == 0 {
+                continue;
+            }
+
+            if flags & TbFlag::WIDE != 0 {
+                offset += offset & 1;
+                for i in 0..4 {
+                    self.items[0][file].map_idx[i] = (offset - self.map) / 2 + 1;
+                    offset += 2 * read_u16(&self.data, offset)? as usize + 2;
+                }
+            } else {
+                for i in 0..4 {
+                    self.items[0][file].map_idx[i] = offset - self.map + 1;
+                    offset += read_u8(&self.data, offset)? as usize + 1;
+                }
+            }
+        }
+
+        Ok(offset + (offset & 1))
+    }
+
+    fn btree_left(&self, d: &PairsData, sym: usize) -> usize {
+        let lr = &self.data[d.btree + 3 * sym..];
+        ((lr[1] as usize & 0xF) << 8) | lr[0] as usize
+    }
+
+    fn btree_right(&self, d: &PairsData, sym: usize) -> usize {
+        let lr = &self.data[d.btree + 3 * sym..];
+        ((lr[2] as usize) << 4) | (lr[1] as usize >> 4)
+    }
+
+    fn block_length(&self, d: &PairsData, block: usize) -> i64 {
+        read_u16(&self.data, d.block_length + 2 * block).unwrap_or(0) as i64
+    }
+
+    fn read_be<const N: usize>(&self, offset: usize) -> u64 {
+        // past the end of the file the stream only feeds unused bits
+        let mut bytes = [0u8; 8];
+        for (i, byte) in bytes[8 - N..].iter_mut().enumerate() {
+            *byte = self.data.get(offset + i).copied().unwrap_or(0);
+        }
+        u64::from_be_bytes(bytes)
+    }
+
+    /// Finds the value stored at `idx`
+    fn decompress_pairs(&self, d: &PairsData, idx: u64) -> i32 {
+        if d.flags & TbFlag::SINGLE_VALUE != 0 {
+            return d.min_sym_len as i32;
+        }
+
+        // the sparse index points to the block and offset of every `span`th value, starting
+        // from the middle of the span, so only a few blocks have to be walked
+        let k = (idx / d.span) as usize;
+        let entry = d.sparse_index + 6 * k;
+        let mut block = read_u32(&self.data, entry).unwrap_or(0) as usize;
+        let mut offset = read_u16(&self.data, entry + 4).unwrap_or(0) as i64;
+        offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+
+        while offset < 0 {
+            block -= 1;
+            offset += self.block_length(d, block) + 1;
+        }
+        while offset > self.block_length(d, block) {
+            offset -= self.block_length(d, block) + 1;
+            block += 1;
+        }
+
+        // walk the huffman coded symbols of the block until the one containing our value
+        let mut ptr = d.data + block * d.sizeof_block;
+        let mut buf64 = self.read_be::<8>(ptr);
+        ptr += 8;
+        let mut buf64_size = 64;
+        let min_sym_len = d.min_sym_len as usize;
+
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while len + 1 < d.base64.len() && buf64 < d.base64[len] {
+                len += 1;
+            }
+
+            sym = ((buf64 - d.base64[len]) >> (64 - len - min_sym_len)) as u16;
+            let lowest = read_u16(&self.data, d.lowest_sym + 2 * len).unwrap_or(0);
+            sym = sym.wrapping_add(lowest);
+
+            let sym_values = d.symlen.get(sym as usize).map_or(1, |&len| len as i64 + 1);
+            if offset < sym_values {
+                break;
+            }
+
+            offset -= sym_values;
+            len += min_sym_len;
+            buf64 <<= len;
+            buf64_size -= len;
+            if buf64_size <= 32 {
+                buf64_size += 32;
+                buf64 |= self.read_be::<4>(ptr) << (64 - buf64_size);
+                ptr += 4;
+            }
+        }
+
+        // expand the pairs until the leaf holding the value
+        let mut sym = sym as usize;
+        while d.symlen.get(sym).is_some_and(|&len| len != 0) {
+            let left = self.btree_left(d, sym);
+            if offset < d.symlen[left] as i64 + 1 {
+                sym = left;
+            } else {
+                offset -= d.symlen[left] as i64 + 1;
+                sym = self.btree_right(d, sym);
+            }
+        }
+
+        self.btree_left(d, sym) as i32
+    }
+
+    /// Turns a stored value into a WDL score, or DTZ plies given the WDL of the position
+    fn map_score(&self, file: usize, value: i32, wdl: i32) -> i32 {
+        if self.table_type == TableType::Wdl {
+            return value - 2;
+        }
+
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+        let d = self.get(0, file);
+        let mut value = value;
+
+        if d.flags & TbFlag::MAPPED != 0 {
+            let idx = d.map_idx[WDL_MAP[(wdl + 2) as usize]] + value as usize;
+            value = if d.flags & TbFlag::WIDE != 0 {
+                read_u16(&self.data, self.map + 2 * idx).unwrap_or(0) as i32
+            } else {
+                read_u8(&self.data, self.map + idx).unwrap_or(0) as i32
+            };
+        }
+
+        // values are stored in moves unless the table says they're plies
+        if (wdl == 2 && d.flags & TbFlag::WIN_PLIES == 0)
+            || (wdl == -2 && d.flags & TbFlag::LOSS_PLIES == 0)
+            || wdl == 1
+            || wdl == -1
+        {
+            value *= 2;
+        }
+
+        value + 1
+    }
+
+    /// Looks the position up. Returns `None` if this is a DTZ table that only stores the other
+    /// side to move.
+    fn probe(&self, board: &Board, black_stronger: bool, wdl: i32) -> Option<i32> {
+        let material = &self.material;
+        let encoding = &*ENCODING;
+
+        // tables are stored with the strong side as white, and symmetric ones only with white to
+        // move, so flip colors and squares to look the position up otherwise
+        let flip = black_stronger || (material.symmetric && board.side_to_move == Color::Black);
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = flip as usize ^ board.side_to_move as usize;
+
+        let table_piece = |square: Square| {
+            let (piece_type, color) = board.pieces[square as usize];
+            ((piece_type as u8 + 1) | (color as u8) << 3) ^ flip_color
+        };
+
+        let mut squares = [0 as Square; TB_PIECES];
+        let mut pieces = [0u8; TB_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0u64;
+        let mut lead_pawns_count = 0;
+        let mut file = 0;
+
+        if material.has_pawns {
+            // the pawns are first in the piece sequence, and their color is the leading one
+            let lead_color = (self.get(0, 0).pieces[0] ^ flip_color) >> 3;
+            lead_pawns = board.bitboards[lead_color as usize][Piece::Pawn as usize];
+            for square in lead_pawns.ones_iter() {
+                squares[size] = square ^ flip_squares;
+                size += 1;
+            }
+            lead_pawns_count = size;
+
+            let lead = (0..lead_pawns_count)
+                .max_by_key(|&i| encoding.map_pawns[squares[i] as usize])
+                .unwrap();
+            squares.swap(0, lead);
+            file = file_of(squares[0]).min(7 - file_of(squares[0])) as usize;
+        }
+
+        if self.table_type == TableType::Dtz
+            && (self.get(stm, file).flags & TbFlag::STM) as usize != stm
+            && (!material.symmetric || material.has_pawns)
+        {
+            return None;
+        }
+
+        let occupancy = (board.occupancies[0] | board.occupancies[1]) ^ lead_pawns;
+        for square in occupancy.ones_iter() {
+            squares[size] = square ^ flip_squares;
+            pieces[size] = table_piece(square);
+            size += 1;
+        }
+
+        let d = self.get(stm, file);
+
+        // reorder the pieces like the table does
+        for i in lead_pawns_count..size - 1 {
+            for j in i + 1..size {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // the leading piece goes on the a-d files
+        if file_of(squares[0]) > 3 {
+            for square in &mut squares[..size] {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx;
+        if material.has_pawns {
+            idx = encoding.lead_pawn_idx[lead_pawns_count][squares[0] as usize];
+            squares[1..lead_pawns_count].sort_by_key(|&square| encoding.map_pawns[square as usize]);
+            for (i, &square) in squares.iter().enumerate().take(lead_pawns_count).skip(1) {
+                idx += encoding.binomial[i][encoding.map_pawns[square as usize]];
+            }
+        } else {
+            // without pawns, the leading piece also goes on ranks 1-4...
+            if rank_of(squares[0]) > 3 {
+                for square in &mut squares[..size] {
+                    *square ^= 56;
+                }
+            }
+
+            // ...and the first leading piece off the a1-h8 diagonal below it
+            for i in 0..d.group_len[0] {
+                let off = off_a1h8(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in &mut squares[i..size] {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            let [s0, s1, s2] = [squares[0], squares[1], squares[2]];
+            let map_b1h1h7 = |square: Square| encoding.map_b1h1h7[square as usize];
+            let rank = |square: Square| rank_of(square) as u64;
+
+            idx = if material.has_unique_pieces {
+                let adjust1 = (s1 > s0) as u64;
+                let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+
+                if off_a1h8(s0) != 0 {
+                    (encoding.map_a1d1d4[s0 as usize] * 63 + (s1 as u64 - adjust1)) * 62 + s2 as u64
+                        - adjust2
+                } else if off_a1h8(s1) != 0 {
+                    (6 * 63 + rank(s0) * 28 + map_b1h1h7(s1)) * 62 + s2 as u64 - adjust2
+                } else if off_a1h8(s2) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(s0) * 7 * 28
+                        + (rank(s1) - adjust1) * 28
+                        + map_b1h1h7(s2)
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(s0) * 7 * 6
+                        + (rank(s1) - adjust1) * 6
+                        + (rank(s2) - adjust2)
+                }
+            } else {
+                encoding.map_kk[encoding.map_a1d1d4[s0 as usize] as usize][s1 as usize]
+            };
+        }
+
+        // the rest of the groups, each one as a combination of the squares left
+        idx *= d.group_idx[0];
+        let mut group_start = d.group_len[0];
+        let mut remaining_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let group_end = group_start + d.group_len[next];
+            squares[group_start..group_end].sort();
+
+            let mut n = 0;
+            for i in 0..d.group_len[next] {
+                let square = squares[group_start + i];
+                let adjust = squares[..group_start]
+                    .iter()
+                    .filter(|&&previous| square > previous)
+                    .count();
+                n += encoding.binomial[i + 1]
+                    [square as usize - adjust - 8 * remaining_pawns as usize];
+            }
+
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            group_start = group_end;
+            next += 1;
+        }
+
+        // the factor after the last group is the size of the table
+        debug_assert!(idx < d.group_idx[next], "tablebase index out of range");
+        Some(self.map_score(file, self.decompress_pairs(d, idx), wdl))
+    }
+}
+
+/// Computes how many values a symbol expands to (minus one), children first
+fn set_symlen(data: &[u8], btree: usize, symlen: &mut [u8], sym: usize, visited: &mut [bool]) {
+    visited[sym] = true; // the tree is acyclic, so it can be set right away
+
+    let lr = &data[btree + 3 * sym..];
+    let right = ((lr[2] as usize) << 4) | (lr[1] as usize >> 4);
+    if right == 0xFFF {
+        return; // a leaf, the value itself
+    }
+    let left = ((lr[1] as usize & 0xF) << 8) | lr[0] as usize;
+
+    for child in [left, right] {
+        if child < visited.len() && !visited[child] {
+            set_symlen(data, btree, symlen, child, visited);
+        }
+    }
+
+    let child_len = |child: usize| symlen.get(child).copied().unwrap_or(0);
+    symlen[sym] = child_len(left)
+        .wrapping_add(child_len(right))
+        .wrapping_add(1);
+}
+
+/// What a table has stored for a position
+enum TableProbe {
+    Value(i32),
+    /// DTZ tables only store one side to move, and this is the other one
+    ChangeStm,
+}
+
+/// A table file, opened the first time it's probed
+struct TableFile {
+    path: PathBuf,
+    table: OnceLock<Option<Table>>,
+}
+
+impl TableFile {
+    fn new(path: PathBuf) -> TableFile {
+        TableFile {
+            path,
+            table: OnceLock::new(),
+        }
+    }
+
+    fn get(&self, name: &str, table_type: TableType) -> Option<&Table> {
+        self.table
+            .get_or_init(|| {
+                let file = std::fs::File::open(&self.path).ok()?;
+                // SAFETY: tables are read only, nothing should modify them while we're running
+                let data = unsafe { memmap2::Mmap::map(&file) }.ok()?;
+                Table::new(name, data, table_type).ok()
+            })
+            .as_ref()
+    }
+}
+
+struct TableFiles {
+    wdl: TableFile,
+    dtz: Option<TableFile>,
+}
+
+/// The Syzygy tables found in the `SyzygyPath` directories
+pub struct Tablebases {
+    tables: HashMap<String, TableFiles>,
+    max_pieces: usize,
+    /// Minimum remaining depth to probe in search, to save on disk accesses
+    pub probe_depth: usize,
+    /// Maximum number of pieces to probe, tables with more are ignored
+    pub probe_limit: usize,
+}
+
+impl Default for Tablebases {
+    fn default() -> Self {
+        Tablebases {
+            tables: HashMap::new(),
+            max_pieces: 0,
+            probe_depth: 1,
+            probe_limit: TB_PIECES,
+        }
+    }
+}
+
+/// Name of the pieces of a color, as in table names (e.g. `KRP`)
+fn material_name(board: &Board, color: Color) -> String {
+    const ORDER: [Piece; 6] = [
+        Piece::King,
+        Piece::Queen,
+        Piece::Rook,
+        Piece::Bishop,
+        Piece::Knight,
+        Piece::Pawn,
+    ];
+
+    ORDER
+        .iter()
+        .flat_map(|&piece_type| {
+            let count = board.bitboards[color as usize][piece_type as usize].count_ones();
+            std::iter::repeat_n(piece_type.to_char().to_ascii_uppercase(), count as usize)
+        })
+        .collect()
+}
+
+fn is_capture(mov: Move) -> bool {
+    matches!(
+        mov.get_flags().move_type,
+        MoveType::Capture | MoveType::EnPassantCapture
+    )
+}
+
+fn legal_moves(board: &mut Board) -> MoveList {
+    gen_color_moves(board)
+        .into_iter()
+        .filter(|&mov| {
+            let undo = board.make_move(mov);
+            let legal = is_legal_move(mov, board);
+            board.undo_move(&undo);
+            legal
+        })
+        .collect()
+}
+
+fn is_checkmate(board: &mut Board) -> bool {
+    is_king_attcked(board.side_to_move, board) && legal_moves(board).is_empty()
+}
+
+/// DTZ of a position whose best move zeroes the 50-move counter, from its WDL
+fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        2 => 1,
+        1 => 101,
+        -1 => -101,
+        -2 => -1,
+        _ => 0,
+    }
+}
+
+impl Tablebases {
+    /// Looks for tables in a list of directories, separated like the `PATH` environment variable
+    pub fn new(paths: &str) -> Tablebases {
+        let mut wdl_paths = HashMap::new();
+        let mut dtz_paths = HashMap::new();
+
+        for directory in std::env::split_paths(paths) {
+            let Ok(entries) = std::fs::read_dir(&directory) else {
+                continue;
+            };
+
+            for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+                let (Some(name), Some(extension)) = (
+                    path.file_stem().and_then(|name| name.to_str()),
+                    path.extension().and_then(|extension| extension.to_str()),
+                ) else {
+                    continue;
+                };
+
+                if !Tablebases::is_valid_name(name) {
+                    continue;
+                }
+
+                let paths = if extension == TableType::Wdl.extension() {
+                    &mut wdl_paths
+                } else if extension == TableType::Dtz.extension() {
+                    &mut dtz_paths
+                } else {
+                    continue;
+                };
+                paths.entry(name.to_string()).or_insert(path);
+            }
+        }
+
+        let mut tablebases = Tablebases::default();
+        for (name, path) in wdl_paths {
+            tablebases.max_pieces = tablebases.max_pieces.max(name.len() - 1);
+
+            let files = TableFiles {
+                wdl: TableFile::new(path),
+                dtz: dtz_paths.remove(&name).map(TableFile::new),
+            };
+            tablebases.tables.insert(name, files);
+        }
+
+        tablebases
+    }
+
+    fn is_valid_name(name: &str) -> bool {
+        let Some((white, black)) = name.split_once('v') else {
+            return false;
+        };
+
+        [white, black]
+            .iter()
+            .all(|side| side.starts_with('K') && side[1..].chars().all(|chr| "QRBNP".contains(chr)))
+            && name.len() - 1 <= TB_PIECES
+    }
+
+    /// Number of tables found
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Number of pieces of the biggest tables found
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Positions with this many pieces or fewer can be probed
+    pub fn cardinality(&self) -> usize {
+        self.max_pieces.min(self.probe_limit)
+    }
+
+    fn can_probe(&self, board: &Board) -> bool {
+        let pieces = (board.occupancies[0] | board.occupancies[1]).count_ones() as usize;
+        board.castling_rights == 0 && pieces <= self.cardinality()
+    }
+
+    fn probe_table(&self, board: &Board, table_type: TableType, wdl: i32) -> Option<TableProbe> {
+        if (board.occupancies[0] | board.occupancies[1]).count_ones() == 2 {
+            return Some(TableProbe::Value(0)); // KvK
+        }
+
+        let white = material_name(board, Color::White);
+        let black = material_name(board, Color::Black);
+
+        let (name, black_stronger) = match self.tables.contains_key(&format!("{white}v{black}")) {
+            true => (format!("{white}v{black}"), false),
+            false => (format!("{black}v{white}"), true),
+        };
+
+        let files = self.tables.get(&name)?;
+        let table = match table_type {
+            TableType::Wdl => files.wdl.get(&name, table_type)?,
+            TableType::Dtz => files.dtz.as_ref()?.get(&name, table_type)?,
+        };
+
+        Some(match table.probe(board, black_stronger, wdl) {
+            Some(value) => TableProbe::Value(value),
+            None => TableProbe::ChangeStm,
+        })
+    }
+
+    /// WDL of a position, playing out the captures (and pawn moves with `check_zeroing`) the
+    /// tables don't take into account. Also tells if the best move found is a zeroing one.
+    fn search(&self, board: &mut Board, check_zeroing: bool) -> Option<(i32, bool)> {
+        let moves = legal_moves(board);
+        let mut best_value = -2;
+        let mut move_count = 0;
+
+        for &mov in &moves {
+            let is_pawn = board.pieces[mov.get_from() as usize].0 == Piece::Pawn;
+            if !is_capture(mov) && (!check_zeroing || !is_pawn) {
+                continue;
+            }
+            move_count += 1;
+
+            let undo = board.make_move(mov);
+            let value = self.search(board, false);
+            board.undo_move(&undo);
+
+            let value = -value?.0;
+            if value > best_value {
+                best_value = value;
+                if value >= 2 {
+                    return Some((value, true)); // winning zeroing move
+                }
+            }
+        }
+
+        // when every legal move was searched the stored value isn't needed, and it could be
+        // wrong (the tables don't know about en passant, for example)
+        let no_more_moves = move_count > 0 && move_count == moves.len();
+        let value = if no_more_moves {
+            best_value
+        } else {
+            match self.probe_table(board, TableType::Wdl, 0)? {
+                TableProbe::Value(value) => value,
+                TableProbe::ChangeStm => unreachable!("WDL tables store both sides"),
+            }
+        };
+
+        // DTZ tables store a "don't care" value when the best move is a zeroing one
+        if best_value >= value {
+            Some((best_value, best_value > 0 || no_more_moves))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    fn dtz(&self, board: &mut Board) -> Option<i32> {
+        let (wdl, zeroing_best) = self.search(board, true)?;
+        if wdl == 0 {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        match self.probe_table(board, TableType::Dtz, wdl)? {
+            TableProbe::Value(dtz) => {
+                let cursed = wdl == 1 || wdl == -1;
+                Some((dtz + 100 * cursed as i32) * wdl.signum())
+            }
+            TableProbe::ChangeStm => {
+                // the table stores the other side to move, so search one ply further
+                let mut min_dtz = i32::MAX;
+                for mov in legal_moves(board) {
+                    let is_pawn = board.pieces[mov.get_from() as usize].0 == Piece::Pawn;
+                    let zeroing = is_capture(mov) || is_pawn;
+
+                    let undo = board.make_move(mov);
+                    // for zeroing moves we want the DTZ before doing them, with the sign of the
+                    // position after them (we could be giving the win away)
+                    let dtz = if zeroing {
+                        self.search(board, false)
+                            .map(|(wdl, _)| -dtz_before_zeroing(wdl))
+                    } else {
+                        self.dtz(board).map(|dtz| -dtz)
+                    };
+                    let mates = dtz == Some(1) && is_checkmate(board);
+                    board.undo_move(&undo);
+
+                    let mut dtz = dtz?;
+                    if mates {
+                        min_dtz = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    // skip draws, and only take winning moves if we're winning
+                    if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                        min_dtz = dtz;
+                    }
+                }
+
+                // no legal moves means checkmate
+                Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+            }
+        }
+    }
+
+    /// Win, draw or loss for the side to move, if the position is in the tables
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        let (wdl, _) = self.search(&mut board.clone(), false)?;
+        Some(Wdl::from_i32(wdl))
+    }
+
+    /// Distance to zero in plies: how many plies until the next capture or pawn move that keeps
+    /// the result, positive for wins and negative for losses. 101 or more means the 50-move rule
+    /// turns the result into a draw. Draws are 0.
+    pub fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        self.dtz(&mut board.clone())
+    }
+
+    /// The legal moves that keep the best result possible in a tablebase position.
+    ///
+    /// Moves are ranked with DTZ (or with WDL if the DTZ tables are missing), so certain wins are
+    /// preferred to wins the 50-move rule could spoil. `history` holds the zobrist keys of the
+    /// game positions up to the current one, to tell repetitions apart.
+    pub fn root_moves(&self, board: &Board, history: &[u64]) -> Option<MoveList> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        let mut board = board.clone();
+        let ranked = self
+            .rank_root_moves_dtz(&mut board, history)
+            .or_else(|| self.rank_root_moves_wdl(&mut board, history))?;
+
+        let best_rank = ranked.iter().map(|&(_, rank)| rank).max()?;
+        Some(
+            ranked
+                .into_iter()
+                .filter(|&(_, rank)| rank == best_rank)
+                .map(|(mov, _)| mov)
+                .collect(),
+        )
+    }
+
+    fn rank_root_moves_dtz(&self, board: &mut Board, history: &[u64]) -> Option<Vec<(Move, i32)>> {
+        let cnt50 = board.halfmove_clock as i32;
+        let reversible = &history[history.len().saturating_sub(cnt50 as usize + 1)..];
+        let repeated = reversible
+            .iter()
+            .enumerate()
+            .any(|(i, key)| reversible[i + 1..].contains(key));
+
+        let mut ranked = Vec::new();
+        for mov in legal_moves(board) {
+            let undo = board.make_move(mov);
+            let dtz = if board.halfmove_clock == 0 {
+                self.search(board, false)
+                    .map(|(wdl, _)| dtz_before_zeroing(-wdl))
+            } else if reversible.contains(&board.zobrist) || board.is_fifty_move() {
+                Some(0)
+            } else {
+                // the DTZ of the position after the move, counted from the root
+                self.dtz(board).map(|dtz| -dtz - dtz.signum())
+            };
+            let mates = dtz == Some(2) && is_checkmate(board);
+            board.undo_move(&undo);
+
+            let dtz = if mates { 1 } else { dtz? };
+
+            // all certain wins rank the same, losses too unless a 50-move draw is in sight
+            let rank = if dtz > 0 {
+                if dtz + cnt50 <= 99 && !repeated {
+                    MAX_DTZ
+                } else {
+                    MAX_DTZ / 2 - (dtz + cnt50)
+                }
+            } else if dtz < 0 {
+                if -dtz * 2 + cnt50 < 100 {
+                    -MAX_DTZ
+                } else {
+                    -MAX_DTZ / 2 + (-dtz + cnt50)
+                }
+            } else {
+                0
+            };
+            ranked.push((mov, rank));
+        }
+
+        Some(ranked)
+    }
+
+    fn rank_root_moves_wdl(&self, board: &mut Board, history: &[u64]) -> Option<Vec<(Move, i32)>> {
+        const WDL_TO_RANK: [i32; 5] = [-MAX_DTZ, -MAX_DTZ + 101, 0, MAX_DTZ - 101, MAX_DTZ];
+
+        let reversible = &history[history
+            .len()
+            .saturating_sub(board.halfmove_clock as usize + 1)..];
+
+        let mut ranked = Vec::new();
+        for mov in legal_moves(board) {
+            let undo = board.make_move(mov);
+            let wdl = if board.halfmove_clock != 0
+                && (reversible.contains(&board.zobrist) || board.is_fifty_move())
+            {
+                Some(0)
+            } else {
+                self.search(board, false).map(|(wdl, _)| -wdl)
+            };
+            board.undo_move(&undo);
+
+            ranked.push((mov, WDL_TO_RANK[(wdl? + 2) as usize]));
+        }
+
+        Some(ranked)
+    }
+}
@@ -0,0 +1,275 @@
+//! Breakdown of the evaluation for the `eval` command: every term for each side and what each
+//! piece is worth where it stands.
+
+use std::fmt;
+
+use crate::{
+    chess::*,
+    engine::{
+        endgame::{self, Endgame},
+        evaluation::{EvalTerms, W},
+        material::MaterialEntry,
+        params::Param,
+        pawns::PawnEntry,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Term {
+    Material,
+    Imbalance,
+    Pst,
+    Pawns,
+    BishopPair,
+    Mobility,
+    KingSafety,
+    RookFiles,
+    Outposts,
+    Threats,
+    Hanging,
+}
+
+impl Term {
+    pub const ALL: [Term; 11] = [
+        Term::Material,
+        Term::Imbalance,
+        Term::Pst,
+        Term::Pawns,
+        Term::BishopPair,
+        Term::Mobility,
+        Term::KingSafety,
+        Term::RookFiles,
+        Term::Outposts,
+        Term::Threats,
+        Term::Hanging,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::Imbalance => "Imbalance",
+            Term::Pst => "PST",
+            Term::Pawns => "Pawns",
+            Term::BishopPair => "Bishop pair",
+            Term::Mobility => "Mobility",
+            Term::KingSafety => "King safety",
+            Term::RookFiles => "Rook files",
+            Term::Outposts => "Outposts",
+            Term::Threats => "Threats",
+            Term::Hanging => "Hanging",
+        }
+    }
+}
+
+/// Receives the terms of the evaluation as they're computed
+pub trait Trace {
+    /// False when nothing is recorded, so the work that only feeds the trace can be skipped
+    const ENABLED: bool;
+
+    fn add(&mut self, term: Term, color: Color, value: W);
+
+    /// How many times the weight at `index` of `param` counted for `color`, for the tuner
+    fn feature(&mut self, _param: Param, _index: usize, _color: Color, _count: i16) {}
+}
+
+impl Trace for () {
+    const ENABLED: bool = false;
+
+    #[inline(always)]
+    fn add(&mut self, _: Term, _: Color, _: W) {}
+}
+
+pub struct EvalTrace {
+    /// Midgame and endgame value of each term, for each side from its point of view
+    pub terms: [[W; 2]; Term::ALL.len()],
+    /// From 0 (endgame) to `Board::PHASE_SCALE` (opening)
+    pub phase_ratio: i32,
+    /// Specialized endgame or drawish material adjusting the result, if any
+    pub note: Option<String>,
+    /// From white's point of view in centipawns
+    pub eval: i16,
+    pub pieces: [(Piece, Color); BOARD_SIZE],
+    /// How much the evaluation drops when the piece on the square is removed (kings excluded)
+    pub squares: [Option<i16>; BOARD_SIZE],
+}
+
+impl Trace for EvalTrace {
+    const ENABLED: bool = true;
+
+    fn add(&mut self, term: Term, color: Color, value: W) {
+        self.terms[term as usize][color as usize] += value;
+    }
+}
+
+impl EvalTrace {
+    /// Midgame and endgame values tapered by the phase, like the evaluation does
+    pub fn blend(&self, value: W) -> i16 {
+        ((value.0 as i32 * self.phase_ratio
+            + value.1 as i32 * (Board::PHASE_SCALE as i32 - self.phase_ratio))
+            / Board::PHASE_SCALE as i32) as i16
+    }
+
+    /// Sum of the terms, from white's point of view
+    pub fn total(&self) -> W {
+        let mut total = W(0, 0);
+        for [white, black] in self.terms {
+            total += white;
+            total -= black;
+        }
+        total
+    }
+}
+
+impl Board {
+    /// Evaluates the position recording every term, which is a lot slower than `evaluate`
+    pub fn trace(&self) -> EvalTrace {
+        let material = MaterialEntry::new(self);
+        let pawns = PawnEntry::new(self);
+
+        let mut trace = EvalTrace {
+            terms: [[W(0, 0); 2]; Term::ALL.len()],
+            phase_ratio: material.phase_ratio,
+            note: None,
+            eval: 0,
+            pieces: self.pieces,
+            squares: [None; BOARD_SIZE],
+        };
+        trace.eval = self.evaluate_traced(
+            &material,
+            &pawns,
+            &self.pst_bonus(),
+            EvalTerms::ALL,
+            &mut trace,
+        );
+
+        let mut notes = Vec::new();
+        if let Some((Endgame::Value(_), strong)) = material.endgame {
+            notes.push(format!(
+                "specialized endgame, {strong:?} is the strong side"
+            ));
+        } else {
+            let strong = match trace.blend(trace.total()) > 0 {
+                true => Color::White,
+                false => Color::Black,
+            };
+            let scale = self.scale_factor(&material, strong);
+            if scale != endgame::SCALE_NORMAL {
+                notes.push(format!(
+                    "scaled by {scale}/{} when {strong:?} is better",
+                    endgame::SCALE_NORMAL
+                ));
+            }
+            if self.mating_material() == MatingMaterial::Drawish {
+                notes.push(format!(
+                    "drawish material, divided by {}",
+                    Board::DRAWISH_DIVISOR
+                ));
+            }
+        }
+        if self.fifty_move_left() < Board::FIFTY_MOVE_DAMPING {
+            notes.push(format!(
+                "damped by the 50-move rule to {}/{}",
+                self.fifty_move_left(),
+                Board::FIFTY_MOVE_DAMPING
+            ));
+        }
+        trace.note = (!notes.is_empty()).then(|| notes.join(", "));
+
+        let occupancy =
+            self.occupancies[Color::White as usize] | self.occupancies[Color::Black as usize];
+        for square in occupancy.ones_iter() {
+            let (piece_type, color) = self.pieces[square as usize];
+            if piece_type == Piece::King {
+                continue;
+            }
+
+            let mut without = self.clone();
+            without.toggle_piece(square, piece_type, color);
+            trace.squares[square as usize] = Some(trace.eval - without.evaluate());
+        }
+
+        trace
+    }
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = "+-------------+-------------+-------------+---------------------+";
+        writeln!(f, "{separator}")?;
+        writeln!(
+            f,
+            "|        Term |    White    |    Black    |        Total        |"
+        )?;
+        writeln!(
+            f,
+            "|             |   MG    EG  |   MG    EG  |   MG    EG  Blended |"
+        )?;
+        writeln!(f, "{separator}")?;
+
+        let row = |f: &mut fmt::Formatter<'_>, name: &str, sides: Option<[W; 2]>, total: W| {
+            let side = |w: Option<W>| match w {
+                Some(W(mg, eg)) => format!("{mg:>5} {eg:>5}"),
+                None => format!("{:>5} {:>5}", "-", "-"),
+            };
+            writeln!(
+                f,
+                "| {name:>11} | {} | {} | {:>5} {:>5} {:>7} |",
+                side(sides.map(|[white, _]| white)),
+                side(sides.map(|[_, black]| black)),
+                total.0,
+                total.1,
+                self.blend(total)
+            )
+        };
+
+        for term in Term::ALL {
+            let [white, black] = self.terms[term as usize];
+            row(
+                f,
+                term.name(),
+                Some([white, black]),
+                W(white.0 - black.0, white.1 - black.1),
+            )?;
+        }
+        writeln!(f, "{separator}")?;
+        row(f, "Total", None, self.total())?;
+        writeln!(f, "{separator}")?;
+
+        writeln!(
+            f,
+            "Phase: {}/{} (0 is the endgame)",
+            self.phase_ratio,
+            Board::PHASE_SCALE
+        )?;
+        if let Some(note) = &self.note {
+            writeln!(f, "Note: {note}")?;
+        }
+        writeln!(f, "Evaluation: {} (white side)", self.eval)?;
+        writeln!(f)?;
+
+        // what every piece is worth, white's point of view
+        let line = "+-------".repeat(BOARD_WIDTH) + "+";
+        writeln!(f, "{line}")?;
+        for rank in (0..BOARD_WIDTH as i8).rev() {
+            let mut pieces = String::new();
+            let mut values = String::new();
+            for file in 0..BOARD_WIDTH as i8 {
+                let square = to_square(rank, file);
+                let symbol = match self.pieces[square as usize] {
+                    (piece_type, Color::White) => piece_type.to_char().to_ascii_uppercase(),
+                    (piece_type, Color::Black) => piece_type.to_char(),
+                };
+                pieces += &format!("|{symbol:^7}");
+                values += &match self.squares[square as usize] {
+                    Some(value) => format!("|{value:^7}"),
+                    None => "|       ".to_string(),
+                };
+            }
+            writeln!(f, "{pieces}|")?;
+            writeln!(f, "{values}|")?;
+            writeln!(f, "{line}")?;
+        }
+
+        Ok(())
+    }
+}
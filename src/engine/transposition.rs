@@ -1,21 +1,22 @@
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::{
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering},
+};
 
-use crate::{chess::*, engine::search::Searcher};
+use crate::{chess::*, engine::search};
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
 pub enum Bound {
+    #[default]
     Exact,
     Upper,
     Lower,
 }
 
-impl Default for Bound {
-    fn default() -> Self {
-        Bound::Exact
-    }
-}
-
 impl Bound {
     pub fn from_score(score: i16, alpha: i16, beta: i16) -> Bound {
         if score >= beta {
@@ -37,27 +38,24 @@ impl Bound {
     }
 }
 
-#[repr(C, align(16))]
-#[derive(Default)]
-pub(crate) struct TTEntry {
-    key: AtomicU64, // zobrist
-    data: AtomicU64,
-}
-
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct TTEntryData {
     pub depth: u8,
     score: i16,
+    /// Static evaluation of the position, `EVAL_NONE` if it wasn't computed
+    pub eval: i16,
     bound: Bound,
+    /// Whether the position was ever searched as part of the principal variation
+    pub pv: bool,
     pub best_move: Move,
 }
 
 impl TTEntryData {
     fn decode_mate(score: i16, ply: usize) -> i16 {
-        if score > Searcher::CHECKMATE_THRESHOLD {
+        if score > search::CHECKMATE_THRESHOLD {
             score + ply as i16
-        } else if score < -Searcher::CHECKMATE_THRESHOLD {
+        } else if score < -search::CHECKMATE_THRESHOLD {
             score - ply as i16
         } else {
             score // not a checkmate
@@ -93,163 +91,590 @@ impl TTEntryData {
 
         None
     }
+
+    /// A better guess than the static evaluation `eval` of what the position is worth: the
+    /// stored score if it's exact or a bound past `eval`. Mates are left out, they say nothing
+    /// about the evaluation
+    pub fn eval_estimate(&self, eval: i16) -> i16 {
+        let past = match self.bound {
+            Bound::Exact => true,
+            Bound::Lower => self.score > eval,
+            Bound::Upper => self.score < eval,
+        };
+
+        match past && self.score.abs() < search::CHECKMATE_THRESHOLD {
+            true => self.score,
+            false => eval,
+        }
+    }
 }
 
+/// Entry data packed in a `u64`: [age:5][pv:1][bound:2][depth:8][eval:16][score:16][move:16]
+///
+/// The depth is stored plus one so an all zero entry is an empty one. The key is kept next to
+/// it, see `Cluster`
+#[derive(Clone, Copy)]
+struct TTEntry(u64);
+
 struct EntryEncoding;
 impl EntryEncoding {
-    const DEPTH_SHIFT: u64 = 56;
-    const AGE_SHIFT: u64 = 48;
-    const SCORE_SHIFT: u64 = 32;
-    const BOUND_SHIFT: u64 = 16;
+    const SCORE_SHIFT: u64 = 16;
+    const EVAL_SHIFT: u64 = 32;
+    const DEPTH_SHIFT: u64 = 48;
+    const BOUND_SHIFT: u64 = 56;
+    const PV_SHIFT: u64 = 58;
+    const AGE_SHIFT: u64 = 59;
+    const AGE_MASK: u8 = 0x1F;
 }
 
 impl TTEntry {
     pub fn encode_mate(score: i16, ply: usize) -> i16 {
-        if score > Searcher::CHECKMATE_THRESHOLD {
+        if score > search::CHECKMATE_THRESHOLD {
             score - ply as i16
-        } else if score < -Searcher::CHECKMATE_THRESHOLD {
+        } else if score < -search::CHECKMATE_THRESHOLD {
             score + ply as i16
         } else {
             score // not a checkmate
         }
     }
 
-    pub fn get_depth(&self) -> usize {
-        (self.data.load(Ordering::Acquire) >> EntryEncoding::DEPTH_SHIFT) as usize
+    fn new(
+        depth: usize,
+        score: i16,
+        eval: i16,
+        mov: Move,
+        bound: Bound,
+        pv: bool,
+        age: u8,
+    ) -> TTEntry {
+        TTEntry(
+            (mov.0 as u64)
+                | (score as u16 as u64) << EntryEncoding::SCORE_SHIFT
+                | (eval as u16 as u64) << EntryEncoding::EVAL_SHIFT
+                | (depth as u64 + 1) << EntryEncoding::DEPTH_SHIFT
+                | (bound as u64) << EntryEncoding::BOUND_SHIFT
+                | (pv as u64) << EntryEncoding::PV_SHIFT
+                | ((age & EntryEncoding::AGE_MASK) as u64) << EntryEncoding::AGE_SHIFT,
+        )
+    }
+
+    /// The data folded to 16 bits, what the stored key is XORed with
+    fn check(self) -> u16 {
+        (self.0 ^ self.0 >> 16 ^ self.0 >> 32 ^ self.0 >> 48) as u16
+    }
+
+    fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    fn best_move(self) -> Move {
+        Move(self.0 as u16)
+    }
+
+    fn eval(self) -> i16 {
+        (self.0 >> EntryEncoding::EVAL_SHIFT) as u16 as i16
     }
-    pub fn get_age(&self) -> u8 {
-        ((self.data.load(Ordering::Acquire) >> EntryEncoding::AGE_SHIFT) & 0xFF) as u8
+
+    fn depth(self) -> usize {
+        ((self.0 >> EntryEncoding::DEPTH_SHIFT) as u8).saturating_sub(1) as usize
     }
 
-    pub fn store(&self, key: u64, depth: usize, score: i16, mov: Move, bound: Bound, age: u8) {
-        // pack: [depth:8][age:8][score:16][bound:8][move:16] == 56 bits used
-        let packed = (depth as u64) << EntryEncoding::DEPTH_SHIFT
-            | (age as u64) << EntryEncoding::AGE_SHIFT
-            | (score as u16 as u64) << EntryEncoding::SCORE_SHIFT
-            | (bound as u64) << EntryEncoding::BOUND_SHIFT
-            | (mov.0 as u64);
+    fn pv(self) -> bool {
+        (self.0 >> EntryEncoding::PV_SHIFT) & 1 != 0
+    }
 
-        self.key.store(key, Ordering::Release);
-        self.data.store(packed, Ordering::Release);
+    fn age(self) -> u8 {
+        (self.0 >> EntryEncoding::AGE_SHIFT) as u8
     }
 
-    pub fn load(&self) -> TTEntryData {
-        let data = self.data.load(Ordering::Acquire);
+    /// How many searches ago the entry was written, ages wrap around
+    fn relative_age(self, age: u8) -> u8 {
+        age.wrapping_sub(self.age()) & EntryEncoding::AGE_MASK
+    }
 
+    fn decode(self) -> TTEntryData {
         TTEntryData {
-            depth: (data >> EntryEncoding::DEPTH_SHIFT) as u8,
-            score: (data >> EntryEncoding::SCORE_SHIFT) as u16 as i16,
-            bound: Bound::from_u64((data >> EntryEncoding::BOUND_SHIFT) & 0xFF),
-            best_move: Move((data & 0xFFFF) as u16),
+            depth: self.depth() as u8,
+            score: (self.0 >> EntryEncoding::SCORE_SHIFT) as u16 as i16,
+            eval: self.eval(),
+            bound: Bound::from_u64((self.0 >> EntryEncoding::BOUND_SHIFT) & 0x3),
+            pv: self.pv(),
+            best_move: self.best_move(),
         }
     }
+}
 
-    pub fn get_key(&self) -> u64 {
-        self.key.load(Ordering::Acquire)
+/// Stored when the static evaluation of a position isn't known
+pub const EVAL_NONE: i16 = i16::MIN;
+
+const CLUSTER_SIZE: usize = 3;
+
+/// Three entries in 32 bytes, two clusters to a cache line.
+///
+/// Only 16 bits of each key are kept, the cluster an entry is in already depends on the rest.
+/// They're stored XORed with a fold of the data (`TTEntry::check`), so when two threads writing
+/// the same entry leave the key of one with the data of the other, the key comes out wrong and
+/// the torn entry is a miss
+#[repr(C, align(32))]
+#[derive(Default)]
+struct Cluster {
+    entries: [AtomicU64; CLUSTER_SIZE],
+    keys: [AtomicU16; CLUSTER_SIZE],
+}
+
+const _: () = assert!(size_of::<Cluster>() == 32);
+
+impl Cluster {
+    /// Entry `i` and the key it was stored with, both from a single read of the data
+    fn entry(&self, i: usize) -> (TTEntry, u16) {
+        let entry = TTEntry(self.entries[i].load(Ordering::Relaxed));
+        (entry, self.keys[i].load(Ordering::Relaxed) ^ entry.check())
+    }
+
+    fn set(&self, i: usize, key: u64, entry: TTEntry) {
+        self.keys[i].store(key as u16 ^ entry.check(), Ordering::Relaxed);
+        self.entries[i].store(entry.0, Ordering::Relaxed);
     }
 }
 
-const BUCKET_SIZE: usize = 2;
-type Bucket<const N: usize> = [TTEntry; N];
+/// What probes found and why stores replaced the entry they did
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct TTStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Stores to the entry of the same position
+    pub updates: u64,
+    /// Stores that kept the deeper entry of the same position instead
+    pub kept: u64,
+    pub empty: u64,
+    /// Entries of another position replaced, from an older search or from this one
+    pub aged: u64,
+    pub collisions: u64,
+}
+
+impl fmt::Display for TTStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let probes = self.hits + self.misses;
+        let stores = self.updates + self.kept + self.empty + self.aged + self.collisions;
+        write!(
+            f,
+            "tt probes {probes} hits {} ({:.1}%) misses {} stores {stores} same position {} \
+             kept deeper {} empty {} older search {} collisions {}",
+            self.hits,
+            self.hits as f64 * 100.0 / probes.max(1) as f64,
+            self.misses,
+            self.updates,
+            self.kept,
+            self.empty,
+            self.aged,
+            self.collisions,
+        )
+    }
+}
+
+/// Counters behind `TTStats`, only kept while enabled since every search thread would write to
+/// them
+#[derive(Default)]
+struct StatsCounters {
+    enabled: AtomicBool,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    updates: AtomicU64,
+    kept: AtomicU64,
+    empty: AtomicU64,
+    aged: AtomicU64,
+    collisions: AtomicU64,
+}
+
+impl StatsCounters {
+    fn counters(&self) -> [&AtomicU64; 7] {
+        [
+            &self.hits,
+            &self.misses,
+            &self.updates,
+            &self.kept,
+            &self.empty,
+            &self.aged,
+            &self.collisions,
+        ]
+    }
+
+    #[inline]
+    fn add(&self, counter: impl Fn(&Self) -> &AtomicU64) {
+        if self.enabled.load(Ordering::Relaxed) {
+            counter(self).fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// Saved tables are little endian: the magic `STTF`, the format version of the entries as a
+// `u32`, then `u64`s for the number of entries, the signature of the Zobrist keys and the
+// signature of the evaluator the scores come from, followed by the data of every entry and its
+// stored key as a `u16`
+const FILE_MAGIC: &[u8; 4] = b"STTF";
+const FILE_VERSION: u32 = 6;
+const FILE_HEADER_SIZE: usize = 32;
+const FILE_ENTRY_SIZE: usize = size_of::<u64>() + size_of::<u16>();
+
+/// Entries sampled for the hashfull, the first thousand as the UCI protocol suggests
+const HASHFULL_SAMPLE: usize = 1000;
+
+/// Clusters zeroed by each thread at least, below that starting threads costs more than it saves
+const MIN_CLEAR_CHUNK: usize = (1 << 20) / size_of::<Cluster>();
+
+/// Memory of the table. On Linux it's an anonymous mapping backed by transparent huge pages if
+/// the kernel has them, so the TLB covers much more of the table. Elsewhere, or if the mapping
+/// fails, it's a zeroed heap allocation
+enum TableMemory {
+    #[cfg(target_os = "linux")]
+    Mapped(memmap2::MmapMut),
+    Heap(Box<[Cluster]>),
+}
+
+impl TableMemory {
+    fn new(clusters: usize) -> TableMemory {
+        #[cfg(target_os = "linux")]
+        if let Ok(mapping) = memmap2::MmapMut::map_anon(clusters * size_of::<Cluster>()) {
+            // just advice, without huge pages it's a normal mapping
+            let _ = mapping.advise(memmap2::Advice::HugePage);
+            return TableMemory::Mapped(mapping);
+        }
+
+        // SAFETY: clusters are atomics, all zeros is an empty cluster
+        TableMemory::Heap(unsafe { Box::new_zeroed_slice(clusters).assume_init() })
+    }
+}
+
+impl Deref for TableMemory {
+    type Target = [Cluster];
+
+    #[inline]
+    fn deref(&self) -> &[Cluster] {
+        match self {
+            // SAFETY: the mapping is page aligned and sized for whole clusters, and any bytes
+            // are a valid cluster
+            #[cfg(target_os = "linux")]
+            TableMemory::Mapped(mapping) => unsafe {
+                std::slice::from_raw_parts(
+                    mapping.as_ptr().cast(),
+                    mapping.len() / size_of::<Cluster>(),
+                )
+            },
+            TableMemory::Heap(clusters) => clusters,
+        }
+    }
+}
+
+impl DerefMut for TableMemory {
+    fn deref_mut(&mut self) -> &mut [Cluster] {
+        match self {
+            // SAFETY: as in `deref`, and the mapping is borrowed mutably
+            #[cfg(target_os = "linux")]
+            TableMemory::Mapped(mapping) => unsafe {
+                std::slice::from_raw_parts_mut(
+                    mapping.as_mut_ptr().cast(),
+                    mapping.len() / size_of::<Cluster>(),
+                )
+            },
+            TableMemory::Heap(clusters) => clusters,
+        }
+    }
+}
 
 pub struct TT {
-    table: Box<[Bucket<BUCKET_SIZE>]>,
-    used: AtomicUsize,
-    mask: usize,
+    table: TableMemory,
+    stats: StatsCounters,
 }
 
 impl TT {
     pub fn new(megabytes: usize) -> TT {
         const MIB: usize = 1 << 20;
-        let entry_size = std::mem::size_of::<TTEntry>();
-        let requested_bytes = megabytes * MIB;
+        TT::with_clusters((megabytes * MIB / size_of::<Cluster>()).max(1))
+    }
 
-        let mut entries = requested_bytes / entry_size;
-        entries = entries.next_power_of_two();
+    fn with_clusters(clusters: usize) -> TT {
+        let mut tt = TT {
+            table: TableMemory::new(clusters),
+            stats: StatsCounters::default(),
+        };
+        // the memory is already zero, but writing it now makes the pages fault in from every
+        // thread instead of one at a time during the search
+        tt.clear();
+        tt
+    }
+
+    /// Whether the table could be mapped with huge pages asked for
+    pub fn huge_pages(&self) -> bool {
+        match self.table {
+            #[cfg(target_os = "linux")]
+            TableMemory::Mapped(_) => true,
+            TableMemory::Heap(_) => false,
+        }
+    }
 
-        let table = (0..entries)
-            .map(|_| Bucket::default())
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
+    /// Empties every entry, splitting the table between all the available threads
+    pub fn clear(&mut self) {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = self.table.len().div_ceil(threads).max(MIN_CLEAR_CHUNK);
 
-        TT {
-            table,
-            used: AtomicUsize::new(0),
-            mask: entries - 1,
+        if chunk >= self.table.len() {
+            self.table.fill_with(Cluster::default);
+            return;
         }
+
+        std::thread::scope(|scope| {
+            for clusters in self.table.chunks_mut(chunk) {
+                scope.spawn(|| clusters.fill_with(Cluster::default));
+            }
+        });
     }
 
+    /// Maps the key onto the table with a multiply and a shift, which works for any size and
+    /// uses the high bits, leaving the low ones to tell apart the entries of a cluster
     fn index(&self, key: u64) -> usize {
-        (key as usize) & self.mask
+        ((key as u128 * self.table.len() as u128) >> 64) as usize
+    }
+
+    /// Starts loading the cluster of `key` into the cache, so probing it later doesn't wait on
+    /// memory. Does nothing where there's no prefetch instruction to use
+    #[inline]
+    pub fn prefetch(&self, key: u64) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_MM_HINT_T0, _mm_prefetch};
+
+            let cluster: *const Cluster = &self.table[self.index(key)];
+            // SAFETY: prefetching is only a hint, it can't fault
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(cluster.cast()) };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = key;
     }
 
     pub fn probe(&self, key: u64, depth: usize) -> Option<TTEntryData> {
-        debug_assert!(depth < Searcher::MAX_PLY);
+        debug_assert!(depth < search::MAX_PLY);
 
-        for entry in &self.table[self.index(key)] {
-            if entry.get_key() == key {
-                let data = entry.load();
+        let cluster = &self.table[self.index(key)];
+        for i in 0..CLUSTER_SIZE {
+            let (entry, entry_key) = cluster.entry(i);
+            if !entry.is_empty() && entry_key == key as u16 {
+                let data = entry.decode();
                 if data.depth >= depth as u8 {
+                    self.stats.add(|stats| &stats.hits);
                     return Some(data);
                 }
             }
         }
 
+        self.stats.add(|stats| &stats.misses);
         None
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn store(
         &self,
         key: u64,
         depth: usize,
         score: i16,
+        eval: i16,
         best_move: Move,
         bound: Bound,
+        pv: bool,
         age: u8,
         ply: usize,
     ) {
-        debug_assert!(depth < Searcher::MAX_PLY);
-        debug_assert!(ply < Searcher::MAX_PLY);
+        debug_assert!(depth < search::MAX_PLY);
+        debug_assert!(ply < search::MAX_PLY);
 
-        let bucket = &self.table[self.index(key)];
+        let cluster = &self.table[self.index(key)];
         let score = TTEntry::encode_mate(score, ply);
-
-        // 1. replace same key if deeper
-        for entry in bucket.iter() {
-            if entry.get_key() == key {
-                if depth >= entry.get_depth() {
-                    entry.store(key, depth, score, best_move, bound, age);
+        let age = age & EntryEncoding::AGE_MASK;
+
+        // every entry is read once, so what's kept of it comes from the same write
+        let entries: [_; CLUSTER_SIZE] = std::array::from_fn(|i| cluster.entry(i));
+        let same = (0..CLUSTER_SIZE).find(|&i| {
+            let (entry, entry_key) = entries[i];
+            !entry.is_empty() && entry_key == key as u16
+        });
+
+        let (i, best_move, eval) = match same {
+            Some(i) => {
+                let (entry, _) = entries[i];
+                // keep a deeper result from this search, unless this one is exact
+                if bound != Bound::Exact && depth < entry.depth() && entry.age() == age {
+                    self.stats.add(|stats| &stats.kept);
+                    return;
                 }
-                return;
+                self.stats.add(|stats| &stats.updates);
+                // a search that failed low found no move, the old one is still the best guess,
+                // and the static evaluation doesn't change
+                let best_move = match best_move {
+                    Move(0) => entry.best_move(),
+                    _ => best_move,
+                };
+                let eval = match eval {
+                    EVAL_NONE => entry.eval(),
+                    _ => eval,
+                };
+                (i, best_move, eval)
             }
-        }
+            None => {
+                // replace an empty entry, or the one least worth keeping: a shallow one from
+                // an old search that was never in the PV
+                let i = (0..CLUSTER_SIZE)
+                    .min_by_key(|&i| {
+                        let (entry, _) = entries[i];
+                        match entry.is_empty() {
+                            true => i32::MIN,
+                            false => {
+                                entry.depth() as i32 + 2 * entry.pv() as i32
+                                    - 8 * entry.relative_age(age) as i32
+                            }
+                        }
+                    })
+                    .unwrap();
+
+                let (entry, _) = entries[i];
+                self.stats.add(|stats| match entry {
+                    _ if entry.is_empty() => &stats.empty,
+                    _ if entry.age() != age => &stats.aged,
+                    _ => &stats.collisions,
+                });
+                (i, best_move, eval)
+            }
+        };
 
-        // 2. replace old slot
-        for entry in bucket.iter() {
-            if entry.get_age() != age {
-                entry.store(key, depth, score, best_move, bound, age);
-                self.used.fetch_add(1, Ordering::Relaxed);
-                return;
+        cluster.set(
+            i,
+            key,
+            TTEntry::new(depth, score, eval, best_move, bound, pv, age),
+        );
+    }
+
+    /// Writes the table to `path`, so the work done can be picked up by a later run with `load`.
+    /// `evaluator` identifies the evaluation the scores come from, see `EvaluatorKind::signature`
+    pub fn save(&self, path: &str, evaluator: u64) -> Result<(), &'static str> {
+        let file = File::create(path).map_err(|_| "can't create hash file")?;
+        let mut writer = BufWriter::new(file);
+
+        let mut header = FILE_MAGIC.to_vec();
+        header.extend(FILE_VERSION.to_le_bytes());
+        header.extend(((self.table.len() * CLUSTER_SIZE) as u64).to_le_bytes());
+        header.extend(zobrist_signature().to_le_bytes());
+        header.extend(evaluator.to_le_bytes());
+        debug_assert_eq!(header.len(), FILE_HEADER_SIZE);
+        writer
+            .write_all(&header)
+            .map_err(|_| "can't write hash file")?;
+
+        for cluster in self.table.iter() {
+            for i in 0..CLUSTER_SIZE {
+                let (entry, key) = (
+                    cluster.entries[i].load(Ordering::Relaxed),
+                    cluster.keys[i].load(Ordering::Relaxed),
+                );
+                writer
+                    .write_all(&entry.to_le_bytes())
+                    .and_then(|_| writer.write_all(&key.to_le_bytes()))
+                    .map_err(|_| "can't write hash file")?;
             }
         }
 
-        // 3. replace shallowest
-        let mut min_idx = 0;
-        let mut min_depth = bucket[0].get_depth();
-        for (i, entry) in bucket.iter().enumerate().skip(1) {
-            let depth = entry.get_depth();
-            if depth < min_depth {
-                min_depth = depth;
-                min_idx = i;
+        writer.flush().map_err(|_| "can't write hash file")
+    }
+
+    /// Reads a table written by `save`, with the size it was saved with. Files from another
+    /// version, made with other Zobrist keys or with another evaluator are refused, their
+    /// entries would mean nothing
+    pub fn load(path: &str, evaluator: u64) -> Result<TT, &'static str> {
+        let bytes = std::fs::read(path).map_err(|_| "can't read hash file")?;
+        TT::from_bytes(&bytes, evaluator)
+    }
+
+    fn from_bytes(bytes: &[u8], evaluator: u64) -> Result<TT, &'static str> {
+        let (header, entries) = bytes
+            .split_at_checked(FILE_HEADER_SIZE)
+            .ok_or("hash file too short")?;
+        let field =
+            |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+
+        if &header[0..4] != FILE_MAGIC {
+            return Err("not a hash file");
+        }
+        if u32::from_le_bytes(header[4..8].try_into().unwrap()) != FILE_VERSION {
+            return Err("hash file has another version");
+        }
+        let count = field(8) as usize;
+        if count == 0 || !count.is_multiple_of(CLUSTER_SIZE) {
+            return Err("hash file has an invalid size");
+        }
+        if field(16) != zobrist_signature() {
+            return Err("hash file was made with other Zobrist keys");
+        }
+        if field(24) != evaluator {
+            return Err("hash file was made with another evaluator");
+        }
+        if entries.len() != count * FILE_ENTRY_SIZE {
+            return Err("hash file has the wrong size");
+        }
+
+        let mut entries = entries.chunks_exact(FILE_ENTRY_SIZE).map(|chunk| {
+            let (entry, key) = chunk.split_at(size_of::<u64>());
+            (
+                u64::from_le_bytes(entry.try_into().unwrap()),
+                u16::from_le_bytes(key.try_into().unwrap()),
+            )
+        });
+        let tt = TT::with_clusters(count / CLUSTER_SIZE);
+        for cluster in tt.table.iter() {
+            for i in 0..CLUSTER_SIZE {
+                let (entry, key) = entries.next().unwrap();
+                cluster.entries[i].store(entry, Ordering::Relaxed);
+                cluster.keys[i].store(key, Ordering::Relaxed);
             }
         }
 
-        bucket[min_idx].store(key, depth, score, best_move, bound, age);
+        Ok(tt)
+    }
+
+    /// Permille of the sampled entries written by the search of `age`
+    pub fn get_hashfull(&self, age: u8) -> u16 {
+        let age = age & EntryEncoding::AGE_MASK;
+        let sample = self
+            .table
+            .iter()
+            .flat_map(|cluster| (0..CLUSTER_SIZE).map(|i| cluster.entry(i).0))
+            .take(HASHFULL_SAMPLE);
+
+        let (mut total, mut used) = (0, 0);
+        for entry in sample {
+            total += 1;
+            used += (!entry.is_empty() && entry.age() == age) as usize;
+        }
+        (used * 1000 / total) as u16
+    }
+
+    /// Starts counting hits, misses and replacements from zero, or stops counting
+    pub fn track_stats(&self, enabled: bool) {
+        for counter in self.stats.counters() {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.stats.enabled.store(enabled, Ordering::Relaxed);
     }
 
-    pub fn get_hashfull(&self) -> u16 {
-        let total = (self.table.len() * BUCKET_SIZE) as u64;
-        let used = (self.used.load(Ordering::Relaxed) as u64).min(total);
-        ((used * 1000u64) / total) as u16
+    pub fn stats(&self) -> TTStats {
+        let [hits, misses, updates, kept, empty, aged, collisions] = self
+            .stats
+            .counters()
+            .map(|counter| counter.load(Ordering::Relaxed));
+        TTStats {
+            hits,
+            misses,
+            updates,
+            kept,
+            empty,
+            aged,
+            collisions,
+        }
     }
 }
@@ -0,0 +1,350 @@
+//! Texel tuning: fits the weights in `EvalParams` to the results of the games a set of quiet
+//! positions come from, by minimizing the squared error between the result and the evaluation
+//! mapped to an expected score.
+//!
+//! The evaluation is linear in the weights except for a few terms (imbalance, attacks on the
+//! king) and the scaling of drawish endgames, so each position is reduced to how many times each
+//! weight counts for white minus black, a scale factor, and an offset covering everything else.
+
+use std::thread;
+
+use crate::{
+    chess::*,
+    engine::{
+        endgame::{self, Endgame},
+        evaluation::{EvalTerms, W},
+        material::MaterialEntry,
+        params::{EvalParams, Param, params},
+        pawns::PawnEntry,
+        trace::{Term, Trace},
+    },
+};
+
+/// Collects the coefficients of the weights, white minus black
+struct Features {
+    coefficients: Vec<i16>,
+}
+
+impl Trace for Features {
+    const ENABLED: bool = true;
+
+    fn add(&mut self, _: Term, _: Color, _: W) {}
+
+    fn feature(&mut self, param: Param, index: usize, color: Color, count: i16) {
+        let coefficient = &mut self.coefficients[param.offset() + index];
+        match color {
+            Color::White => *coefficient += count,
+            Color::Black => *coefficient -= count,
+        }
+    }
+}
+
+/// How many times each weight (by its index once all the groups are laid out one after the
+/// other) counts in the evaluation, white minus black. The ones that cancel out are left out
+pub fn features(board: &Board) -> Vec<(usize, i16)> {
+    let mut features = Features {
+        coefficients: vec![0; Param::COUNT],
+    };
+    board.evaluate_traced(
+        &MaterialEntry::new(board),
+        &PawnEntry::new(board),
+        &board.pst_bonus(),
+        EvalTerms::ALL,
+        &mut features,
+    );
+
+    features
+        .coefficients
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, coefficient)| coefficient != 0)
+        .collect()
+}
+
+/// A position reduced to what the tuner needs
+pub struct TuningPosition {
+    features: Vec<(u16, i16)>,
+    /// Weight of the midgame values, from 0 to 1
+    phase: f32,
+    /// What the drawish and scaled endgames multiply the evaluation by
+    factor: f32,
+    /// The part of the evaluation that doesn't depend on the weights
+    offset: f32,
+    /// 1 for a white win, 0.5 for a draw and 0 for a black win
+    result: f32,
+}
+
+impl TuningPosition {
+    /// `None` for the specialized endgames, their value doesn't depend on the weights
+    pub fn new(board: &Board, result: f32) -> Option<TuningPosition> {
+        let material = MaterialEntry::new(board);
+        if let Some((Endgame::Value(_), _)) = material.endgame {
+            return None;
+        }
+
+        let features = features(board);
+        let linear = features
+            .iter()
+            .fold((0, 0), |(mg, eg), &(index, coefficient)| {
+                let W(weight_mg, weight_eg) = weight(params(), index);
+                (
+                    mg + weight_mg as i32 * coefficient as i32,
+                    eg + weight_eg as i32 * coefficient as i32,
+                )
+            });
+        let phase = material.phase_ratio as f32 / Board::PHASE_SCALE as f32;
+        let linear = linear.0 as f32 * phase + linear.1 as f32 * (1.0 - phase);
+
+        let strong = if linear > 0.0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let mut factor =
+            board.scale_factor(&material, strong) as f32 / endgame::SCALE_NORMAL as f32;
+        if board.mating_material() == MatingMaterial::Drawish {
+            factor /= Board::DRAWISH_DIVISOR as f32;
+        }
+        factor *= board.fifty_move_left() as f32 / Board::FIFTY_MOVE_DAMPING as f32;
+
+        Some(TuningPosition {
+            features: features
+                .into_iter()
+                .map(|(index, coefficient)| (index as u16, coefficient))
+                .collect(),
+            phase,
+            factor,
+            offset: board.evaluate() as f32 - factor * linear,
+            result,
+        })
+    }
+}
+
+fn weight(params: &EvalParams, index: usize) -> W {
+    let param = Param::ALL
+        .into_iter()
+        .rfind(|param| param.offset() <= index)
+        .expect("the first group starts at 0");
+    params.weights(param)[index - param.offset()]
+}
+
+/// Reads a line made of a FEN (the counters can be left out) and the result of the game, as
+/// `1-0`, `0-1` or `1/2-1/2` (also inside quotes, like EPD's `c9 "1-0";`) or as `[1.0]`,
+/// `[0.5]` or `[0.0]`
+pub fn parse_position(line: &str) -> Result<(Board, f32), &'static str> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() < 4 {
+        return Err("not a FEN");
+    }
+    let board = Board::new(&tokens[..4].join(" "))?;
+
+    let result = tokens[4..]
+        .iter()
+        .find_map(
+            |token| match token.trim_matches(|c| matches!(c, '[' | ']' | '"' | ';')) {
+                "1-0" | "1.0" => Some(1.0),
+                "1/2-1/2" | "0.5" => Some(0.5),
+                "0-1" | "0.0" => Some(0.0),
+                _ => None,
+            },
+        )
+        .ok_or("no game result")?;
+
+    Ok((board, result))
+}
+
+/// Fits the weights with Adam over the whole set at every step
+pub struct Tuner {
+    positions: Vec<TuningPosition>,
+    /// Midgame and endgame value of every weight
+    weights: Vec<[f64; 2]>,
+    /// Scales the evaluation into the sigmoid
+    pub k: f64,
+    threads: usize,
+    // Adam's moving averages of the gradient and of its square, and the steps done
+    momentum: Vec<[f64; 2]>,
+    velocity: Vec<[f64; 2]>,
+    steps: i32,
+}
+
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+
+impl Tuner {
+    /// Starts from the weights in use
+    pub fn new(positions: Vec<TuningPosition>, threads: usize) -> Tuner {
+        let weights = Param::ALL
+            .into_iter()
+            .flat_map(|param| params().weights(param))
+            .map(|&W(mg, eg)| [mg as f64, eg as f64])
+            .collect();
+
+        Tuner {
+            positions,
+            weights,
+            // about the usual Texel constant of 1.13 with a base 10 sigmoid
+            k: 0.0065,
+            threads: threads.max(1),
+            momentum: vec![[0.0; 2]; Param::COUNT],
+            velocity: vec![[0.0; 2]; Param::COUNT],
+            steps: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    fn evaluate(&self, position: &TuningPosition) -> f64 {
+        let (mg, eg) =
+            position
+                .features
+                .iter()
+                .fold((0.0, 0.0), |(mg, eg), &(index, coefficient)| {
+                    let [weight_mg, weight_eg] = self.weights[index as usize];
+                    (
+                        mg + weight_mg * coefficient as f64,
+                        eg + weight_eg * coefficient as f64,
+                    )
+                });
+        let phase = position.phase as f64;
+        position.factor as f64 * (mg * phase + eg * (1.0 - phase)) + position.offset as f64
+    }
+
+    fn sigmoid(&self, eval: f64) -> f64 {
+        1.0 / (1.0 + (-self.k * eval).exp())
+    }
+
+    /// Runs `work` over the positions split between the threads and sums what it returns
+    fn parallel<R: Send>(
+        &self,
+        work: impl Fn(&[TuningPosition]) -> R + Sync,
+        sum: impl Fn(R, R) -> R,
+    ) -> R {
+        let chunk_size = self.positions.len().div_ceil(self.threads).max(1);
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .positions
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(|| work(chunk)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("tuning thread panicked"))
+                .reduce(sum)
+                .unwrap_or_else(|| work(&[]))
+        })
+    }
+
+    /// Mean squared error between the results and the expected scores
+    pub fn loss(&self) -> f64 {
+        let total = self.parallel(
+            |positions| {
+                positions
+                    .iter()
+                    .map(|position| {
+                        (position.result as f64 - self.sigmoid(self.evaluate(position))).powi(2)
+                    })
+                    .sum::<f64>()
+            },
+            |a, b| a + b,
+        );
+        total / self.positions.len().max(1) as f64
+    }
+
+    /// Picks the `k` that fits the current weights best, with a ternary search since the loss
+    /// has a single minimum in it
+    pub fn fit_k(&mut self) -> f64 {
+        let (mut low, mut high) = (0.0001, 0.05);
+        for _ in 0..60 {
+            let third = (high - low) / 3.0;
+            self.k = low + third;
+            let loss_low = self.loss();
+            self.k = high - third;
+            let loss_high = self.loss();
+            if loss_low < loss_high {
+                high -= third;
+            } else {
+                low += third;
+            }
+        }
+        self.k = (low + high) / 2.0;
+        self.k
+    }
+
+    fn gradient(&self) -> Vec<[f64; 2]> {
+        let mut gradient = self.parallel(
+            |positions| {
+                let mut gradient = vec![[0.0; 2]; Param::COUNT];
+                for position in positions {
+                    let score = self.sigmoid(self.evaluate(position));
+                    let error = (score - position.result as f64) * score * (1.0 - score);
+                    let phase = position.phase as f64;
+                    let scaled = error * position.factor as f64;
+                    for &(index, coefficient) in &position.features {
+                        let slope = scaled * coefficient as f64;
+                        gradient[index as usize][0] += slope * phase;
+                        gradient[index as usize][1] += slope * (1.0 - phase);
+                    }
+                }
+                gradient
+            },
+            |mut a, b| {
+                for (a, b) in a.iter_mut().zip(b) {
+                    a[0] += b[0];
+                    a[1] += b[1];
+                }
+                a
+            },
+        );
+
+        // the constant factors of the derivative
+        let scale = 2.0 * self.k / self.positions.len().max(1) as f64;
+        for pair in gradient.iter_mut() {
+            pair[0] *= scale;
+            pair[1] *= scale;
+        }
+        gradient
+    }
+
+    /// One step of Adam over all the positions
+    pub fn step(&mut self, learning_rate: f64) {
+        let gradient = self.gradient();
+        self.steps += 1;
+
+        let momentum_correction = 1.0 - BETA1.powi(self.steps);
+        let velocity_correction = 1.0 - BETA2.powi(self.steps);
+        for (i, slope) in gradient.into_iter().enumerate() {
+            let weights = self.weights[i].iter_mut();
+            let momentums = self.momentum[i].iter_mut();
+            let velocities = self.velocity[i].iter_mut();
+            for (((weight, momentum), velocity), slope) in
+                weights.zip(momentums).zip(velocities).zip(slope)
+            {
+                *momentum = BETA1 * *momentum + (1.0 - BETA1) * slope;
+                *velocity = BETA2 * *velocity + (1.0 - BETA2) * slope * slope;
+
+                *weight -= learning_rate * (*momentum / momentum_correction)
+                    / ((*velocity / velocity_correction).sqrt() + EPSILON);
+            }
+        }
+    }
+
+    /// The weights rounded back to centipawns
+    pub fn params(&self) -> EvalParams {
+        let mut params = EvalParams::default();
+        let mut weights = self.weights.iter();
+        for param in Param::ALL {
+            for weight in params.weights_mut(param) {
+                let [mg, eg] = weights.next().expect("one weight per parameter");
+                *weight = W(mg.round() as i16, eg.round() as i16);
+            }
+        }
+        params
+    }
+}
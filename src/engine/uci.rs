@@ -1,9 +1,17 @@
 use crate::{
     chess::*,
-    engine::{ordering::HistoryHeuristics, search::*, transposition::TT},
+    engine::{
+        book::Book,
+        endgame,
+        evaluation::{Classical, Evaluator, EvaluatorKind, Material},
+        nnue::{self, Network, Nnue},
+        ordering::HistoryHeuristics,
+        search::*,
+        syzygy::{TB_PIECES, Tablebases},
+        transposition::TT,
+    },
 };
 use std::{str::SplitWhitespace, sync::Arc, thread::JoinHandle};
-use tinyvec::ArrayVec;
 
 #[macro_export]
 macro_rules! send {
@@ -16,10 +24,36 @@ macro_rules! send {
 
 const TT_SIZE_MB: usize = 16;
 
+/// Positions searched by `bench`: openings, middlegames with tactics and endgames
+const BENCH_FENS: [&str; 8] = [
+    STARTPOS_FEN,
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+    "r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "8/8/1p1k4/p1p2p2/P1P2P2/1P1K4/8/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/1R3PPP/6K1 w - - 0 1",
+];
+const BENCH_DEPTH: usize = 8;
+
 pub struct Uci {
     // canonical position & history used when parsing `position`
-    position_board: Board,
-    position_history: ZobristHistory,
+    game: Game,
+    chess960: bool,
+
+    book: Option<Book>,
+    own_book: bool,
+    best_book_move: bool,
+
+    tablebases: Arc<Tablebases>,
+    evaluator: EvaluatorKind,
+
+    contempt: i16,
+    analyse_mode: bool,
+    // true keeps the contempt under `UCI_AnalyseMode`, by default analysis has none so both
+    // sides are judged the same
+    analysis_contempt: bool,
 
     worker: Option<JoinHandle<()>>,
     search_mode: Arc<AtomicSearchMode>,
@@ -27,6 +61,15 @@ pub struct Uci {
     history_heuristic: Arc<HistoryHeuristics>,
     age: u8,
     tt: Arc<TT>,
+    hash_file: String,
+    // report what the TT probes and stores found after each search
+    tt_stats: bool,
+}
+
+impl Default for Uci {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Uci {
@@ -37,19 +80,38 @@ impl Uci {
                 send!("id name Sand");
                 send!("id author P1x3r");
                 send!("option name Ponder type check default false");
+                send!("option name UCI_Chess960 type check default false");
+                send!("option name OwnBook type check default false");
+                send!("option name BookFile type string default <empty>");
+                send!("option name BestBookMove type check default false");
+                send!("option name SyzygyPath type string default <empty>");
+                send!("option name SyzygyProbeDepth type spin default 1 min 1 max 100");
+                send!("option name SyzygyProbeLimit type spin default 7 min 0 max 7");
+                send!(
+                    "option name Evaluator type combo default Classical var Classical var Material \
+                     var NNUE"
+                );
+                send!("option name EvalFile type string default <embedded>");
+                send!("option name Contempt type spin default 0 min -100 max 100");
+                send!("option name Analysis Contempt type check default false");
+                send!("option name UCI_AnalyseMode type check default false");
+                send!("option name HashFile type string default <empty>");
+                send!("option name Clear Hash type button");
+                send!("option name SaveHash type button");
+                send!("option name LoadHash type button");
+                send!("option name TTStats type check default false");
                 send!("uciok");
             }
             Some("debug") => {}
             Some("isready") => send!("readyok"),
-            Some("setoption") => {}
+            Some("setoption") => self.handle_setoption(tokens),
             Some("register") => send!("registration ok"),
             Some("ucinewgame") => {
                 self.stop_and_join();
 
-                self.position_board = Board::new(STARTPOS_FEN).unwrap();
-                self.position_history = ArrayVec::new();
+                self.game = Game::new(STARTPOS_FEN).unwrap();
                 self.history_heuristic = Arc::new(HistoryHeuristics::new());
-                self.tt = Arc::new(TT::new(TT_SIZE_MB));
+                self.clear_hash();
                 self.age = 1;
             }
             Some("position") => {
@@ -68,16 +130,25 @@ impl Uci {
                 self.stop_and_join();
                 return true;
             }
+            Some("bench") => {
+                let depth = tokens.next().and_then(|depth| depth.parse().ok());
+                match self.evaluator {
+                    EvaluatorKind::Classical => self.bench::<Classical>(depth),
+                    EvaluatorKind::Material => self.bench::<Material>(depth),
+                    EvaluatorKind::Nnue => self.bench::<Nnue>(depth),
+                }
+            }
             Some("eval") => {
-                send!("bonus: {:?}", self.position_board.bonus);
-                send!("material: {:?}", self.position_board.material);
-                send!("phase: {}", self.position_board.phase);
+                let board = self.game.board();
+                send!("{}", board.trace());
+                let eval = self.evaluator.evaluate(board);
                 send!(
-                    "static eval: {}",
-                    match self.position_board.side_to_move {
-                        Color::White => self.position_board.evaluate(),
-                        Color::Black => -self.position_board.evaluate(),
-                    }
+                    "static eval: {} ({})",
+                    match board.side_to_move {
+                        Color::White => eval,
+                        Color::Black => -eval,
+                    },
+                    self.evaluator.name()
                 );
             }
             None => {}
@@ -95,32 +166,160 @@ impl Uci {
         self.worker = None;
     }
 
+    fn clear_hash(&mut self) {
+        // the searcher holds a reference until it's done
+        self.stop_and_join();
+        Arc::get_mut(&mut self.tt).unwrap().clear();
+    }
+
+    fn handle_setoption(&mut self, tokens: &mut SplitWhitespace) {
+        if tokens.next() != Some("name") {
+            return;
+        }
+
+        let name = tokens
+            .by_ref()
+            .take_while(|&t| t != "value")
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let value = tokens.collect::<Vec<&str>>().join(" ");
+
+        // option names are case insensitive
+        match name.to_ascii_lowercase().as_str() {
+            "ponder" => {}
+            "uci_chess960" => self.chess960 = value == "true",
+            "ownbook" => self.own_book = value == "true",
+            "bestbookmove" => self.best_book_move = value == "true",
+            "bookfile" => {
+                self.book = None;
+                if value.is_empty() || value == "<empty>" {
+                    return;
+                }
+
+                match Book::open(&value) {
+                    Ok(book) => {
+                        send!("info string loaded book with {} entries", book.len());
+                        self.book = Some(book);
+                    }
+                    Err(e) => send!("info string book error {e}"),
+                }
+            }
+            "syzygypath" => {
+                self.stop_and_join();
+
+                let mut tablebases = Tablebases::default();
+                if !value.is_empty() && value != "<empty>" {
+                    tablebases = Tablebases::new(&value);
+                    send!("info string found {} tablebases", tablebases.len());
+                }
+                tablebases.probe_depth = self.tablebases.probe_depth;
+                tablebases.probe_limit = self.tablebases.probe_limit;
+                self.tablebases = Arc::new(tablebases);
+            }
+            "syzygyprobedepth" | "syzygyprobelimit" => {
+                let Ok(value) = value.parse::<usize>() else {
+                    send!("info string invalid value for {name}");
+                    return;
+                };
+
+                // the searcher holds a reference until it's done
+                self.stop_and_join();
+                let tablebases = Arc::get_mut(&mut self.tablebases).unwrap();
+                if name.eq_ignore_ascii_case("syzygyprobedepth") {
+                    tablebases.probe_depth = value.max(1);
+                } else {
+                    tablebases.probe_limit = value.min(TB_PIECES);
+                }
+            }
+            "contempt" => match value.parse::<i16>() {
+                Ok(contempt) => self.contempt = contempt.clamp(-100, 100),
+                Err(_) => send!("info string invalid value for {name}"),
+            },
+            "analysis contempt" => self.analysis_contempt = value == "true",
+            "uci_analysemode" => self.analyse_mode = value == "true",
+            "evaluator" => match EvaluatorKind::from_name(&value) {
+                // scores from the other evaluator would linger in the table
+                Some(evaluator) if evaluator != self.evaluator => {
+                    self.evaluator = evaluator;
+                    self.clear_hash();
+                }
+                Some(_) => {}
+                None => send!("info string invalid value for {name}"),
+            },
+            "evalfile" => {
+                // a search in progress keeps the network it started with
+                if value.is_empty() || value == "<embedded>" {
+                    let previous = nnue::network();
+                    nnue::reset_network();
+                    if !Arc::ptr_eq(&previous, &nnue::network()) {
+                        self.clear_hash();
+                    }
+                    return;
+                }
+
+                match Network::load(&value) {
+                    Ok(network) => {
+                        if network != *nnue::network() {
+                            nnue::set_network(network);
+                            self.clear_hash();
+                        }
+                        send!("info string loaded network {value}");
+                    }
+                    Err(e) => send!("info string network error {e}"),
+                }
+            }
+            "ttstats" => self.tt_stats = value == "true",
+            "clear hash" => self.clear_hash(),
+            "hashfile" => match value.as_str() {
+                "<empty>" => self.hash_file.clear(),
+                _ => self.hash_file = value,
+            },
+            "savehash" | "loadhash" if self.hash_file.is_empty() => {
+                send!("info string no HashFile set")
+            }
+            "savehash" => {
+                // entries written during a search could be saved half updated
+                self.stop_and_join();
+                match self.tt.save(&self.hash_file, self.evaluator.signature()) {
+                    Ok(()) => send!("info string saved hash to {}", self.hash_file),
+                    Err(e) => send!("info string hash error {e}"),
+                }
+            }
+            "loadhash" => {
+                self.stop_and_join();
+                match TT::load(&self.hash_file, self.evaluator.signature()) {
+                    Ok(tt) => {
+                        self.tt = Arc::new(tt);
+                        send!("info string loaded hash from {}", self.hash_file);
+                    }
+                    Err(e) => send!("info string hash error {e}"),
+                }
+            }
+            _ => send!("info string unknown option {name}"),
+        }
+    }
+
     fn handle_position(&mut self, tokens: &mut SplitWhitespace) -> Result<(), &'static str> {
+        // both arms consume the `moves` token, leaving only the moves themselves
         let fen: String = match tokens.next() {
-            Some("startpos") => STARTPOS_FEN.to_string(),
             Some("fen") => tokens
                 .by_ref()
                 .take_while(|&t| t != "moves")
                 .collect::<Vec<&str>>()
                 .join(" "),
-            _ => STARTPOS_FEN.to_string(),
+            _ => {
+                tokens.next();
+                STARTPOS_FEN.to_string()
+            }
         };
 
-        self.position_board = Board::new(&fen)?;
-        self.position_history.clear();
-        self.position_history.push(self.position_board.zobrist);
+        let mut board = Board::new(&fen)?;
+        board.chess960 |= self.chess960;
+        self.game = Game::from_board(board);
 
-        if tokens.next() == Some("moves") {
-            for move_uci in tokens {
-                // check pseudo-legality
-                let move_list = gen_color_moves(&self.position_board);
-                let Some(&mov) = move_list.iter().find(|m| m.to_uci() == move_uci) else {
-                    continue; // silently ignore invalid moves
-                };
-
-                self.position_board.make_move(mov);
-                self.position_history.push(self.position_board.zobrist);
-            }
+        for move_uci in tokens {
+            // silently ignore illegal moves
+            let _ = self.game.make_uci_move(move_uci);
         }
 
         Ok(())
@@ -133,7 +332,7 @@ impl Uci {
 
         while let Some(key) = tokens.next() {
             match key {
-                "movetime" | "depth" | "wtime" | "btime" | "winc" | "binc" | "perft" => {
+                "movetime" | "depth" | "nodes" | "wtime" | "btime" | "winc" | "binc" | "perft" => {
                     let Some(val) = tokens.next() else {
                         continue;
                     };
@@ -144,6 +343,7 @@ impl Uci {
                     match key {
                         "movetime" => time_control = TimeControl::MoveTime(val),
                         "depth" => time_control = TimeControl::Depth(val as usize),
+                        "nodes" => time_control = TimeControl::Nodes(val as usize),
                         "wtime" => {
                             has_clock_time = true;
                             clock_time.white_time_ms = val;
@@ -167,24 +367,109 @@ impl Uci {
             time_control = TimeControl::ClockTime(clock_time);
         }
 
-        let mut searcher = Searcher::new(
-            self.position_board.clone(),
-            self.position_history,
+        // nothing to search, but the GUI still expects an answer
+        if self.game.legal_moves().is_empty() {
+            send!("bestmove 0000");
+            return;
+        }
+
+        if self.own_book
+            && let Some(book) = &self.book
+            && let Some(mov) = book.pick_move(&self.game, self.best_book_move)
+        {
+            // no search will reset a `go ponder`
+            self.search_mode.store(SearchMode::Normal);
+            send!("bestmove {}", self.game.board().move_to_uci(mov));
+            return;
+        }
+
+        self.worker = Some(match self.evaluator {
+            EvaluatorKind::Classical => self.spawn_search::<Classical>(time_control),
+            EvaluatorKind::Material => self.spawn_search::<Material>(time_control),
+            EvaluatorKind::Nnue => self.spawn_search::<Nnue>(time_control),
+        });
+        self.age = self.age.wrapping_add(1);
+    }
+
+    /// Searches every bench position to a fixed depth from an empty table, and sends the nodes
+    /// and speed. The node count is the same from build to build unless the search changed
+    fn bench<E: Evaluator>(&mut self, depth: Option<usize>) {
+        self.stop_and_join();
+
+        let depth = depth.unwrap_or(BENCH_DEPTH).clamp(1, MAX_PLY - 1);
+        let search_mode = Arc::new(AtomicSearchMode::new(SearchMode::Normal));
+        let start = std::time::Instant::now();
+        let mut nodes = 0;
+        for fen in BENCH_FENS {
+            self.clear_hash();
+            let mut searcher = Searcher::<E>::new(
+                &Game::new(fen).unwrap(),
+                &search_mode,
+                &Arc::new(HistoryHeuristics::new()),
+                1,
+                &self.tt,
+                &self.tablebases,
+            );
+            searcher.set_silent(true);
+            searcher.start_search(TimeControl::Depth(depth));
+            nodes += searcher.nodes();
+        }
+        self.clear_hash();
+
+        let elapsed = start.elapsed();
+        send!(
+            "info string bench depth {depth} nodes {nodes} time {} nps {}",
+            elapsed.as_millis(),
+            (nodes as f64 / elapsed.as_secs_f64()) as u64
+        );
+    }
+
+    fn spawn_search<E: Evaluator + Send + 'static>(
+        &self,
+        time_control: TimeControl,
+    ) -> JoinHandle<()> {
+        let mut searcher = Searcher::<E>::new(
+            &self.game,
             &self.search_mode,
             &self.history_heuristic,
             self.age,
             &self.tt,
+            &self.tablebases,
         );
+        searcher.set_contempt(self.search_contempt());
+
+        self.tt.track_stats(self.tt_stats);
+        let tt = self.tt_stats.then(|| self.tt.clone());
 
-        self.worker = Some(std::thread::spawn(move || {
+        let chess960 = self.game.board().chess960;
+        std::thread::spawn(move || {
             let (best_move, ponder_move) = searcher.start_search(time_control);
+            if let Some(tt) = tt {
+                send!("info string {}", tt.stats());
+            }
             if let Some(p) = ponder_move {
-                send!("bestmove {} ponder {}", best_move.to_uci(), p.to_uci());
+                send!(
+                    "bestmove {} ponder {}",
+                    best_move.to_uci(chess960),
+                    p.to_uci(chess960)
+                );
             } else {
-                send!("bestmove {}", best_move.to_uci());
+                send!("bestmove {}", best_move.to_uci(chess960));
             }
-        }));
-        self.age = self.age.wrapping_add(1);
+        })
+    }
+
+    /// The contempt searches get, none when analysing unless `Analysis Contempt` is set
+    pub fn search_contempt(&self) -> i16 {
+        match !self.analyse_mode || self.analysis_contempt {
+            true => self.contempt,
+            false => 0,
+        }
+    }
+
+    /// Runs a single command, returns true if it's `quit`
+    pub fn execute(&mut self, command: &str) -> bool {
+        self.execute_commands(&mut command.split_whitespace())
     }
 
     pub fn uci_loop(&mut self) {
@@ -201,16 +486,30 @@ impl Uci {
                 // EOF -> parent closed stdin (communicate finished)
                 break;
             }
-            if self.execute_commands(&mut input.split_whitespace()) {
+            if self.execute(&input) {
                 break;
             }
         }
     }
 
     pub fn new() -> Uci {
+        // the KPK bitbase takes a moment to build, better now than in the middle of a search
+        endgame::init();
+
         Uci {
-            position_board: Board::new(STARTPOS_FEN).unwrap(),
-            position_history: ArrayVec::new(),
+            game: Game::new(STARTPOS_FEN).unwrap(),
+            chess960: false,
+
+            book: None,
+            own_book: false,
+            best_book_move: false,
+
+            tablebases: Arc::new(Tablebases::default()),
+            evaluator: EvaluatorKind::Classical,
+
+            contempt: 0,
+            analyse_mode: false,
+            analysis_contempt: false,
 
             worker: None,
             search_mode: Arc::new(AtomicSearchMode::new(SearchMode::Normal)),
@@ -218,6 +517,8 @@ impl Uci {
             history_heuristic: Arc::new(HistoryHeuristics::new()),
             age: 1,
             tt: Arc::new(TT::new(TT_SIZE_MB)),
+            hash_file: String::new(),
+            tt_stats: false,
         }
     }
 }
@@ -1,4 +1,10 @@
-use sand::{engine::uci::Uci, send};
+use sand::{
+    engine::{
+        params::{EvalParams, set_params},
+        uci::Uci,
+    },
+    send,
+};
 
 pub fn main() {
     std::panic::set_hook(Box::new(|info| {
@@ -18,16 +24,32 @@ pub fn main() {
 
         send!("info string panic {msg} {location}");
 
-        if let Ok(bt) = std::env::var("RUST_BACKTRACE") {
-            if bt == "1" || bt == "full" {
-                let bt = std::backtrace::Backtrace::force_capture().to_string();
-                for line in bt.lines() {
-                    send!("info string {line}");
-                }
+        if let Ok(bt) = std::env::var("RUST_BACKTRACE")
+            && (bt == "1" || bt == "full")
+        {
+            let bt = std::backtrace::Backtrace::force_capture().to_string();
+            for line in bt.lines() {
+                send!("info string {line}");
             }
         }
     }));
 
+    // evaluation weights other than the compiled ones, like the ones the tuner writes
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--params" {
+            let loaded = args
+                .next()
+                .ok_or("missing parameter file")
+                .and_then(|path| EvalParams::load(&path))
+                .and_then(set_params);
+            if let Err(err) = loaded {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+    }
+
     let mut uci = Uci::new();
     uci.uci_loop();
 }
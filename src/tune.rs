@@ -0,0 +1,105 @@
+use std::io::{BufRead, BufReader};
+
+use sand::engine::{
+    params::{EvalParams, set_params},
+    tune::{Tuner, TuningPosition, parse_position},
+};
+
+const USAGE: &str = "usage: tune <positions.txt> <params.txt> [--epochs N] [--learning-rate X] \
+                     [--threads N] [--params <start.txt>]";
+
+// how often the loss is reported and the weights are saved
+const REPORT_INTERVAL: usize = 50;
+
+struct Options {
+    positions_path: String,
+    output_path: String,
+    epochs: usize,
+    learning_rate: f64,
+    threads: usize,
+    /// weights to start from instead of the compiled ones
+    start_path: Option<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, &'static str> {
+        let mut options = Options {
+            positions_path: args.next().ok_or(USAGE)?,
+            output_path: args.next().ok_or(USAGE)?,
+            epochs: 1000,
+            learning_rate: 1.0,
+            threads: std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+            start_path: None,
+        };
+
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or(USAGE)?;
+            match flag.as_str() {
+                "--epochs" => options.epochs = value.parse().map_err(|_| "bad epoch count")?,
+                "--learning-rate" => {
+                    options.learning_rate = value.parse().map_err(|_| "bad learning rate")?
+                }
+                "--threads" => options.threads = value.parse().map_err(|_| "bad thread count")?,
+                "--params" => options.start_path = Some(value),
+                _ => return Err(USAGE),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+pub fn main() -> Result<(), &'static str> {
+    let options = Options::parse(std::env::args().skip(1))?;
+
+    if let Some(path) = &options.start_path {
+        set_params(EvalParams::load(path)?)?;
+    }
+
+    let file = std::fs::File::open(&options.positions_path).map_err(|_| "can't read positions")?;
+    let mut positions = Vec::new();
+    let mut skipped = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|_| "can't read positions")?;
+        match parse_position(&line) {
+            Ok((board, result)) => match TuningPosition::new(&board, result) {
+                Some(position) => positions.push(position),
+                None => skipped += 1,
+            },
+            Err(_) if line.trim().is_empty() => {}
+            Err(_) => skipped += 1,
+        }
+    }
+    println!("{} positions loaded, {skipped} skipped", positions.len());
+
+    let mut tuner = Tuner::new(positions, options.threads);
+    if tuner.is_empty() {
+        return Err("no positions to tune on");
+    }
+
+    let k = tuner.fit_k();
+    println!("k = {k:.6}, loss = {:.6}", tuner.loss());
+
+    let save = |tuner: &Tuner| {
+        std::fs::write(&options.output_path, tuner.params().to_string())
+            .map_err(|_| "can't write parameter file")
+    };
+
+    for epoch in 1..=options.epochs {
+        tuner.step(options.learning_rate);
+
+        if epoch % REPORT_INTERVAL == 0 {
+            println!("epoch {epoch}: loss = {:.6}", tuner.loss());
+            save(&tuner)?;
+        }
+    }
+
+    save(&tuner)?;
+    println!(
+        "final loss = {:.6}, parameters written to {}",
+        tuner.loss(),
+        options.output_path
+    );
+
+    Ok(())
+}
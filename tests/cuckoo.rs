@@ -0,0 +1,34 @@
+use sand::chess::*;
+
+// how a move changes the Zobrist key
+fn key_change(fen: &str, uci: &str) -> Result<u64, &'static str> {
+    let mut board = Board::new(fen)?;
+    let before = board.zobrist;
+    let mov = gen_color_moves(&board)
+        .into_iter()
+        .find(|&mov| mov.to_uci(false) == uci)
+        .ok_or("no such move")?;
+    board.make_move(mov);
+    Ok(before ^ board.zobrist)
+}
+
+#[test]
+fn test_cuckoo_table() -> Result<(), &'static str> {
+    // every reversible move of a knight, bishop, rook, queen or king on an empty board
+    assert_eq!(cuckoo::len(), 3668);
+
+    let fen = "r3k3/8/8/8/8/8/8/R3K1N1 w - - 0 1";
+    for (uci, squares) in [("g1f3", (6, 21)), ("e1d2", (4, 11)), ("a1a5", (0, 32))] {
+        let entry = cuckoo::lookup(key_change(fen, uci)?).ok_or("move not found")?;
+        assert_eq!(entry.squares, squares, "{uci}");
+    }
+
+    // the rook crosses b1 and c1 on its way
+    let entry = cuckoo::lookup(key_change(fen, "a1d1")?).ok_or("move not found")?;
+    assert_eq!(entry.between, bit(1) | bit(2));
+
+    // pawn moves can't be undone
+    assert!(cuckoo::lookup(key_change(STARTPOS_FEN, "e2e3")?).is_none());
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use sand::{
+    chess::*,
+    engine::{
+        datagen::{Sample, Settings, generate, play_game},
+        evaluation::EvaluatorKind,
+        transposition::TT,
+        tune::parse_position,
+    },
+};
+
+fn settings() -> Settings {
+    Settings {
+        nodes: 500,
+        random_plies: 6,
+        hash_mb: 1,
+        evaluator: EvaluatorKind::Classical,
+    }
+}
+
+#[test]
+fn test_fen_round_trip() -> Result<(), &'static str> {
+    for fen in [
+        STARTPOS_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 13 1",
+    ] {
+        assert_eq!(Board::new(fen)?.to_fen(), fen);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sample_formats() -> Result<(), &'static str> {
+    let sample = Sample {
+        board: Board::new("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 3 1")?,
+        score: -42,
+        result: 0.0,
+    };
+
+    let decoded = Sample::from_bytes(&sample.to_bytes())?;
+    assert_eq!(
+        decoded.board.to_fen(),
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 3 1"
+    );
+    assert_eq!((decoded.score, decoded.result), (-42, 0.0));
+
+    // some of the rights are gone
+    let partial = Sample {
+        board: Board::new("r3k2r/8/8/8/8/8/8/R3K2R b Kq - 5 20")?,
+        score: 0,
+        result: 0.5,
+    };
+    let decoded = Sample::from_bytes(&partial.to_bytes())?;
+    assert_eq!(decoded.board.to_fen(), "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 5 1");
+    assert_eq!(decoded.board.zobrist, partial.board.zobrist);
+
+    // the tuner reads the position without the move counters
+    let (board, result) = parse_position(&sample.to_text())?;
+    assert_eq!(
+        board.to_fen(),
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 1"
+    );
+    assert_eq!(result, 0.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_deterministic_games() {
+    let settings = settings();
+    // the second game starts with what the first left in the table, which is cleared
+    let mut tt = Arc::new(TT::new(settings.hash_mb));
+    let game = play_game(7, &[], &settings, &mut tt);
+    assert_eq!(play_game(7, &[], &settings, &mut tt), game);
+
+    for sample in &game {
+        assert!(!is_king_attcked(sample.board.side_to_move, &sample.board));
+        assert!(game.iter().all(|other| other.result == sample.result));
+    }
+
+    // the order of the output doesn't depend on the threads
+    let mut single = Vec::new();
+    generate(4, 1, 3, &[], &settings, |_, samples| single.extend(samples));
+    let mut multi = Vec::new();
+    generate(4, 3, 3, &[], &settings, |_, samples| multi.extend(samples));
+    assert_eq!(single, multi);
+}
@@ -0,0 +1,152 @@
+use sand::{
+    chess::*,
+    engine::endgame::{self, KNOWN_WIN, SCALE_NORMAL},
+};
+
+fn evaluate(fen: &str) -> Result<i16, &'static str> {
+    Ok(Board::new(fen)?.evaluate())
+}
+
+#[test]
+fn test_kpk() -> Result<(), &'static str> {
+    // the defending king holds the opposition in front of the pawn
+    assert_eq!(evaluate("4k3/8/4P3/4K3/8/8/8/8 w - - 0 1")?, 0);
+    assert_eq!(evaluate("4k3/8/4P3/4K3/8/8/8/8 b - - 0 1")?, 0);
+
+    // e7 wins, but black to move gets the opposition
+    assert!(evaluate("4k3/8/3KP3/8/8/8/8/8 w - - 0 1")? > KNOWN_WIN);
+    assert_eq!(evaluate("4k3/8/3KP3/8/8/8/8/8 b - - 0 1")?, 0);
+
+    // the king in front of the pawn wins whoever moves
+    assert!(evaluate("4k3/8/4K3/8/4P3/8/8/8 w - - 0 1")? > KNOWN_WIN);
+    assert!(evaluate("4k3/8/4K3/8/4P3/8/8/8 b - - 0 1")? > KNOWN_WIN);
+
+    // the black king is outside the square of the pawn, but not if it's its move on the h-file
+    assert!(evaluate("8/8/8/8/8/7k/P7/K7 w - - 0 1")? > KNOWN_WIN);
+    assert_eq!(evaluate("k7/8/8/8/8/8/P7/K7 w - - 0 1")?, 0);
+
+    // same thing with black
+    assert!(evaluate("8/8/8/4p3/8/4k3/8/4K3 w - - 0 1")? < -KNOWN_WIN);
+    assert_eq!(evaluate("8/8/8/8/4k3/4p3/8/4K3 b - - 0 1")?, 0);
+
+    // the bitbase only has the a-d files, the others are mirrored
+    for (fen, mirrored) in [
+        (
+            "4k3/8/4P3/4K3/8/8/8/8 w - - 0 1",
+            "3k4/8/3P4/3K4/8/8/8/8 w - - 0 1",
+        ),
+        (
+            "4k3/8/3KP3/8/8/8/8/8 w - - 0 1",
+            "3k4/8/3PK3/8/8/8/8/8 w - - 0 1",
+        ),
+        (
+            "8/8/8/8/8/7k/P7/K7 w - - 0 1",
+            "8/8/8/8/8/k7/7P/7K w - - 0 1",
+        ),
+    ] {
+        assert_eq!(evaluate(fen)?, evaluate(mirrored)?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_mating_patterns() -> Result<(), &'static str> {
+    // with the dark squared bishop on c1 the king goes to a1 or h8, not to h1
+    let a1 = evaluate("8/8/8/8/8/2K5/3N4/k1B5 w - - 0 1")?;
+    let h1 = evaluate("8/8/8/8/8/5K2/4N3/2B4k w - - 0 1")?;
+    let center = evaluate("8/8/8/3k4/8/2K5/3N4/2B5 w - - 0 1")?;
+    assert!(a1 > h1);
+    assert!(h1 > KNOWN_WIN && center > KNOWN_WIN);
+    assert!(a1 > center);
+
+    // and the other way around with a light squared bishop
+    let a1 = evaluate("8/8/8/8/8/2K5/3N4/kB6 w - - 0 1")?;
+    let h1 = evaluate("8/8/8/8/8/5K2/4N3/1B5k w - - 0 1")?;
+    assert!(h1 > a1);
+
+    // the bare king is better in the center
+    let edge = evaluate("8/8/8/8/8/8/8/R3K2k w - - 0 1")?;
+    let center = evaluate("8/8/8/4k3/8/8/8/R3K3 w - - 0 1")?;
+    assert!(edge > center && center > KNOWN_WIN);
+    assert!(evaluate("q3k3/8/8/8/8/8/8/4K3 w - - 0 1")? < -KNOWN_WIN);
+
+    Ok(())
+}
+
+#[test]
+fn test_known_draws() -> Result<(), &'static str> {
+    assert_eq!(evaluate("4k3/8/8/8/8/8/8/2NNK3 w - - 0 1")?, 0);
+
+    // the bishop doesn't control the promotion square
+    assert_eq!(evaluate("k7/8/8/8/8/8/P7/K1B5 w - - 0 1")?, 0);
+    assert!(evaluate("k7/8/8/8/8/8/P7/KB6 w - - 0 1")? > 0);
+
+    // doubled rook pawns don't help
+    assert_eq!(evaluate("k7/8/8/P7/P7/8/8/K7 w - - 0 1")?, 0);
+    assert!(evaluate("8/8/8/P7/P7/8/8/K5k1 w - - 0 1")? > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_drawish_scaling() -> Result<(), &'static str> {
+    let scale = |fen: &str, strong: Color| -> Result<i16, &'static str> {
+        Ok(endgame::scale_factor(&Board::new(fen)?, strong))
+    };
+
+    // opposite colored bishops, alone and with rooks
+    assert_eq!(
+        scale("4k3/2b1p3/8/8/3P4/2PB4/8/4K3 w - - 0 1", Color::White)?,
+        16
+    );
+    assert_eq!(
+        scale("4k3/2b1p3/8/8/1P1P4/2PB4/8/4K3 w - - 0 1", Color::White)?,
+        32
+    );
+    assert_eq!(
+        scale("r3k3/2b5/8/8/3P4/2PB4/8/R3K3 w - - 0 1", Color::White)?,
+        46
+    );
+    // same colored bishops are a normal ending
+    let same = "4k3/4p3/2b5/8/3P4/2PB4/8/4K3 w - - 0 1";
+    assert_eq!(scale(same, Color::White)?, SCALE_NORMAL);
+    assert!(evaluate(same)? > 2 * evaluate("4k3/2b1p3/8/8/3P4/2PB4/8/4K3 w - - 0 1")?);
+
+    // no pawns and only a minor piece more
+    assert_eq!(scale("4k3/8/8/3b4/8/8/8/R3K3 w - - 0 1", Color::White)?, 4);
+    assert_eq!(scale("r3k3/8/8/8/8/8/8/RB2K3 w - - 0 1", Color::White)?, 14);
+    assert_eq!(
+        scale("4k3/8/8/8/8/8/8/R2QK3 w - - 0 1", Color::White)?,
+        SCALE_NORMAL
+    );
+    // a bishop can't beat the pawns, even if it's worth more
+    assert!(evaluate("4k3/4p3/4p3/8/8/8/8/2B1K3 w - - 0 1")? <= 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_fifty_move_damping() -> Result<(), &'static str> {
+    let fen = |clock: u8| format!("4k3/8/8/8/8/4P3/r7/R3K3 w - - {clock} 1");
+
+    let fresh = evaluate(&fen(0))?;
+    assert!(fresh > 0);
+    assert_eq!(evaluate(&fen(20))?, fresh);
+    assert_eq!(evaluate(&fen(60))?, fresh / 2);
+    assert!(evaluate(&fen(90))? < fresh / 4);
+    assert_eq!(evaluate(&fen(100))?, 0);
+
+    // specialized endgames too, KBNK and a bare king
+    for fen in [
+        |clock: u8| format!("8/8/8/3k4/8/2K5/3N4/2B5 w - - {clock} 1"),
+        |clock: u8| format!("8/8/8/4k3/8/8/8/R3K3 w - - {clock} 1"),
+    ] {
+        let fresh = evaluate(&fen(0))?;
+        assert!(fresh > KNOWN_WIN);
+        assert_eq!(evaluate(&fen(60))?, fresh / 2);
+        assert_eq!(evaluate(&fen(100))?, 0);
+    }
+
+    Ok(())
+}
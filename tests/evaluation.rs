@@ -0,0 +1,155 @@
+mod utils;
+
+use sand::{
+    chess::*,
+    engine::{
+        evaluation::{Classical, EvalTerms, Evaluator, EvaluatorKind, Material},
+        material::MaterialEntry,
+        nnue::Nnue,
+        pawns::PawnEntry,
+        trace::Term,
+    },
+};
+use utils::check_incremental;
+
+// how much a term is worth for white in the position
+fn term(fen: &str, term: u16) -> Result<i16, &'static str> {
+    let board = Board::new(fen)?;
+    let (material, pawns) = (MaterialEntry::new(&board), PawnEntry::new(&board));
+
+    Ok(board.evaluate_terms(&material, &pawns, EvalTerms::ALL)
+        - board.evaluate_terms(&material, &pawns, EvalTerms::ALL & !term))
+}
+
+#[test]
+fn test_eval_terms() -> Result<(), &'static str> {
+    // the knight on d4 goes everywhere, the one on b8 doesn't
+    assert!(term("1n2k3/pp6/8/8/3N4/8/PP6/4K3 w - - 0 1", EvalTerms::MOBILITY)? > 0);
+
+    // no pawns in front of the white king, with queens on the board
+    assert!(
+        term(
+            "r2q2k1/5ppp/8/8/8/8/PPP5/R2Q2K1 w - - 0 1",
+            EvalTerms::KING_SAFETY
+        )? < 0
+    );
+    // queen and rook both hitting the squares around the king
+    assert!(
+        term(
+            "6k1/5ppp/8/8/8/5q2/5PPP/4r1K1 w - - 0 1",
+            EvalTerms::KING_SAFETY
+        )? < 0
+    );
+
+    assert!(
+        term(
+            "1n2kn2/pppp4/8/8/8/8/PPPP4/2B1KB2 w - - 0 1",
+            EvalTerms::BISHOP_PAIR
+        )? > 0
+    );
+
+    // a-file is semi-open for the white rook only, the e-file is open
+    assert!(term("r3k3/p7/8/8/8/8/1P6/R3K3 w - - 0 1", EvalTerms::ROOK_FILES)? > 0);
+    assert!(term("4k3/p7/8/8/8/8/1P6/4R1K1 w - - 0 1", EvalTerms::ROOK_FILES)? > 0);
+
+    // d5 is defended by e4 and no black pawn can chase the knight
+    assert!(term("4k3/p6p/8/3N4/4P3/8/7P/4K3 w - - 0 1", EvalTerms::OUTPOSTS)? > 0);
+    assert_eq!(
+        term(
+            "4k3/p1p4p/8/3N4/4P3/8/7P/4K3 w - - 0 1",
+            EvalTerms::OUTPOSTS
+        )?,
+        0
+    );
+
+    // the e4 pawn attacks the knight, which is defended
+    assert!(term("4k3/8/4p3/3n4/4P3/8/8/4K3 w - - 0 1", EvalTerms::THREATS)? > 0);
+    assert_eq!(
+        term("4k3/8/4p3/3n4/4P3/8/8/4K3 w - - 0 1", EvalTerms::HANGING)?,
+        0
+    );
+
+    // nothing defends the knight
+    assert!(term("4k3/p7/8/3n4/8/8/P7/3RK3 w - - 0 1", EvalTerms::HANGING)? > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_eval_terms_off() -> Result<(), &'static str> {
+    // without the extra terms, only material, piece-square tables and pawns are left
+    let board = Board::new("r1bqk2r/pp2bppp/2n1pn2/2pp4/3P4/2PBPN2/PP1N1PPP/R1BQK2R w KQkq - 0 7")?;
+    let (material, pawns) = (MaterialEntry::new(&board), PawnEntry::new(&board));
+
+    let all = board.evaluate_terms(&material, &pawns, EvalTerms::ALL);
+    assert_eq!(board.evaluate_with(&material, &pawns), all);
+    assert_eq!(board.evaluate(), all);
+
+    let mut sum = board.evaluate_terms(&material, &pawns, 0);
+    for flag in 0..EvalTerms::ALL.count_ones() {
+        let term = 1 << flag;
+        sum += board.evaluate_terms(&material, &pawns, term)
+            - board.evaluate_terms(&material, &pawns, 0);
+    }
+    // the terms are added before the phase scaling, so only rounding separates them
+    assert!((sum - all).abs() <= EvalTerms::ALL.count_ones() as i16);
+
+    Ok(())
+}
+
+#[test]
+fn test_eval_trace() -> Result<(), &'static str> {
+    let board = Board::new("r1bqk2r/pp2bppp/2n1pn2/2pp4/3P4/2PBPN2/PP1N1PPP/R1BQK2R w KQkq - 0 7")?;
+    let trace = board.trace();
+    assert_eq!(trace.eval, board.evaluate());
+    // every term is blended on its own, so they may round differently than the evaluation
+    assert!((trace.blend(trace.total()) - trace.eval).abs() <= 1);
+
+    // the same position with the colors swapped
+    let mirrored =
+        Board::new("r1bqk2r/pp1n1ppp/2pbpn2/3p4/2PP4/2N1PN2/PP2BPPP/R1BQK2R b KQkq - 0 7")?;
+    for (term, [white, black]) in mirrored.trace().terms.into_iter().enumerate() {
+        assert_eq!([black, white], trace.terms[term], "{:?}", Term::ALL[term]);
+    }
+
+    // pieces are worth something to their own side
+    for square in 0..BOARD_SIZE {
+        match (board.pieces[square], trace.squares[square]) {
+            ((Piece::None | Piece::King, _), value) => assert_eq!(value, None),
+            ((_, Color::White), value) => assert!(value.ok_or("no value")? > 0),
+            ((_, Color::Black), value) => assert!(value.ok_or("no value")? < 0),
+        }
+    }
+
+    let text = trace.to_string();
+    assert!(text.contains("King safety") && text.contains("Evaluation: "));
+    assert!(
+        Board::new("4k3/8/8/8/8/8/8/2BNK3 w - - 0 1")?
+            .trace()
+            .note
+            .is_some()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_evaluators() -> Result<(), &'static str> {
+    // castling, promotions and en passant among them
+    for line in utils::LARGE_TEST_EPDS.iter().take(12) {
+        let fen = line.split(';').next().unwrap();
+        let mut board = Board::new(fen)?;
+        let (mut classical, mut material) = (Classical::new(&board), Material::new(&board));
+        let mut nnue = Nnue::new(&board);
+        check_incremental(&mut board, &mut classical, 2, &Classical::evaluate);
+        check_incremental(&mut board, &mut material, 2, &Material::evaluate);
+        check_incremental(&mut board, &mut nnue, 2, &Nnue::evaluate);
+    }
+
+    let board = Board::new("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")?;
+    assert_eq!(EvaluatorKind::Material.evaluate(&board), 900);
+    assert_eq!(EvaluatorKind::Classical.evaluate(&board), board.evaluate());
+    assert_eq!(EvaluatorKind::from_name("nnue"), Some(EvaluatorKind::Nnue));
+
+    Ok(())
+}
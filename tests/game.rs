@@ -0,0 +1,143 @@
+use sand::chess::*;
+
+fn play(game: &mut Game, moves: &str) -> Result<(), &'static str> {
+    for uci in moves.split_whitespace() {
+        game.make_uci_move(uci)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_checkmate_and_stalemate() -> Result<(), &'static str> {
+    let mut game = Game::new(STARTPOS_FEN)?;
+    play(&mut game, "f2f3 e7e5 g2g4 d8h4")?;
+    assert_eq!(
+        game.outcome(),
+        Some(Outcome::Checkmate {
+            winner: Color::Black
+        })
+    );
+
+    let game = Game::new("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1")?;
+    assert_eq!(game.outcome(), Some(Outcome::Stalemate));
+
+    // checkmate takes precedence over the 75-move rule
+    let game = Game::new("7k/6Q1/6K1/8/8/8/8/8 b - - 160 1")?;
+    assert_eq!(
+        game.outcome(),
+        Some(Outcome::Checkmate {
+            winner: Color::White
+        })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_repetitions() -> Result<(), &'static str> {
+    const SHUFFLE: &str = "g1f3 g8f6 f3g1 f6g8";
+
+    let mut game = Game::new(STARTPOS_FEN)?;
+    play(&mut game, SHUFFLE)?;
+    assert_eq!(game.repetitions(), 2);
+    assert_eq!(game.outcome(), None);
+
+    play(&mut game, SHUFFLE)?;
+    assert_eq!(game.outcome(), Some(Outcome::ThreefoldRepetition));
+
+    play(&mut game, SHUFFLE)?;
+    play(&mut game, SHUFFLE)?;
+    assert_eq!(game.outcome(), Some(Outcome::FivefoldRepetition));
+
+    // the double push can't be captured, so it doesn't change the position
+    let mut game = Game::new("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")?;
+    play(&mut game, "e2e4")?;
+    let pushed = Board::new("4k3/8/8/8/4P3/8/8/4K3 b - - 0 1")?;
+    assert_eq!(game.board().zobrist, pushed.zobrist);
+
+    Ok(())
+}
+
+#[test]
+fn test_move_rules_and_material() -> Result<(), &'static str> {
+    let game = Game::new("4k3/8/8/8/8/8/4P3/4K3 w - - 100 80")?;
+    assert_eq!(game.outcome(), Some(Outcome::FiftyMoveRule));
+    assert!(!game.outcome().unwrap().is_automatic());
+
+    let game = Game::new("4k3/8/8/8/8/8/4P3/4K3 w - - 150 80")?;
+    assert_eq!(game.outcome(), Some(Outcome::SeventyFiveMoveRule));
+    assert!(game.outcome().unwrap().is_automatic());
+
+    let game = Game::new("4k3/4n3/8/8/8/8/8/4K3 w - - 0 1")?;
+    assert_eq!(game.outcome(), Some(Outcome::InsufficientMaterial));
+
+    Ok(())
+}
+
+#[test]
+fn test_undo() -> Result<(), &'static str> {
+    let mut game = Game::new(STARTPOS_FEN)?;
+    let initial = game.board().clone();
+
+    play(&mut game, "e2e4 d7d5 e4d5 g8f6")?;
+    assert_eq!(game.moves().len(), 4);
+    assert!(game.make_uci_move("e1g1").is_err());
+
+    while game.undo_move().is_some() {}
+    assert_eq!(game.board(), &initial);
+    assert_eq!(game.positions(), &[initial.zobrist]);
+
+    Ok(())
+}
+
+#[test]
+fn test_castling_notation() -> Result<(), &'static str> {
+    // castling is written as the king's two square move in standard chess
+    let mut game = Game::new("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")?;
+    assert!(game.make_uci_move("e1h1").is_err());
+    game.make_uci_move("e1g1")?;
+    assert_eq!(game.board().pieces[5], (Piece::Rook, Color::White));
+
+    // and as king takes rook in Chess960, where the king might not even move
+    let mut game = Game::new("1r4kr/8/8/8/8/8/8/1R4KR w HBhb - 0 1")?;
+    assert!(game.board().chess960);
+    game.make_uci_move("g1h1")?;
+    assert_eq!(game.board().pieces[6], (Piece::King, Color::White));
+    assert_eq!(game.board().pieces[5], (Piece::Rook, Color::White));
+    // the rook on f1 covers f8, so black can't castle queen side through it
+    assert!(game.make_uci_move("g8b8").is_err());
+    game.make_uci_move("g8h8")?;
+    assert_eq!(game.board().pieces[61], (Piece::Rook, Color::Black));
+    assert_eq!(game.board().zobrist, game.board().calculate_zobrist());
+
+    Ok(())
+}
+
+#[test]
+fn test_key_after() -> Result<(), &'static str> {
+    let fens = [
+        STARTPOS_FEN,
+        "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+        // castling, promotions with and without capture, en passant
+        "r3k2r/1P2p3/8/3pP3/8/8/6p1/R3K2R w KQkq d6 0 1",
+        "r3k2r/1P2p3/8/3pP3/8/8/6p1/R3K2R b KQkq - 0 1",
+    ];
+
+    let mut exact = 0;
+    for fen in fens {
+        let board = Board::new(fen)?;
+        for mov in gen_color_moves(&board) {
+            let mut after = board.clone();
+            after.make_move(mov);
+            // castling rights and new en passant squares are left out
+            if after.castling_rights == board.castling_rights && after.en_passant_square.is_none() {
+                assert_eq!(board.key_after(mov), after.zobrist, "{fen} {mov:?}");
+                exact += 1;
+            }
+        }
+    }
+    assert!(exact > 50);
+
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use sand::{
+    chess::{MatingMaterial::*, *},
+    engine::{
+        evaluation::W,
+        material::{MaterialEntry, MaterialTable},
+        pawns::PawnEntry,
+    },
+};
+
+const MATING_MATERIAL_POSITIONS: [(&str, MatingMaterial); 22] = [
+    // dead positions
+    ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", Insufficient),
+    ("4k3/8/8/8/8/8/8/3NK3 w - - 0 1", Insufficient),
+    ("4k3/4n3/8/8/8/8/8/4K3 w - - 0 1", Insufficient),
+    ("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", Insufficient),
+    ("2b1k3/8/8/8/8/8/8/4K3 b - - 0 1", Insufficient),
+    // KB v KB with both bishops on dark squares (c1 and f8)
+    ("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", Insufficient),
+    // KBB v KB, every bishop on light squares (b1, d1 and c8)
+    ("2b1k3/8/8/8/8/8/8/1B1BK3 w - - 0 1", Insufficient),
+    // drawish: mate exists but can't be forced
+    ("4k3/8/8/8/8/8/8/2NNK3 w - - 0 1", Drawish),
+    ("4kn2/8/8/8/8/8/8/3NK3 w - - 0 1", Drawish),
+    ("4kn2/8/8/8/8/8/8/2B1K3 w - - 0 1", Drawish),
+    // KB v KB on opposite colors (c1 and c8)
+    ("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1", Drawish),
+    ("4kn2/8/8/8/8/8/8/2NNK3 w - - 0 1", Drawish),
+    // KBB v KN with both bishops on the same color
+    ("4k1n1/8/8/8/8/8/8/1B1BK3 w - - 0 1", Drawish),
+    // mate can be forced
+    ("4k3/8/8/8/8/8/8/2BBK3 w - - 0 1", Sufficient),
+    ("4k3/8/8/8/8/8/8/2BNK3 w - - 0 1", Sufficient),
+    ("4k3/8/8/8/8/8/8/1NNNK3 w - - 0 1", Sufficient),
+    ("2bnk3/8/8/8/8/8/8/4K3 b - - 0 1", Sufficient),
+    ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", Sufficient),
+    ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", Sufficient),
+    ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", Sufficient),
+    ("4k3/4p3/8/8/8/8/8/3NK3 w - - 0 1", Sufficient),
+    (STARTPOS_FEN, Sufficient),
+];
+
+#[test]
+fn test_mating_material() -> Result<(), &'static str> {
+    for (fen, expected) in MATING_MATERIAL_POSITIONS {
+        let board = Board::new(fen)?;
+
+        assert_eq!(board.mating_material(), expected, "FEN: {fen}");
+        assert_eq!(
+            board.is_insufficient_material(),
+            expected == MatingMaterial::Insufficient,
+            "FEN: {fen}"
+        );
+
+        // the material entry knows it too, unless the bishops' squares decide
+        let bishops = fen.split(' ').next().unwrap().matches(['b', 'B']).count();
+        let cached = MaterialEntry::new(&board).mating_material;
+        match cached {
+            Some(cached) => assert_eq!(cached, expected, "FEN: {fen}"),
+            None => assert!(bishops >= 2, "FEN: {fen}"),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_material_key() -> Result<(), &'static str> {
+    // captures, promotions and en passant all change the material
+    let mut board = Board::new("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1")?;
+    let key = board.material_key;
+    assert_eq!(
+        key,
+        Board::new("1r2k2r/8/4P3/8/8/p7/3P4/RR2K3 b - - 0 1")?.material_key
+    );
+
+    let count_material = |board: &Board| {
+        let mut key = 0;
+        for color in [Color::White, Color::Black] {
+            for piece_type in PIECE_TYPES {
+                let count = board.bitboards[color as usize][piece_type as usize].count_ones();
+                key += count as u64 * material_key_unit(piece_type, color);
+            }
+        }
+        key
+    };
+
+    for mov in gen_color_moves(&board) {
+        let undo = board.make_move(mov);
+        assert_eq!(board.material_key, count_material(&board));
+        board.undo_move(&undo);
+        assert_eq!(board.material_key, key);
+    }
+
+    // same material on different squares
+    assert_eq!(
+        Board::new(STARTPOS_FEN)?.material_key,
+        Board::new("rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2")?.material_key
+    );
+    assert_ne!(
+        Board::new("4k3/8/8/8/8/8/8/3NK3 w - - 0 1")?.material_key,
+        Board::new("4k3/8/8/8/8/8/8/3BK3 w - - 0 1")?.material_key
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_material_table() -> Result<(), &'static str> {
+    let mut table = MaterialTable::new();
+
+    for fen in [
+        STARTPOS_FEN,
+        "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+        "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    ] {
+        let board = Board::new(fen)?;
+        // the first probe fills the entry and the second one finds it
+        for _ in 0..2 {
+            assert_eq!(
+                board.evaluate_with(table.probe(&board), &PawnEntry::new(&board)),
+                board.evaluate(),
+                "FEN: {fen}"
+            );
+            assert_eq!(table.probe(&board).key(), board.material_key);
+        }
+    }
+
+    // the bishop pair is worth something on its own
+    let pair = MaterialEntry::new(&Board::new("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1")?);
+    let knights = MaterialEntry::new(&Board::new("4k3/8/8/8/8/8/8/2BNK3 w - - 0 1")?);
+    assert!(pair.bishop_pair.0 > 0 && pair.bishop_pair.1 > 0);
+    assert_eq!(knights.bishop_pair, W(0, 0));
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+mod utils;
+
+use std::sync::Arc;
+
+use sand::{
+    chess::*,
+    engine::{
+        evaluation::Evaluator,
+        nnue::{self, Network, Nnue, network},
+        params::EvalParams,
+    },
+};
+use utils::{check_incremental, mirror};
+
+fn nnue_eval(board: &Board) -> i16 {
+    Nnue::new(board).evaluate(board)
+}
+
+#[test]
+fn test_network_file() -> Result<(), &'static str> {
+    let bytes = network().to_bytes();
+    assert_eq!(Network::from_bytes(&bytes)?, *network());
+
+    // the embedded network comes from the default tables
+    assert_eq!(Network::from_params(&EvalParams::default()), *network());
+
+    assert!(Network::from_bytes(&bytes[..bytes.len() - 2]).is_err());
+    assert!(Network::from_bytes(&[b"NNUE", &bytes[4..]].concat()).is_err());
+    assert!(Network::from_bytes(&[]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_set_network() -> Result<(), &'static str> {
+    // a copy of the embedded network, so the other tests see the same weights meanwhile
+    let embedded = network();
+    let board = Board::new(STARTPOS_FEN)?;
+    let mut before = Nnue::new(&board);
+
+    nnue::set_network((*embedded).clone());
+    let loaded = network();
+    assert!(!Arc::ptr_eq(&embedded, &loaded));
+    let mut after = Nnue::new(&board);
+
+    // evaluators keep the network they started with, and it lives as long as they do
+    nnue::reset_network();
+    assert!(Arc::ptr_eq(&embedded, &network()));
+    let weak = Arc::downgrade(&loaded);
+    drop(loaded);
+    assert_eq!(before.evaluate(&board), after.evaluate(&board));
+    drop(after);
+    assert!(weak.upgrade().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_accumulator() -> Result<(), &'static str> {
+    // castling, promotions and en passant among them
+    for line in utils::LARGE_TEST_EPDS.iter().take(12) {
+        let fen = line.split(';').next().unwrap();
+        let mut board = Board::new(fen)?;
+        // the accumulator, which the evaluation could hide differences in
+        let accumulator = |nnue: &mut Nnue, _: &Board| nnue.accumulator.clone();
+        let mut nnue = Nnue::new(&board);
+        check_incremental(&mut board, &mut nnue, 2, &accumulator);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_nnue_eval() -> Result<(), &'static str> {
+    assert_eq!(nnue_eval(&Board::new(STARTPOS_FEN)?), 0);
+
+    // a queen up
+    let board = Board::new("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")?;
+    assert!(nnue_eval(&board) > 700);
+
+    for fen in [
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        "8/5pk1/6p1/8/3R4/6P1/5PK1/8 b - - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    ] {
+        let board = Board::new(fen)?;
+        let mirrored = Board::new(&mirror(fen))?;
+        assert_eq!(nnue_eval(&board), -nnue_eval(&mirrored), "{fen}");
+
+        // close to the tables it was built from
+        assert!((nnue_eval(&board) - board.evaluate()).abs() < 150, "{fen}");
+    }
+
+    Ok(())
+}
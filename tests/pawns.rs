@@ -0,0 +1,89 @@
+mod utils;
+
+use sand::{
+    chess::*,
+    engine::{evaluation::W, pawns::PawnEntry},
+};
+use utils::mirror;
+
+fn pawn_entry(fen: &str) -> Result<PawnEntry, &'static str> {
+    Ok(PawnEntry::new(&Board::new(fen)?))
+}
+
+#[test]
+fn test_pawn_key() -> Result<(), &'static str> {
+    let mut game = Game::new(STARTPOS_FEN)?;
+    let start = game.board().pawn_key;
+
+    // pieces don't change it
+    game.make_san_move("Nf3")?;
+    game.make_san_move("Nc6")?;
+    assert_eq!(game.board().pawn_key, start);
+
+    game.make_san_move("e4")?;
+    assert_ne!(game.board().pawn_key, start);
+    game.make_san_move("d5")?;
+    game.make_san_move("exd5")?;
+
+    let other = Board::new("r1bqkbnr/ppp1pppp/2n5/3P4/8/8/PPPP1PPP/R1BQKB1R b KQkq - 0 3")?;
+    assert_eq!(game.board().pawn_key, other.pawn_key);
+    assert_ne!(game.board().zobrist, other.zobrist);
+
+    Ok(())
+}
+
+#[test]
+fn test_pawn_structure() -> Result<(), &'static str> {
+    assert_eq!(pawn_entry(STARTPOS_FEN)?.score.1, 0);
+
+    // a2 is passed, d5 is stopped by c6 and c6 by d5
+    let entry = pawn_entry("4k3/8/2p5/3P4/8/8/P7/4K3 w - - 0 1")?;
+    assert_eq!(entry.passed, [bit(8), 0]);
+
+    // passed pawns are worth more closer to promotion
+    let far = pawn_entry("4k3/8/8/8/3P4/8/8/4K3 w - - 0 1")?;
+    let close = pawn_entry("4k3/8/3P4/8/8/8/8/4K3 w - - 0 1")?;
+    assert!(close.score.1 > far.score.1);
+
+    // isolated and doubled pawns are weak
+    let phalanx = pawn_entry("4k3/8/8/8/8/8/PP6/4K3 w - - 0 1")?;
+    let isolated = pawn_entry("4k3/8/8/8/8/8/P1P5/4K3 w - - 0 1")?;
+    let doubled = pawn_entry("4k3/8/8/8/8/P7/P7/4K3 w - - 0 1")?;
+    assert!(phalanx.score.1 > isolated.score.1);
+    assert!(isolated.score.1 > doubled.score.1);
+
+    // d3 can't move to d4 and nothing can defend it
+    let backward = pawn_entry("4k3/8/8/4p3/2pP4/3P4/8/4K3 w - - 0 1")?;
+    let defended = pawn_entry("4k3/8/8/4p3/2pP4/2P5/8/4K3 w - - 0 1")?;
+    assert!(defended.score.0 > backward.score.0);
+
+    // c5 can't be stopped by b6 alone, since b4 helps it
+    let candidate = pawn_entry("4k3/8/1p6/2P5/1P6/8/8/4K3 w - - 0 1")?;
+    let blocked = pawn_entry("4k3/8/1pp5/2P5/1P6/8/8/4K3 w - - 0 1")?;
+    assert!(candidate.passed[0] == 0 && candidate.score.1 > blocked.score.1);
+
+    Ok(())
+}
+
+#[test]
+fn test_evaluation_symmetry() -> Result<(), &'static str> {
+    for fen in [
+        STARTPOS_FEN,
+        "4k3/8/2p5/3P4/8/8/P7/4K3 w - - 0 1",
+        "4k3/8/8/4p3/2pP4/3P4/8/4K3 w - - 0 1",
+        "r1bqkb1r/pp3ppp/2n1pn2/2pp4/3P4/2PBPN2/PP3PPP/RNBQK2R w KQkq - 0 6",
+        "2r3k1/5pp1/1p2p2p/p2pP3/P2P4/1P3N1P/5PP1/2R3K1 b - - 0 30",
+        "8/5k2/3p4/1p1Pp2p/pP2Pp1P/P4P1K/8/8 b - - 0 40",
+    ] {
+        let mirrored = mirror(fen);
+        let W(midgame, endgame) = pawn_entry(&mirrored)?.score;
+        assert_eq!(pawn_entry(fen)?.score, W(-midgame, -endgame), "FEN: {fen}");
+        assert_eq!(
+            Board::new(fen)?.evaluate(),
+            -Board::new(&mirrored)?.evaluate(),
+            "FEN: {fen}"
+        );
+    }
+
+    Ok(())
+}
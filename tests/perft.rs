@@ -100,16 +100,13 @@ fn perft(board: &mut Board, depth: u8, tt: &mut PerftTT) -> u32 {
     nodes
 }
 
-#[test]
-fn edp_test() -> io::Result<()> {
-    const TT_SIZE_MB: usize = 128;
+fn run_suite(epds: &[&str], table: &mut PerftTT) -> (u32, Duration) {
     const PERFT_DEPTH: usize = 4;
 
-    let mut table = PerftTT::new(TT_SIZE_MB);
     let mut total_nodes = 0;
     let mut total_elapsed = Duration::ZERO;
 
-    for line in utils::LARGE_TEST_EPDS {
+    for line in epds {
         let fields: Vec<&str> = line.split_whitespace().collect();
         if fields.len() < 10 {
             continue;
@@ -131,7 +128,7 @@ fn edp_test() -> io::Result<()> {
         for (idx, &expected_nodes) in expected.iter().enumerate() {
             let depth = (idx + 1) as u8;
             let depth_start = Instant::now();
-            let nodes = perft(&mut board, depth, &mut table);
+            let nodes = perft(&mut board, depth, table);
             let elapsed = depth_start.elapsed();
 
             total_nodes += nodes;
@@ -146,6 +143,16 @@ fn edp_test() -> io::Result<()> {
         println!();
     }
 
+    (total_nodes, total_elapsed)
+}
+
+#[test]
+fn edp_test() -> io::Result<()> {
+    const TT_SIZE_MB: usize = 128;
+
+    let mut table = PerftTT::new(TT_SIZE_MB);
+    let (total_nodes, total_elapsed) = run_suite(&utils::LARGE_TEST_EPDS, &mut table);
+
     println!(
         "Estimated: {:.0} N/s",
         total_nodes as f64 / total_elapsed.as_secs_f64(),
@@ -153,3 +160,13 @@ fn edp_test() -> io::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn chess960_test() -> io::Result<()> {
+    const TT_SIZE_MB: usize = 16;
+
+    let mut table = PerftTT::new(TT_SIZE_MB);
+    run_suite(&utils::CHESS960_TEST_EPDS, &mut table);
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use sand::{chess::*, engine::book::BookEntry};
+
+const COLLECTION: &str = r#"[Event "Casual"]
+[White "Morphy, Paul"]
+[Black "Duke Karl / Count Isouard"]
+[WhiteElo "2690"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 d6 3. d4 Bg4 {This is a weak move} 4. dxe5 Bxf3 5. Qxf3 dxe5
+6. Bc4 Nf6 7. Qb3 Qe7 8. Nc3 c6 9. Bg5 b5 10. Nxb5 cxb5 11. Bxb5+ Nbd7
+12. O-O-O Rd8 13. Rxd7 Rxd7 14. Rd1 Qe6 15. Bxd7+ Nxd7 16. Qb8+ Nxb8 17. Rd8# 1-0
+
+[Event "Annotated"]
+[Result "1/2-1/2"]
+
+1.d4 d5 2.c4 (2.Nf3 Nf6 (2...c5) 3.c4) 2...e6 $1 ; the solid choice
+3.Nc3 {comments can
+span lines} Nf6 1/2-1/2
+
+[Event "Promotion"]
+[FEN "8/1P5k/8/8/8/8/8/K7 w - - 0 1"]
+
+1. b8=Q Kg6 *
+"#;
+
+#[test]
+fn test_parse_pgn() -> Result<(), &'static str> {
+    let games = parse_pgn(COLLECTION);
+    assert_eq!(games.len(), 3);
+
+    assert_eq!(games[0].tag("White"), Some("Morphy, Paul"));
+    assert_eq!(games[0].tag("BlackElo"), None);
+    assert_eq!(games[0].result, "1-0");
+    assert_eq!(games[0].moves.len(), 33);
+
+    // variations, NAGs and comments are skipped
+    assert_eq!(games[1].moves, ["d4", "d5", "c4", "e6", "Nc3", "Nf6"]);
+    assert_eq!(games[1].result, "1/2-1/2");
+
+    let game = games[0].replay()?;
+    assert_eq!(
+        game.outcome(),
+        Some(Outcome::Checkmate {
+            winner: Color::White
+        })
+    );
+
+    let game = games[2].replay()?;
+    assert_eq!(game.board().pieces[57], (Piece::Queen, Color::White));
+
+    Ok(())
+}
+
+#[test]
+fn test_san() -> Result<(), &'static str> {
+    // both knights can reach d2, and a pawn can promote by capturing
+    let mut game = Game::new("r3k3/1P6/8/8/8/5N2/8/RN2K2R w KQq - 0 1")?;
+    assert_eq!(game.parse_san("Nd2"), Err("ambiguous SAN move"));
+    assert_eq!(game.parse_san("Nd3"), Err("illegal SAN move"));
+    assert_eq!(game.board().move_to_uci(game.parse_san("Nfd2")?), "f3d2");
+    assert_eq!(game.board().move_to_uci(game.parse_san("N1d2")?), "b1d2");
+    assert!(game.parse_san("Raf1").is_err());
+    assert_eq!(game.board().move_to_uci(game.parse_san("O-O")?), "e1g1");
+    assert_eq!(
+        game.board().move_to_uci(game.parse_san("bxa8=N+")?),
+        "b7a8n"
+    );
+    assert_eq!(game.board().move_to_uci(game.parse_san("b8Q")?), "b7b8q");
+
+    // the pawn on b7 covers c8
+    game.make_san_move("Rh1h2")?;
+    assert_eq!(game.parse_san("O-O-O"), Err("illegal castling"));
+    assert!(game.parse_san("Kd7").is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_book_move_encoding() -> Result<(), &'static str> {
+    let game = Game::new("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1")?;
+
+    for mov in game.legal_moves() {
+        let entry = BookEntry {
+            key: game.board().polyglot_key(),
+            mov: BookEntry::encode_move(mov),
+            weight: 1,
+            learn: 0,
+        };
+        assert_eq!(entry.to_move(&game), Some(mov));
+    }
+
+    Ok(())
+}
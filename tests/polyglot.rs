@@ -0,0 +1,93 @@
+use sand::{
+    chess::*,
+    engine::book::{Book, BookEntry},
+};
+
+// test sequence from the Polyglot book format specification
+const POLYGLOT_KEYS: [(&str, u64); 9] = [
+    ("", 0x463b96181691fc9c),
+    ("e2e4", 0x823c9b50fd114196),
+    ("e2e4 d7d5", 0x0756b94461c50fb0),
+    ("e2e4 d7d5 e4e5", 0x662fafb965db29d4),
+    ("e2e4 d7d5 e4e5 f7f5", 0x22a48b5a8e47ff78),
+    ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652a607ca3f242c1),
+    ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00fdd303c946bdd9),
+    ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3c8123ea7b067637),
+    ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5c3f9b829b279560),
+];
+
+fn play(moves: &str) -> Result<Game, &'static str> {
+    let mut game = Game::new(STARTPOS_FEN)?;
+    for uci in moves.split_whitespace() {
+        game.make_uci_move(uci)?;
+    }
+
+    Ok(game)
+}
+
+fn polyglot_move(from: &str, to: &str) -> Result<u16, &'static str> {
+    Ok((square_from_uci(from)? as u16) << 6 | square_from_uci(to)? as u16)
+}
+
+#[test]
+fn test_polyglot_keys() -> Result<(), &'static str> {
+    for (moves, expected) in POLYGLOT_KEYS {
+        let game = play(moves)?;
+        assert_eq!(game.board().polyglot_key(), expected, "moves: {moves}");
+    }
+
+    // an en passant square nobody can capture on isn't part of the key
+    let board = Board::new("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")?;
+    assert_eq!(board.polyglot_key(), 0x823c9b50fd114196);
+
+    Ok(())
+}
+
+#[test]
+fn test_book_probing() -> Result<(), &'static str> {
+    let start = play("")?;
+    let castle = Game::new("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")?;
+    let key = start.board().polyglot_key();
+
+    let mut entries = vec![
+        (key, polyglot_move("e2", "e4")?, 10),
+        (key, polyglot_move("d2", "d4")?, 5),
+        (key, polyglot_move("g1", "f3")?, 0),
+        // not legal in the position, so it's never played
+        (key, polyglot_move("e2", "e5")?, 100),
+        (castle.board().polyglot_key(), polyglot_move("e1", "h1")?, 1),
+    ]
+    .into_iter()
+    .map(|(key, mov, weight)| BookEntry {
+        key,
+        mov,
+        weight,
+        learn: 0,
+    })
+    .collect::<Vec<BookEntry>>();
+    entries.sort_by_key(|entry| entry.key);
+
+    let bytes: Vec<u8> = entries.iter().flat_map(|entry| entry.to_bytes()).collect();
+    let book = Book::from_bytes(&bytes)?;
+    assert_eq!(book.len(), 5);
+    assert_eq!(book.entries(key).len(), 4);
+    assert!(Book::from_bytes(&bytes[1..]).is_err());
+
+    let best = book.pick_move(&start, true).unwrap();
+    assert_eq!(start.board().move_to_uci(best), "e2e4");
+
+    for _ in 0..100 {
+        let mov = start
+            .board()
+            .move_to_uci(book.pick_move(&start, false).unwrap());
+        assert!(mov == "e2e4" || mov == "d2d4", "unexpected book move {mov}");
+    }
+
+    // polyglot writes castling as king takes rook
+    let mov = book.pick_move(&castle, false).unwrap();
+    assert_eq!(castle.board().move_to_uci(mov), "e1g1");
+
+    assert_eq!(book.pick_move(&play("e2e4")?, false), None);
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 mod utils;
 
 use sand::chess::*;
-use sand::engine::transposition::{Bound, TT};
+use sand::engine::transposition::{Bound, EVAL_NONE, TT};
 
 const MATE_SCORE: i16 = 30_000;
 const INF: i16 = 32_000;
@@ -65,8 +65,10 @@ fn alpha_beta(
                 board.zobrist,
                 depth,
                 best_score,
+                EVAL_NONE,
                 best_move,
                 Bound::from_score(best_score, alpha, beta),
+                false,
                 age,
                 ply,
             );
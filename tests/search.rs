@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use sand::{
+    chess::*,
+    engine::{
+        HistoryHeuristics,
+        evaluation::Classical,
+        search::{AtomicSearchMode, SearchMode, Searcher, TimeControl},
+        syzygy::Tablebases,
+        transposition::TT,
+    },
+};
+
+// score of a fixed depth search, for the side to move
+fn search_score(game: &Game, depth: usize) -> i16 {
+    let mut searcher = Searcher::<Classical>::new(
+        game,
+        &Arc::new(AtomicSearchMode::new(SearchMode::Normal)),
+        &Arc::new(HistoryHeuristics::new()),
+        1,
+        &Arc::new(TT::new(16)),
+        &Arc::new(Tablebases::default()),
+    );
+    searcher.set_silent(true);
+    searcher.start_search(TimeControl::Depth(depth));
+    searcher.score()
+}
+
+fn play(fen: &str, moves: &[&str]) -> Result<Game, &'static str> {
+    let mut game = Game::new(fen)?;
+    for mov in moves {
+        game.make_uci_move(mov)?;
+    }
+    Ok(game)
+}
+
+// draws are scored a point either side of the contempt, here 0
+fn is_draw_score(score: i16) -> bool {
+    score.abs() <= 1
+}
+
+#[test]
+fn test_repetition_in_tree() -> Result<(), &'static str> {
+    // white is a rook down and only holds by checking on h5 and e8, which is a draw because the
+    // checks come back to the same positions
+    let game = Game::new("6k1/6p1/8/7Q/8/7K/r7/q7 w - - 0 1")?;
+    let score = search_score(&game, 8);
+    assert!(is_draw_score(score), "{score}");
+
+    Ok(())
+}
+
+#[test]
+fn test_repetition_before_root() -> Result<(), &'static str> {
+    // black is a queen down, and going back to the start position only repeats it once
+    let fen = "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let game = play(fen, &["g1f3", "g8f6", "f3g1"])?;
+    let score = search_score(&game, 6);
+    assert!(score < -500, "{score}");
+
+    Ok(())
+}
+
+#[test]
+fn test_upcoming_repetition() -> Result<(), &'static str> {
+    // the start position was there twice already, so the knight going back to g8 is a draw and
+    // black, a queen down, takes it at any depth
+    let fen = "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let game = play(
+        fen,
+        &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"],
+    )?;
+    for depth in 1..=4 {
+        let score = search_score(&game, depth);
+        assert!(is_draw_score(score), "depth {depth}: {score}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_nodes_count_every_iteration() -> Result<(), &'static str> {
+    let game = Game::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")?;
+    let mut searcher = Searcher::<Classical>::new(
+        &game,
+        &Arc::new(AtomicSearchMode::new(SearchMode::Normal)),
+        &Arc::new(HistoryHeuristics::new()),
+        1,
+        &Arc::new(TT::new(16)),
+        &Arc::new(Tablebases::default()),
+    );
+    searcher.set_silent(true);
+
+    // the limit is on the whole search, stopped within a few nodes of it
+    searcher.start_search(TimeControl::Nodes(20_000));
+    let nodes = searcher.nodes();
+    assert!((20_000..20_100).contains(&nodes), "{nodes}");
+
+    Ok(())
+}
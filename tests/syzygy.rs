@@ -0,0 +1,324 @@
+use std::path::PathBuf;
+
+use sand::{
+    chess::*,
+    engine::syzygy::{Tablebases, Wdl},
+};
+
+// Built with `build_tables` (see the README there), with the tables captures and promotions of
+// KPvK, KQvKR and KRvKP lead to
+const FIXTURE_TABLES: [&str; 10] = [
+    "KBvK", "KNvK", "KPvK", "KQvK", "KRvK", "KQvKR", "KRvKB", "KRvKN", "KRvKP", "KRvKR",
+];
+
+fn tablebases() -> Tablebases {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/syzygy");
+    for table in FIXTURE_TABLES {
+        for extension in ["rtbw", "rtbz"] {
+            let path = dir.join(format!("{table}.{extension}"));
+            assert!(path.exists(), "missing {}", path.display());
+        }
+    }
+
+    let tablebases = Tablebases::new(dir.to_str().unwrap());
+    assert_eq!(tablebases.len(), FIXTURE_TABLES.len());
+    tablebases
+}
+
+fn legal_moves(board: &Board) -> Vec<Move> {
+    let mut board = board.clone();
+    gen_color_moves(&board)
+        .into_iter()
+        .filter(|&mov| {
+            let undo = board.make_move(mov);
+            let legal = is_legal_move(mov, &board);
+            board.undo_move(&undo);
+            legal
+        })
+        .collect()
+}
+
+fn is_zeroing(board: &Board, mov: Move) -> bool {
+    board.pieces[mov.get_from() as usize].0 == Piece::Pawn
+        || board.pieces[mov.get_to() as usize].0 != Piece::None
+}
+
+/// Legal positions with these pieces, one every `step` placements
+fn legal_positions(pieces: &[(Piece, Color)], step: usize) -> Vec<Board> {
+    let mut boards = Vec::new();
+
+    for placement in (0..2 << (6 * pieces.len())).step_by(step) {
+        let fen = match placement & 1 {
+            0 => "8/8/8/8/8/8/8/8 w - - 0 1",
+            _ => "8/8/8/8/8/8/8/8 b - - 0 1",
+        };
+        let mut board = Board::new(fen).unwrap();
+
+        let squares: Vec<Square> = (0..pieces.len())
+            .map(|i| ((placement >> (1 + 6 * i)) & 63) as Square)
+            .collect();
+        let occupancy = squares
+            .iter()
+            .fold(0, |occupancy, &square| occupancy | bit(square));
+        let pawn_on_back_rank = squares.iter().zip(pieces).any(|(&square, &(piece, _))| {
+            piece == Piece::Pawn && bit(square) & (RANKS[0] | RANKS[7]) != 0
+        });
+        if occupancy.count_ones() as usize != pieces.len() || pawn_on_back_rank {
+            continue;
+        }
+
+        for (&square, &(piece, color)) in squares.iter().zip(pieces) {
+            board.toggle_piece(square, piece, color);
+        }
+        if !is_king_attcked(board.side_to_move.toggle(), &board) {
+            boards.push(board);
+        }
+    }
+
+    boards
+}
+
+/// WDL and DTZ have to follow from the moves: the best child for WDL, and for DTZ the fastest
+/// win (or slowest loss) counting captures and pawn moves as 1 and mates as 1
+fn check_consistency(tablebases: &Tablebases, board: &Board) {
+    let fen = board.to_fen();
+    let wdl = tablebases.probe_wdl(board).expect(&fen) as i32;
+    let dtz = tablebases.probe_dtz(board).expect(&fen);
+
+    let moves = legal_moves(board);
+    if moves.is_empty() {
+        let mated = is_king_attcked(board.side_to_move, board);
+        assert_eq!(wdl, if mated { -2 } else { 0 }, "{fen}");
+        return;
+    }
+
+    let mut best_wdl = -2;
+    let mut best_dtz: Option<i32> = None;
+    for mov in moves {
+        let mut child = board.clone();
+        child.make_move(mov);
+        let child_wdl = -(tablebases.probe_wdl(&child).expect(&fen) as i32);
+        best_wdl = best_wdl.max(child_wdl);
+        if child_wdl != wdl {
+            continue;
+        }
+
+        let mated = legal_moves(&child).is_empty() && is_king_attcked(child.side_to_move, &child);
+        let plies = if is_zeroing(board, mov) || mated {
+            1
+        } else {
+            tablebases.probe_dtz(&child).expect(&fen).abs() + 1
+        };
+        best_dtz = Some(match (wdl, best_dtz) {
+            (_, None) => plies,
+            (2, Some(best)) => best.min(plies),
+            (_, Some(best)) => best.max(plies),
+        });
+    }
+
+    assert_eq!(wdl, best_wdl, "{fen}");
+    match wdl {
+        0 => assert_eq!(dtz, 0, "{fen}"),
+        _ => assert_eq!(dtz, best_dtz.unwrap() * wdl.signum(), "{fen}"),
+    }
+}
+
+#[test]
+fn test_syzygy_wdl() -> Result<(), &'static str> {
+    let tablebases = tablebases();
+    assert_eq!(tablebases.max_pieces(), 4);
+
+    let positions = [
+        // KQvK, the queen hangs, and with colors flipped to look it up
+        ("8/8/8/3k4/8/8/8/KQ6 w - - 0 1", Wdl::Win),
+        ("8/8/8/3k4/8/8/8/KQ6 b - - 0 1", Wdl::Loss),
+        ("8/8/8/8/8/1k6/2Q5/6K1 b - - 0 1", Wdl::Draw),
+        ("8/8/8/3K4/8/8/8/kq6 b - - 0 1", Wdl::Win),
+        ("8/8/8/3k4/8/8/8/K7 w - - 0 1", Wdl::Draw),
+        // KRvK, the rook hangs and then a stalemate
+        ("8/8/8/3k4/8/8/8/KR6 w - - 0 1", Wdl::Win),
+        ("8/8/8/8/8/8/1k6/R5K1 b - - 0 1", Wdl::Draw),
+        ("k7/8/K7/8/8/8/8/1R6 b - - 0 1", Wdl::Draw),
+        // KPvK: the king in front of the pawn on the 6th rank wins whoever moves, the king in
+        // the corner of a rook pawn draws
+        ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Win),
+        ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", Wdl::Loss),
+        ("k7/8/8/8/8/8/P7/K7 w - - 0 1", Wdl::Draw),
+        ("k7/8/8/8/8/8/P7/K7 b - - 0 1", Wdl::Draw),
+        ("8/8/8/8/8/8/4p3/K3k3 b - - 0 1", Wdl::Win),
+        // KQvKR, and the rook taking the queen to win with KRvK
+        ("8/8/8/2k5/8/8/r7/3QK3 w - - 0 1", Wdl::Win),
+        ("4k3/8/8/8/8/8/4K3/Qr6 b - - 0 1", Wdl::Win),
+        // KRvKR is symmetric, so black to move is looked up with colors flipped
+        ("8/8/3k4/8/8/2r5/8/KR6 w - - 0 1", Wdl::Draw),
+        ("8/8/3k4/8/8/2r5/8/KR6 b - - 0 1", Wdl::Draw),
+        ("7k/1r6/8/8/8/8/8/KR6 w - - 0 1", Wdl::Win),
+        ("7k/1r6/8/8/8/8/8/KR6 b - - 0 1", Wdl::Draw),
+        // KRvKP, and the pawn promoting with check
+        ("7k/p7/8/8/3K4/8/8/3R4 w - - 0 1", Wdl::Win),
+        ("7k/8/8/8/8/8/p7/R3K3 b - - 0 1", Wdl::Loss),
+        ("8/8/8/8/8/8/1kp5/3R3K b - - 0 1", Wdl::Win),
+    ];
+
+    for (fen, wdl) in positions {
+        let board = Board::new(fen)?;
+        assert_eq!(tablebases.probe_wdl(&board), Some(wdl), "{fen}");
+    }
+
+    // missing tables
+    let board = Board::new("8/8/8/3k4/8/8/8/KQQ5 w - - 0 1")?;
+    assert_eq!(tablebases.probe_wdl(&board), None);
+    let board = Board::new("8/8/8/3k4/8/8/8/KBN5 w - - 0 1")?;
+    assert_eq!(tablebases.probe_wdl(&board), None);
+    assert!(Tablebases::new("").is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_syzygy_dtz() -> Result<(), &'static str> {
+    let tablebases = tablebases();
+
+    let positions = [
+        // mate in one, and getting mated
+        ("7k/8/6K1/8/8/8/8/Q7 w - - 0 1", 1),
+        ("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1", -1),
+        // promoting or taking the pawn zeroes the counter right away
+        ("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", 1),
+        ("7k/8/8/8/8/8/p7/R3K3 w - - 0 1", 1),
+        ("8/8/8/8/8/8/1kp5/3R3K b - - 0 1", 1),
+        ("k7/8/8/8/8/8/P7/K7 w - - 0 1", 0),
+        ("8/8/8/8/8/1k6/2Q5/6K1 b - - 0 1", 0),
+    ];
+
+    for (fen, dtz) in positions {
+        let board = Board::new(fen)?;
+        assert_eq!(tablebases.probe_dtz(&board), Some(dtz), "{fen}");
+    }
+
+    // mates can't take more than 10 moves with a queen, 16 with a rook
+    let longest = |pieces: &[(Piece, Color)]| {
+        // even placements have white to move, and by symmetry the black king can stay in a1-d1-d4
+        legal_positions(pieces, 2)
+            .iter()
+            .filter(|board| {
+                let king = board.bitboards[Color::Black as usize][Piece::King as usize];
+                let square = king.trailing_zeros();
+                square % 8 < 4 && square / 8 <= square % 8
+            })
+            .map(|board| tablebases.probe_dtz(board).unwrap())
+            .max()
+    };
+    let kings = [(Piece::King, Color::White), (Piece::King, Color::Black)];
+    assert_eq!(
+        longest(&[kings[0], (Piece::Queen, Color::White), kings[1]]),
+        Some(19)
+    );
+    assert_eq!(
+        longest(&[kings[0], (Piece::Rook, Color::White), kings[1]]),
+        Some(31)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_syzygy_root_moves() -> Result<(), &'static str> {
+    let tablebases = tablebases();
+
+    // the queen is attacked, only the moves saving it keep the win
+    let mut board = Board::new("8/8/8/8/8/1k6/2Q5/6K1 w - - 0 1")?;
+    let root_moves = tablebases
+        .root_moves(&board, &[board.zobrist])
+        .ok_or("not in the tables")?;
+
+    let uci: Vec<String> = root_moves
+        .iter()
+        .map(|&mov| board.move_to_uci(mov))
+        .collect();
+    assert!(uci.contains(&"c2c8".to_string()));
+    assert!(!uci.contains(&"c2c3".to_string()));
+    assert!(!uci.contains(&"g1f1".to_string()));
+
+    for mov in root_moves {
+        let undo = board.make_move(mov);
+        assert_eq!(tablebases.probe_wdl(&board), Some(Wdl::Loss));
+        board.undo_move(&undo);
+    }
+
+    // close to the 50-move rule only the fastest wins are left
+    let mut board = Board::new("8/8/8/3k4/8/8/8/KR6 w - - 90 1")?;
+    let dtz = tablebases.probe_dtz(&board).ok_or("not in the tables")?;
+    assert!(dtz > 10);
+    let root_moves = tablebases
+        .root_moves(&board, &[board.zobrist])
+        .ok_or("not in the tables")?;
+    assert!(!root_moves.is_empty());
+    for mov in root_moves {
+        let undo = board.make_move(mov);
+        assert_eq!(tablebases.probe_dtz(&board), Some(-(dtz - 1)));
+        board.undo_move(&undo);
+    }
+
+    let mut tablebases = tablebases;
+    tablebases.probe_limit = 2;
+    assert_eq!(tablebases.root_moves(&board, &[board.zobrist]), None);
+
+    Ok(())
+}
+
+// Every position has to agree with its children, which goes through the pawn indexing of every
+// file, the symmetric tables and the side to move the DTZ tables don't store
+#[test]
+fn test_syzygy_consistency() -> Result<(), &'static str> {
+    let tablebases = tablebases();
+    let (white, black) = (Color::White, Color::Black);
+
+    let materials: [(&[(Piece, Color)], usize); 4] = [
+        (
+            &[
+                (Piece::Pawn, white),
+                (Piece::King, white),
+                (Piece::King, black),
+            ],
+            31,
+        ),
+        (
+            &[
+                (Piece::Pawn, black),
+                (Piece::King, white),
+                (Piece::Rook, white),
+                (Piece::King, black),
+            ],
+            4099,
+        ),
+        (
+            &[
+                (Piece::King, white),
+                (Piece::Queen, white),
+                (Piece::King, black),
+                (Piece::Rook, black),
+            ],
+            8191,
+        ),
+        (
+            &[
+                (Piece::King, white),
+                (Piece::Rook, white),
+                (Piece::King, black),
+                (Piece::Rook, black),
+            ],
+            8191,
+        ),
+    ];
+
+    for (pieces, step) in materials {
+        let boards = legal_positions(pieces, step);
+        assert!(boards.len() > 1000);
+        for board in boards {
+            check_consistency(&tablebases, &board);
+        }
+    }
+
+    Ok(())
+}
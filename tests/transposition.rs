@@ -0,0 +1,308 @@
+use sand::{
+    chess::*,
+    engine::{
+        evaluation::EvaluatorKind,
+        transposition::{Bound, EVAL_NONE, TT, TTStats},
+    },
+};
+
+fn temp_path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("sand_{}_{name}", std::process::id()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+// a few positions from a short game, with something different stored for each
+fn fill(tt: &TT) -> Vec<u64> {
+    let mut game = Game::new(STARTPOS_FEN).unwrap();
+    let mut keys = vec![];
+    for (i, uci) in ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6"]
+        .iter()
+        .enumerate()
+    {
+        let mov = gen_color_moves(game.board())
+            .into_iter()
+            .find(|mov| mov.to_uci(false) == *uci)
+            .unwrap();
+        let bound = [Bound::Exact, Bound::Upper, Bound::Lower][i % 3];
+        let (score, eval) = (10 * i as i16 - 25, 3 * i as i16);
+        tt.store(
+            game.board().zobrist,
+            i + 1,
+            score,
+            eval,
+            mov,
+            bound,
+            i % 2 == 0,
+            1,
+            0,
+        );
+        keys.push(game.board().zobrist);
+        game.make_move(mov);
+    }
+    keys
+}
+
+#[test]
+fn test_save_and_load() -> Result<(), &'static str> {
+    // sizes don't have to be powers of two
+    let tt = TT::new(3);
+    let keys = fill(&tt);
+
+    let path = temp_path("tt.bin");
+    let evaluator = EvaluatorKind::Classical.signature();
+    tt.save(&path, evaluator)?;
+    let loaded = TT::load(&path, evaluator);
+    let _ = std::fs::remove_file(&path);
+    let loaded = loaded?;
+
+    for key in keys.iter().copied().chain([0x1234_5678, !keys[0]]) {
+        assert_eq!(tt.probe(key, 0), loaded.probe(key, 0));
+        for ply in [0, 3] {
+            let (mut alpha, mut beta) = (-30, 30);
+            let (mut loaded_alpha, mut loaded_beta) = (alpha, beta);
+            assert_eq!(
+                tt.probe(key, 0)
+                    .and_then(|data| data.probe(&mut alpha, &mut beta, ply)),
+                loaded.probe(key, 0).and_then(|data| data.probe(
+                    &mut loaded_alpha,
+                    &mut loaded_beta,
+                    ply
+                ))
+            );
+            assert_eq!((alpha, beta), (loaded_alpha, loaded_beta));
+        }
+    }
+    assert!(keys.iter().all(|&key| loaded.probe(key, 1).is_some()));
+    assert_eq!(tt.get_hashfull(1), loaded.get_hashfull(1));
+
+    Ok(())
+}
+
+#[test]
+fn test_load_refuses_bad_files() -> Result<(), &'static str> {
+    let tt = TT::new(1);
+    fill(&tt);
+    let path = temp_path("tt_bad.bin");
+    let evaluator = EvaluatorKind::Nnue.signature();
+    tt.save(&path, evaluator)?;
+    let bytes = std::fs::read(&path).unwrap();
+
+    // the scores came from another evaluator
+    for other in [EvaluatorKind::Classical, EvaluatorKind::Material] {
+        assert_ne!(other.signature(), evaluator);
+        assert_eq!(
+            TT::load(&path, other.signature()).err(),
+            Some("hash file was made with another evaluator")
+        );
+    }
+    assert!(TT::load(&path, evaluator).is_ok());
+
+    let mut cases = vec![];
+    // not a hash file
+    let mut other = bytes.clone();
+    other[0] = b'X';
+    cases.push(other);
+    // another entry format version
+    let mut other = bytes.clone();
+    other[4] += 1;
+    cases.push(other);
+    // other Zobrist keys
+    let mut other = bytes.clone();
+    other[16] ^= 1;
+    cases.push(other);
+    // truncated
+    cases.push(bytes[..bytes.len() - 8].to_vec());
+    cases.push(bytes[..10].to_vec());
+
+    for case in cases {
+        std::fs::write(&path, case).unwrap();
+        assert!(TT::load(&path, evaluator).is_err());
+    }
+    let _ = std::fs::remove_file(&path);
+
+    assert!(TT::load(&temp_path("missing.bin"), evaluator).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_writes() {
+    const THREADS: u64 = 8;
+    const ITERATIONS: u64 = 200_000;
+
+    // entries are told apart by the low 16 bits of the key, so the data is derived from those,
+    // and a table of a single cluster keeps the writes colliding
+    let key = |i: u64| i.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    let expected = |key: u64| {
+        (
+            (key as u16 % 60) as usize,
+            Move((key as u16).rotate_left(3)),
+            (key as u16 >> 1) as i16 - 10_000,
+        )
+    };
+
+    let tt = TT::new(0);
+    std::thread::scope(|scope| {
+        for thread in 0..THREADS {
+            let tt = &tt;
+            scope.spawn(move || {
+                let mut mismatches = 0;
+                for i in 0..ITERATIONS {
+                    let store_key = key(i * THREADS + thread);
+                    let (depth, mov, eval) = expected(store_key);
+                    // half the stores keep the evaluation already there, which has to be the
+                    // one of the same position
+                    let eval = if i % 2 == 0 { eval } else { EVAL_NONE };
+                    tt.store(store_key, depth, 0, eval, mov, Bound::Exact, false, 1, 0);
+
+                    let probe_key = key((i * 7 + thread * 13) % (ITERATIONS * THREADS));
+                    if let Some(data) = tt.probe(probe_key, 0) {
+                        let (depth, mov, eval) = expected(probe_key);
+                        if (data.depth as usize, data.best_move) != (depth, mov)
+                            || (data.eval != eval && data.eval != EVAL_NONE)
+                        {
+                            mismatches += 1;
+                        }
+                    }
+                }
+                assert_eq!(mismatches, 0);
+            });
+        }
+    });
+}
+
+#[test]
+fn test_eval_and_pv_flag() {
+    let tt = TT::new(1);
+    let key = Board::new(STARTPOS_FEN).unwrap().zobrist;
+
+    tt.store(key, 3, 40, 25, Move(0), Bound::Lower, true, 1, 0);
+    let data = tt.probe(key, 0).unwrap();
+    assert_eq!((data.eval, data.pv), (25, true));
+    // a lower bound above the evaluation is a better guess, below it says nothing
+    assert_eq!(data.eval_estimate(data.eval), 40);
+    assert_eq!(data.eval_estimate(60), 60);
+
+    // a later store without an evaluation keeps the one already there
+    tt.store(key, 5, -10, EVAL_NONE, Move(0), Bound::Upper, false, 1, 0);
+    let data = tt.probe(key, 0).unwrap();
+    assert_eq!((data.eval, data.pv), (25, false));
+    assert_eq!(data.eval_estimate(data.eval), -10);
+    assert_eq!(data.eval_estimate(-30), -30);
+}
+
+#[test]
+fn test_hashfull() {
+    // a single cluster, so the sample is every entry
+    let tt = TT::new(0);
+    assert_eq!(tt.get_hashfull(1), 0);
+
+    for key in 1..=2u64 {
+        tt.store(key, 1, 0, EVAL_NONE, Move(0), Bound::Exact, false, 1, 0);
+    }
+    assert_eq!(tt.get_hashfull(1), 666);
+    // rewriting the same position doesn't count twice
+    tt.store(1, 2, 0, EVAL_NONE, Move(0), Bound::Exact, false, 1, 0);
+    assert_eq!(tt.get_hashfull(1), 666);
+
+    // only entries from the current search count
+    tt.store(3, 1, 0, EVAL_NONE, Move(0), Bound::Exact, false, 2, 0);
+    assert_eq!(tt.get_hashfull(1), 666);
+    assert_eq!(tt.get_hashfull(2), 333);
+
+    // a bigger table only samples the first thousand entries
+    let tt = TT::new(1);
+    fill(&tt);
+    assert!(tt.get_hashfull(1) <= 6);
+}
+
+#[test]
+fn test_stats() {
+    let tt = TT::new(0);
+    let store = |key: u64, depth: usize, age: u8| {
+        tt.store(
+            key,
+            depth,
+            0,
+            EVAL_NONE,
+            Move(0),
+            Bound::Lower,
+            false,
+            age,
+            0,
+        );
+    };
+
+    // not counted until enabled
+    store(1, 4, 1);
+    assert_eq!(tt.stats(), TTStats::default());
+
+    tt.track_stats(true);
+    store(2, 4, 1);
+    store(3, 4, 1);
+    store(3, 5, 1);
+    store(3, 1, 1);
+    store(4, 4, 1);
+    store(5, 4, 2);
+    assert!(tt.probe(3, 0).is_some());
+    assert!(tt.probe(6, 0).is_none());
+
+    let stats = tt.stats();
+    assert_eq!(
+        stats,
+        TTStats {
+            hits: 1,
+            misses: 1,
+            updates: 1,
+            kept: 1,
+            empty: 2,
+            aged: 1,
+            collisions: 1,
+        }
+    );
+    assert!(stats.to_string().contains("hits 1 (50.0%)"));
+
+    tt.track_stats(false);
+    store(7, 4, 2);
+    assert_eq!(tt.stats(), TTStats::default());
+}
+
+#[test]
+fn test_clear() {
+    // big enough to be cleared by several threads
+    let mut tt = TT::new(64);
+    assert_eq!(tt.huge_pages(), cfg!(target_os = "linux"));
+
+    let mut keys = fill(&tt);
+    // spread over the whole table, so every thread has something to clear
+    keys.extend((0..1000u64).map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1));
+    for &key in &keys {
+        tt.store(key, 1, 0, EVAL_NONE, Move(0), Bound::Exact, false, 1, 0);
+    }
+    assert!(keys.iter().all(|&key| tt.probe(key, 0).is_some()));
+
+    tt.clear();
+    assert!(keys.iter().all(|&key| tt.probe(key, 0).is_none()));
+    assert_eq!(tt.get_hashfull(1), 0);
+}
+
+#[test]
+fn test_pv_entries_kept() {
+    // a single cluster, full after three stores
+    let tt = TT::new(0);
+    tt.store(1, 4, 0, EVAL_NONE, Move(0), Bound::Exact, true, 1, 0);
+    tt.store(2, 4, 0, EVAL_NONE, Move(0), Bound::Lower, false, 1, 0);
+    tt.store(3, 5, 0, EVAL_NONE, Move(0), Bound::Lower, false, 1, 0);
+
+    // as deep as the non-PV entry, so that's the one to go
+    tt.store(4, 4, 0, EVAL_NONE, Move(0), Bound::Lower, false, 1, 0);
+    assert!(tt.probe(1, 0).is_some());
+    assert!(tt.probe(2, 0).is_none());
+
+    // being in the PV counts for two plies of depth
+    tt.store(5, 1, 0, EVAL_NONE, Move(0), Bound::Lower, false, 1, 0);
+    assert!(tt.probe(1, 0).is_some());
+    assert!(tt.probe(4, 0).is_none());
+}
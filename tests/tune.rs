@@ -0,0 +1,127 @@
+use sand::{
+    chess::*,
+    engine::{
+        evaluation::W,
+        params::{EvalParams, Param},
+        trace::Term,
+        tune::{Tuner, TuningPosition, features, parse_position},
+    },
+};
+
+#[test]
+fn test_params_file() -> Result<(), &'static str> {
+    let defaults = EvalParams::default();
+    assert_eq!(EvalParams::parse(&defaults.to_string())?, defaults);
+
+    // groups left out keep their values
+    let params =
+        EvalParams::parse("# comment\nbishop_pair: 30 60\nmobility: 0 0 1 2 3 4 5 6 7 8 0 0")?;
+    assert_eq!(params.bishop_pair, W(30, 60));
+    assert_eq!(params.mobility[Piece::Rook as usize], W(5, 6));
+    assert_eq!(params.pst, defaults.pst);
+    assert_eq!(params.passed, defaults.passed);
+
+    assert!(EvalParams::parse("bishop_pears: 30 60").is_err());
+    assert!(EvalParams::parse("bishop_pair: 30").is_err());
+    assert!(EvalParams::parse("bishop_pair: 30 sixty").is_err());
+    assert!(EvalParams::parse("30 60").is_err());
+
+    assert_eq!(
+        Param::COUNT,
+        Param::ALL
+            .iter()
+            .map(|param| defaults.weights(*param).len())
+            .sum()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_position() -> Result<(), &'static str> {
+    let (board, result) = parse_position("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 [1.0]")?;
+    assert_eq!(board.side_to_move, Color::White);
+    assert_eq!(result, 1.0);
+
+    let (board, result) = parse_position("4k3/8/8/8/8/8/4P3/4K3 b - - c9 \"1/2-1/2\";")?;
+    assert_eq!(board.side_to_move, Color::Black);
+    assert_eq!(result, 0.5);
+
+    assert_eq!(parse_position("4k3/8/8/8/8/8/4P3/4K3 w - - 0-1")?.1, 0.0);
+    assert!(parse_position("4k3/8/8/8/8/8/4P3/4K3 w - -").is_err());
+    assert!(parse_position("[1.0]").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_features() -> Result<(), &'static str> {
+    let defaults = EvalParams::default();
+    let weights: Vec<W> = Param::ALL
+        .into_iter()
+        .flat_map(|param| defaults.weights(param).to_vec())
+        .collect();
+
+    // no more than one piece near either king, so only the imbalance isn't linear
+    for fen in [
+        STARTPOS_FEN,
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        "8/5pk1/6p1/8/3R4/6P1/5PK1/8 w - - 0 1",
+        "4k3/2p5/8/1P1P4/8/5n2/8/4K2B b - - 0 1",
+    ] {
+        let board = Board::new(fen)?;
+        let trace = board.trace();
+
+        let mut linear = W(0, 0);
+        for (index, coefficient) in features(&board) {
+            let W(mg, eg) = weights[index];
+            linear += W(mg * coefficient, eg * coefficient);
+        }
+
+        let [white, black] = trace.terms[Term::Imbalance as usize];
+        let mut expected = trace.total();
+        expected -= white;
+        expected += black;
+        assert_eq!(linear, expected, "{fen}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_tuner() -> Result<(), &'static str> {
+    let positions: Vec<TuningPosition> = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - [0.5]",
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - [0.5]",
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - [1.0]",
+        "4k3/8/8/3P4/8/8/5PPP/4K3 w - - [1.0]",
+        "4k3/pp6/8/8/8/8/8/4K3 w - - [0.0]",
+        "r3k3/8/8/8/8/8/5PPP/6K1 w - - [0.0]",
+        "6k1/5ppp/8/8/8/8/8/3R2K1 b - - [1.0]",
+        "8/5pk1/6p1/8/3R4/6P1/5PK1/8 w - - [0.5]",
+    ]
+    .iter()
+    .filter_map(|line| {
+        let (board, result) = parse_position(line).ok()?;
+        TuningPosition::new(&board, result)
+    })
+    .collect();
+    assert_eq!(positions.len(), 8);
+
+    let mut tuner = Tuner::new(positions, 2);
+    assert_eq!(tuner.params(), EvalParams::default());
+
+    tuner.fit_k();
+    let before = tuner.loss();
+    for _ in 0..20 {
+        tuner.step(1.0);
+    }
+    assert!(tuner.loss() < before);
+    assert_ne!(tuner.params(), EvalParams::default());
+
+    // specialized endgames don't depend on the weights
+    let board = Board::new("8/8/8/4k3/8/8/4P3/4K3 w - - 0 1")?;
+    assert!(TuningPosition::new(&board, 1.0).is_none());
+
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use sand::engine::uci::Uci;
+
+#[test]
+fn test_analysis_contempt() {
+    let mut uci = Uci::new();
+    uci.execute("setoption name Contempt value 20");
+    assert_eq!(uci.search_contempt(), 20);
+
+    // analysis is neutral by default
+    uci.execute("setoption name UCI_AnalyseMode value true");
+    assert_eq!(uci.search_contempt(), 0);
+
+    uci.execute("setoption name Analysis Contempt value true");
+    assert_eq!(uci.search_contempt(), 20);
+
+    uci.execute("setoption name UCI_AnalyseMode value false");
+    uci.execute("setoption name Analysis Contempt value false");
+    assert_eq!(uci.search_contempt(), 20);
+
+    assert!(uci.execute("quit"));
+}
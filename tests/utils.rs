@@ -1,3 +1,8 @@
+use std::fmt::Debug;
+
+use sand::{chess::*, engine::evaluation::Evaluator};
+
+#[allow(dead_code)]
 pub const LARGE_TEST_EPDS: [&str; 172] = [
     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ;D1 20 ;D2 400 ;D3 8902 ;D4 197281 ;D5 4865609 ;D6 119060324",
     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1 ;D1 20 ;D2 400 ;D3 8902 ;D4 197281 ;D5 4865609 ;D6 119060324",
@@ -189,3 +194,67 @@ pub const LARGE_TEST_EPDS: [&str; 172] = [
     "1k6/8/8/8/R7/1n6/8/R3K3 b Q - 0 1 ;D5 346695",
     "r3k3/8/1N6/r7/8/8/8/1K6 w q - 0 1 ;D5 346695",
 ];
+
+// Chess960 positions, with castling rights in Shredder-FEN and X-FEN
+#[allow(dead_code)]
+pub const CHESS960_TEST_EPDS: [&str; 10] = [
+    "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9 ;D1 21 ;D2 528 ;D3 12189 ;D4 326672 ;D5 8146062",
+    "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9 ;D1 21 ;D2 807 ;D3 18002 ;D4 667366 ;D5 16253601",
+    "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9 ;D1 22 ;D2 593 ;D3 13440 ;D4 382958 ;D5 9183776",
+    "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9 ;D1 28 ;D2 1120 ;D3 31058 ;D4 1171749 ;D5 34030312",
+    "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9 ;D1 29 ;D2 899 ;D3 26578 ;D4 824055 ;D5 24851983",
+    "q1bnrkr1/ppppp2p/2n2p2/4b1p1/2NP4/8/PPP1PPPP/QNB1RRKB w ge - 1 9 ;D1 30 ;D2 860 ;D3 24566 ;D4 732757 ;D5 21093346",
+    "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9 ;D1 29 ;D2 502 ;D3 14569 ;D4 287739 ;D5 8652810",
+    "rbbqn1kr/pp2p1pp/6n1/2pp1p2/2P4P/P7/BP1PPPP1/R1BQNNKR w HAha - 0 9 ;D1 27 ;D2 916 ;D3 25798 ;D4 890435 ;D5 26302461",
+    "rqbbknr1/1ppp2pp/p5n1/4pp2/P7/1PP5/1Q1PPPPP/R1BBKNRN w GAga - 0 9 ;D1 24 ;D2 600 ;D3 15347 ;D4 408207 ;D5 11029596",
+    "nrbbqkrn/pppppppp/8/8/8/8/PPPPPPPP/NRBBQKRN w KQkq - 0 1 ;D1 19 ;D2 361 ;D3 7737 ;D4 165231 ;D5 3950375",
+];
+
+// same position with the colors swapped, only the pieces are mirrored
+#[allow(dead_code)]
+pub fn mirror(fen: &str) -> String {
+    let (placement, rest) = fen.split_once(' ').unwrap();
+    let placement: Vec<String> = placement
+        .split('/')
+        .rev()
+        .map(|rank| {
+            rank.chars()
+                .map(|c| match c.is_ascii_uppercase() {
+                    true => c.to_ascii_lowercase(),
+                    false => c.to_ascii_uppercase(),
+                })
+                .collect()
+        })
+        .collect();
+    let side = if rest.starts_with('w') { "b" } else { "w" };
+    format!("{} {side} - - 0 1", placement.join("/"))
+}
+
+// the evaluator following the moves agrees with one made from scratch, and is back to what it
+// was once each move is undone. `state` is what gets compared: the evaluation, or whatever
+// the evaluator keeps updated
+#[allow(dead_code)]
+pub fn check_incremental<E: Evaluator, S: PartialEq + Debug>(
+    board: &mut Board,
+    evaluator: &mut E,
+    depth: usize,
+    state: &impl Fn(&mut E, &Board) -> S,
+) {
+    assert_eq!(state(evaluator, board), state(&mut E::new(board), board));
+
+    if depth == 0 {
+        return;
+    }
+
+    for mov in gen_color_moves(board) {
+        let before = state(evaluator, board);
+        evaluator.make_move(board, mov);
+        let undo = board.make_move(mov);
+        if is_legal_move(mov, board) {
+            check_incremental(board, evaluator, depth - 1, state);
+        }
+        board.undo_move(&undo);
+        evaluator.undo_move(board, mov);
+        assert_eq!(state(evaluator, board), before);
+    }
+}